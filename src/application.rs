@@ -1,10 +1,27 @@
-use bevy::{asset::AssetMetaCheck, log::LogPlugin, prelude::*};
+use bevy::{
+    app::ScheduleRunnerPlugin,
+    asset::AssetMetaCheck,
+    log::LogPlugin,
+    prelude::*,
+    render::{settings::WgpuSettings, RenderPlugin},
+    window::ExitCondition,
+    winit::WinitPlugin,
+};
 use bevy_inspector_egui::{
     bevy_egui::{EguiContexts, EguiPlugin},
     egui,
 };
+use std::time::Duration;
 
-use crate::schedule::InGameSet;
+use crate::{
+    console::{parse_argument, ConsoleAppExt, ConsoleCommand},
+    difficulty::Difficulty,
+    player::PlayerMode,
+    save_game::{ContinueGame, SavedGameAvailable},
+    schedule::InGameSet,
+    score::HighScores,
+    settings::Settings,
+};
 
 #[derive(States, Debug, Default, Clone, Eq, PartialEq, Hash)]
 pub enum AppState {
@@ -14,12 +31,40 @@ pub enum AppState {
     InGame,
     Paused,
     EndGame,
+    Settings,
+    /// Playing on a server, which runs the game itself.
+    Online,
 }
 
-fn main_menu(mut contexts: EguiContexts, mut app_state: ResMut<NextState<AppState>>) {
+fn start_new_game(
+    commands: &mut Commands,
+    app_state: &mut NextState<AppState>,
+    settings: &Settings,
+) {
+    commands.insert_resource(settings.gameplay.difficulty);
+    commands.insert_resource(settings.gameplay.players);
+    app_state.set(AppState::InGame);
+}
+
+fn main_menu(
+    mut commands: Commands,
+    mut contexts: EguiContexts,
+    mut app_state: ResMut<NextState<AppState>>,
+    mut settings: ResMut<Settings>,
+    saved_game: Res<SavedGameAvailable>,
+    high_scores: Res<HighScores>,
+) {
+    // There's no context on a headless server.
+    let Some(ctx) = contexts.try_ctx_mut() else {
+        return;
+    };
+
+    let mut difficulty = settings.gameplay.difficulty;
+    let mut player_mode = settings.gameplay.players.mode;
+
     egui::SidePanel::left("Side panel")
         .default_width(200.0)
-        .show(contexts.ctx_mut(), |ui| {
+        .show(ctx, |ui| {
             ui.allocate_space(egui::Vec2::new(1.0, 300.0));
 
             ui.heading("Welcome to not-pong");
@@ -28,54 +73,129 @@ fn main_menu(mut contexts: EguiContexts, mut app_state: ResMut<NextState<AppStat
                 ui.label("Testing that deployment works");
             });
 
-            if ui.button("[N]ew game").clicked() {
+            if saved_game.0 && ui.button("[C]ontinue").clicked() {
+                commands.insert_resource(ContinueGame);
                 app_state.set(AppState::InGame);
             }
 
+            ui.horizontal(|ui| {
+                for choice in Difficulty::ALL {
+                    ui.selectable_value(&mut difficulty, choice, choice.to_string());
+                }
+            });
+
+            ui.horizontal(|ui| {
+                for choice in PlayerMode::ALL {
+                    ui.selectable_value(&mut player_mode, choice, format!("{choice:?}"));
+                }
+            });
+
+            if let Some(best) = high_scores.best(difficulty) {
+                ui.label(format!("Best on {difficulty}: {best}"));
+            }
+
+            if ui.button("[N]ew game").clicked() {
+                start_new_game(&mut commands, &mut app_state, &settings);
+            }
+
+            if ui.button("[S]ettings").clicked() {
+                app_state.set(AppState::Settings);
+            }
+
             #[cfg(not(target_arch = "wasm32"))]
             if ui.button("[Q]uit").clicked() {
                 app_state.set(AppState::EndGame);
             }
         });
+
+    if settings.gameplay.difficulty != difficulty {
+        settings.gameplay.difficulty = difficulty;
+    }
+
+    if settings.gameplay.players.mode != player_mode {
+        settings.gameplay.players.mode = player_mode;
+    }
 }
 
 fn main_menu_keys(
+    mut commands: Commands,
     mut app_state: ResMut<NextState<AppState>>,
     keyboad_input: Res<ButtonInput<KeyCode>>,
+    settings: Res<Settings>,
+    saved_game: Res<SavedGameAvailable>,
     #[cfg(not(target_arch = "wasm32"))] mut exit: EventWriter<bevy::app::AppExit>,
 ) {
-    if keyboad_input.just_pressed(KeyCode::KeyN) {
+    if saved_game.0 && keyboad_input.just_pressed(KeyCode::KeyC) {
+        commands.insert_resource(ContinueGame);
         app_state.set(AppState::InGame);
     }
 
+    if keyboad_input.just_pressed(KeyCode::KeyN) {
+        start_new_game(&mut commands, &mut app_state, &settings);
+    }
+
+    if keyboad_input.just_pressed(KeyCode::KeyS) {
+        app_state.set(AppState::Settings);
+    }
+
     #[cfg(not(target_arch = "wasm32"))]
     if keyboad_input.just_pressed(KeyCode::KeyQ) {
         exit.send(bevy::app::AppExit);
     }
 }
 
-pub struct AppPlugin;
+const HEADLESS_TICK_RATE: f64 = 60.0;
+
+pub struct AppPlugin {
+    /// Run without a window or renderer, e.g. as a dedicated server.
+    pub headless: bool,
+}
 
 impl Plugin for AppPlugin {
     fn build(&self, app: &mut App) {
-        app.insert_resource(AssetMetaCheck::Never)
-            .add_plugins(
-                DefaultPlugins
-                    .set(WindowPlugin {
-                        primary_window: Some(Window {
-                            #[cfg(target_arch = "wasm32")]
-                            canvas: Some("#pong-canvas".into()),
-                            prevent_default_event_handling: false,
-                            ..default()
-                        }),
-                        ..default()
-                    })
-                    .set(LogPlugin {
-                        filter: "wgpu=error,bevy_render=info,bevy_ecs=info".into(),
-                        level: bevy::log::Level::INFO,
+        let window = if self.headless {
+            WindowPlugin {
+                primary_window: None,
+                exit_condition: ExitCondition::DontExit,
+                ..default()
+            }
+        } else {
+            WindowPlugin {
+                primary_window: Some(Window {
+                    #[cfg(target_arch = "wasm32")]
+                    canvas: Some("#pong-canvas".into()),
+                    prevent_default_event_handling: false,
+                    ..default()
+                }),
+                ..default()
+            }
+        };
+
+        let mut plugins = DefaultPlugins.set(window).set(LogPlugin {
+            filter: "wgpu=error,bevy_render=info,bevy_ecs=info".into(),
+            level: bevy::log::Level::INFO,
+            ..default()
+        });
+
+        if self.headless {
+            plugins = plugins
+                .set(RenderPlugin {
+                    render_creation: WgpuSettings {
+                        backends: None,
                         ..default()
-                    }),
-            )
+                    }
+                    .into(),
+                    ..default()
+                })
+                .disable::<WinitPlugin>();
+
+            app.add_plugins(ScheduleRunnerPlugin::run_loop(Duration::from_secs_f64(
+                1.0 / HEADLESS_TICK_RATE,
+            )));
+        }
+
+        app.insert_resource(AssetMetaCheck::Never)
+            .add_plugins(plugins)
             .add_plugins(EguiPlugin)
             .insert_resource(ClearColor(Color::rgb(0.1, 0.0, 0.15)))
             .insert_resource(AmbientLight {
@@ -88,6 +208,33 @@ impl Plugin for AppPlugin {
                     .in_set(InGameSet::EntityUpdates)
                     .run_if(in_state(AppState::MainMenu)),
             )
-            .init_state::<AppState>();
+            .init_state::<AppState>()
+            .add_console_command(
+                ConsoleCommand::new(
+                    "state",
+                    "state <state>",
+                    "Switch straight to another state",
+                    switch_state,
+                )
+                .with_arguments(STATE_NAMES),
+            );
     }
 }
+
+const STATE_NAMES: &[&str] = &["MainMenu", "InGame", "Paused", "EndGame", "Settings"];
+
+fn switch_state(world: &mut World, arguments: &[&str]) -> Result<String, String> {
+    let name: String = parse_argument(arguments)?;
+    let state = match name.as_str() {
+        "MainMenu" => AppState::MainMenu,
+        "InGame" => AppState::InGame,
+        "Paused" => AppState::Paused,
+        "EndGame" => AppState::EndGame,
+        "Settings" => AppState::Settings,
+        _ => return Err(format!("No state called {name}")),
+    };
+
+    let message = format!("Switching to {state:?}");
+    world.resource_mut::<NextState<AppState>>().set(state);
+    Ok(message)
+}
@@ -11,6 +11,14 @@ pub struct SceneAssets {
     pub spaceship: Handle<Scene>,
     #[asset(path = "ultimate-space-kit/Bullets Pickup.glb#Scene0")]
     pub missiles: Handle<Scene>,
+    #[asset(path = "ultimate-space-kit/Enemy Large.glb#Scene0")]
+    pub boss: Handle<Scene>,
+    #[asset(path = "ultimate-space-kit/Enemy Small.glb#Scene0")]
+    pub minion: Handle<Scene>,
+    #[asset(path = "ultimate-space-kit/Planet.glb#Scene0")]
+    pub gravity_well: Handle<Scene>,
+    #[asset(path = "ultimate-space-kit/Pickup Sphere.glb#Scene0")]
+    pub mine: Handle<Scene>,
     #[asset(path = "8bit-explosion.ogg")]
     pub explosion: Handle<AudioSource>,
 }
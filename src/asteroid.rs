@@ -1,60 +1,186 @@
-use bevy::{core::Zeroable, prelude::*};
-use rand::{distributions::Distribution, Rng};
+use bevy::{core::Zeroable, ecs::system::SystemState, prelude::*};
+use rand::{distributions::Distribution, rngs::StdRng, Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
 use std::{ops::Range, time::Duration};
 
 use crate::{
     application::AppState,
     asset_loader::SceneAssets,
-    collision_detection::Collider,
+    boss::Boss,
+    collision_detection::{Collider, ColliderFit, ColliderShape},
+    console::{parse_argument, ConsoleAppExt, ConsoleCommand},
+    difficulty::{Difficulty, DifficultyPreset},
     end_game::DespawnAtEndgame,
     movement::{Acceleration, MovingObjectBundle, Velocity},
+    save_game::ContinueGame,
     schedule::InGameSet,
-    spaceship::Spaceship,
+    scoring::PointValue,
+    spaceship::{spawn_spaceships, Spaceship},
+    ui::{BlipKind, RadarBlip},
 };
 
-const SPAWN_RANGE_X: Range<f32> = -50.0..50.0;
-const SPAWN_RANGE_Y: Range<f32> = -50.0..50.0;
+pub const SPAWN_RANGE_X: Range<f32> = -50.0..50.0;
+pub const SPAWN_RANGE_Y: Range<f32> = -50.0..50.0;
 
 const SPAWN_TIMER: f32 = 1.0;
+const WAVE_DURATION: f32 = 30.0;
+const WAVE_SPAWN_SPEEDUP: f32 = 0.1;
 
 const VELOCITY_SCALAR: f32 = 5.0;
+const INITIAL_ASTEROIDS: u32 = 5;
 
 const ROTATION_SPEED: f32 = 1.5;
-const ASTEROID_RADIUS: f32 = 1.0;
+/// Until the model loads and [`ColliderFit`] takes over.
+pub const ASTEROID_RADIUS: f32 = 1.0;
+/// Rocks are lumpy, so trim their outline to the bulk of the rock.
+pub const ASTEROID_COLLIDER_FIT: f32 = 0.9;
+/// Corners of the stand-in outline used before the model loads.
+const ASTEROID_COLLIDER_SIDES: usize = 8;
 
 #[derive(Component, Debug, Reflect)]
+#[reflect(Component)]
 pub struct Asteroid;
 
-#[derive(Resource, Debug)]
+/// Smaller asteroids are harder to hit, so they're worth more.
+#[derive(Component, Debug, Reflect, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[reflect(Component)]
+pub enum AsteroidSize {
+    Small,
+    Medium,
+    Large,
+}
+
+impl AsteroidSize {
+    pub const ALL: [AsteroidSize; 3] = [
+        AsteroidSize::Small,
+        AsteroidSize::Medium,
+        AsteroidSize::Large,
+    ];
+
+    pub fn scale(&self) -> f32 {
+        match self {
+            AsteroidSize::Small => 0.75,
+            AsteroidSize::Medium => 1.25,
+            AsteroidSize::Large => 2.0,
+        }
+    }
+
+    pub fn radius(&self) -> f32 {
+        ASTEROID_RADIUS * self.scale()
+    }
+
+    /// The rock's outline, from its model once that's loaded.
+    pub fn collider(&self) -> Collider {
+        Collider::with_shape(ColliderShape::regular_polygon(
+            ASTEROID_COLLIDER_SIDES,
+            self.radius(),
+        ))
+    }
+
+    pub fn points(&self) -> usize {
+        match self {
+            AsteroidSize::Small => 3,
+            AsteroidSize::Medium => 2,
+            AsteroidSize::Large => 1,
+        }
+    }
+}
+
+/// Sent when a wave's timer runs out, just before the next one starts.
+#[derive(Event, Debug)]
+pub struct WaveCompleted {
+    pub wave: u32,
+}
+
+#[derive(Resource, Debug, Reflect)]
+#[reflect(Resource)]
 pub struct SpawnTimer {
     timer: Timer,
 }
 
-fn spawn_asteroid_on_interval(
+impl SpawnTimer {
+    fn new(difficulty: Difficulty) -> Self {
+        let interval = SPAWN_TIMER * difficulty.preset().spawn_interval;
+
+        Self {
+            timer: Timer::new(Duration::from_secs_f32(interval), TimerMode::Repeating),
+        }
+    }
+
+    /// Whether something spawned this frame.
+    pub fn just_finished(&self) -> bool {
+        self.timer.just_finished()
+    }
+}
+
+impl Default for SpawnTimer {
+    fn default() -> Self {
+        Self::new(Difficulty::default())
+    }
+}
+
+/// Every [`WAVE_DURATION`] seconds the wave goes up and asteroids spawn a bit faster.
+#[derive(Resource, Debug, Reflect)]
+#[reflect(Resource)]
+pub struct Wave {
+    pub number: u32,
+    timer: Timer,
+}
+
+impl Default for Wave {
+    fn default() -> Self {
+        Self {
+            number: 1,
+            timer: Timer::new(Duration::from_secs_f32(WAVE_DURATION), TimerMode::Repeating),
+        }
+    }
+}
+
+impl std::fmt::Display for Wave {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.number)
+    }
+}
+
+/// Randomness for spawning, so a run can be repeated from its seed.
+#[derive(Resource, Debug, Deref, DerefMut)]
+pub struct SpawnRng(StdRng);
+
+impl Default for SpawnRng {
+    fn default() -> Self {
+        Self(StdRng::from_entropy())
+    }
+}
+
+type SpaceshipColliders<'w, 's> =
+    Query<'w, 's, (&'static GlobalTransform, &'static Collider), With<Spaceship>>;
+
+pub fn spawn_asteroid_on_interval(
     mut commands: Commands,
     mut spawn_timer: ResMut<SpawnTimer>,
     time: Res<Time>,
     assets: Res<SceneAssets>,
-    spaceship: Query<(&GlobalTransform, &Collider), With<Spaceship>>,
+    difficulty: Res<Difficulty>,
+    spaceships: SpaceshipColliders,
+    mut rng: ResMut<SpawnRng>,
 ) {
     spawn_timer.timer.tick(time.delta());
 
     if spawn_timer.timer.just_finished() {
-        let Ok(spaceship) = spaceship.get_single() else {
-            error!("Didn't find a spaceship");
-            return;
-        };
-        spawn_asteroid(&mut commands, &assets, spaceship);
+        spawn_asteroid(&mut commands, &assets, *difficulty, &spaceships, &mut rng);
     }
 }
 
 fn spawn_asteroid(
     commands: &mut Commands,
     assets: &Res<SceneAssets>,
-    (spaceship_transform, spaceship_collider): (&GlobalTransform, &Collider),
+    difficulty: Difficulty,
+    spaceships: &SpaceshipColliders,
+    rng: &mut SpawnRng,
 ) {
-    let mut rng = rand::thread_rng();
+    let rng = &mut rng.0;
     let distribution = rand::distributions::Uniform::new_inclusive(-1.0, 1.0);
+    let size = AsteroidSize::ALL[rng.gen_range(0..AsteroidSize::ALL.len())];
 
     let translation = loop {
         let potential_spawn_point = Vec3::new(
@@ -63,39 +189,61 @@ fn spawn_asteroid(
             rng.gen_range(SPAWN_RANGE_Y),
         );
 
-        let distance = spaceship_transform
-            .translation()
-            .distance(potential_spawn_point);
+        let clear_of_spaceships =
+            spaceships
+                .iter()
+                .all(|(spaceship_transform, spaceship_collider)| {
+                    let distance = spaceship_transform
+                        .translation()
+                        .distance(potential_spawn_point);
+
+                    distance > spaceship_collider.radius + (size.radius() * 3.)
+                });
 
-        if distance > spaceship_collider.radius + (ASTEROID_RADIUS * 3.) {
+        if clear_of_spaceships {
             break potential_spawn_point;
         }
     };
 
     let mut random_unit_vector = || {
         Vec3::new(
-            distribution.sample(&mut rng),
+            distribution.sample(&mut *rng),
             0.,
-            distribution.sample(&mut rng),
+            distribution.sample(&mut *rng),
         )
     };
 
-    let velocity = random_unit_vector() * VELOCITY_SCALAR;
+    let velocity = random_unit_vector() * VELOCITY_SCALAR * difficulty.preset().asteroid_speed;
 
     info!("Spawning asteroid velocity: {velocity:?}");
 
+    spawn_asteroid_at(commands, assets, size, translation, velocity);
+}
+
+pub fn spawn_asteroid_at(
+    commands: &mut Commands,
+    assets: &SceneAssets,
+    size: AsteroidSize,
+    translation: Vec3,
+    velocity: Vec3,
+) {
     commands.spawn((
         MovingObjectBundle {
             velocity: Velocity::new(velocity),
             acceleration: Acceleration::new(Vec3::zeroed()),
             model: SceneBundle {
                 scene: assets.asteroids.clone(),
-                transform: Transform::from_translation(translation),
+                transform: Transform::from_translation(translation)
+                    .with_scale(Vec3::splat(size.scale())),
                 ..default()
             },
-            collider: Collider::new(ASTEROID_RADIUS),
+            collider: size.collider(),
         },
+        ColliderFit::new(ASTEROID_COLLIDER_FIT),
         Asteroid,
+        size,
+        PointValue(size.points()),
+        RadarBlip(BlipKind::Asteroid),
         DespawnAtEndgame,
     ));
 }
@@ -103,16 +251,90 @@ fn spawn_asteroid(
 fn spawn_initial_asteroids(
     mut commands: Commands,
     assets: Res<SceneAssets>,
-    spaceship_query: Query<(&GlobalTransform, &Collider), With<Spaceship>>,
+    difficulty: Res<Difficulty>,
+    spaceships: SpaceshipColliders,
+    mut rng: ResMut<SpawnRng>,
 ) {
-    let Ok(spaceship) = spaceship_query.get_single() else {
-        error!("Didn't find a spaceship");
-        return;
-    };
+    let count =
+        DifficultyPreset::scale_count(difficulty.preset().initial_asteroids, INITIAL_ASTEROIDS);
+
+    for _ in 0..count {
+        spawn_asteroid(&mut commands, &assets, *difficulty, &spaceships, &mut rng);
+    }
+}
+
+fn spawn_asteroids_command(world: &mut World, arguments: &[&str]) -> Result<String, String> {
+    let count: u32 = parse_argument(arguments)?;
+    if !world.contains_resource::<SceneAssets>() {
+        return Err("Still loading".to_string());
+    }
+
+    let mut state: SystemState<(
+        Commands,
+        Res<SceneAssets>,
+        Res<Difficulty>,
+        SpaceshipColliders,
+        ResMut<SpawnRng>,
+    )> = SystemState::new(world);
+    let (mut commands, assets, difficulty, spaceships, mut rng) = state.get_mut(world);
+
+    for _ in 0..count {
+        spawn_asteroid(&mut commands, &assets, *difficulty, &spaceships, &mut rng);
+    }
+
+    state.apply(world);
+    Ok(format!("Spawned {count} asteroids"))
+}
 
-    for _ in 0..5 {
-        spawn_asteroid(&mut commands, &assets, spaceship);
+fn set_wave(world: &mut World, arguments: &[&str]) -> Result<String, String> {
+    let number: u32 = parse_argument(arguments)?;
+    if number == 0 {
+        return Err("Waves start at 1".to_string());
     }
+
+    let difficulty = *world.resource::<Difficulty>();
+    world.resource_mut::<Wave>().number = number;
+    world
+        .resource_mut::<SpawnTimer>()
+        .timer
+        .set_duration(spawn_interval(number, difficulty));
+    Ok(format!("Starting wave {number}"))
+}
+
+fn set_seed(world: &mut World, arguments: &[&str]) -> Result<String, String> {
+    let seed: u64 = parse_argument(arguments)?;
+    world.insert_resource(SpawnRng(StdRng::seed_from_u64(seed)));
+    Ok(format!("Spawning from seed {seed}"))
+}
+
+fn spawn_interval(wave: u32, difficulty: Difficulty) -> Duration {
+    let speedup = 1. + WAVE_SPAWN_SPEEDUP * (wave - 1) as f32;
+    Duration::from_secs_f32(SPAWN_TIMER * difficulty.preset().spawn_interval / speedup)
+}
+
+fn advance_wave(
+    mut wave: ResMut<Wave>,
+    mut spawn_timer: ResMut<SpawnTimer>,
+    mut completed: EventWriter<WaveCompleted>,
+    difficulty: Res<Difficulty>,
+    time: Res<Time>,
+) {
+    if !wave.timer.tick(time.delta()).just_finished() {
+        return;
+    }
+
+    completed.send(WaveCompleted { wave: wave.number });
+    wave.number += 1;
+    info!("Starting wave {}", wave.number);
+
+    spawn_timer
+        .timer
+        .set_duration(spawn_interval(wave.number, *difficulty));
+}
+
+fn reset_waves(mut commands: Commands, difficulty: Res<Difficulty>) {
+    commands.insert_resource(Wave::default());
+    commands.insert_resource(SpawnTimer::new(*difficulty));
 }
 
 fn rotate_asteroids(mut query: Query<&mut Transform, With<Asteroid>>, time: Res<Time>) {
@@ -125,22 +347,50 @@ pub struct AsteroidPlugin;
 
 impl Plugin for AsteroidPlugin {
     fn build(&self, app: &mut App) {
-        app.insert_resource(SpawnTimer {
-            timer: Timer::new(Duration::from_secs_f32(SPAWN_TIMER), TimerMode::Repeating),
-        })
-        .register_type::<Asteroid>()
-        .add_systems(
-            OnTransition {
-                from: AppState::MainMenu,
-                to: AppState::InGame,
-            },
-            spawn_initial_asteroids,
-        )
-        .add_systems(
-            Update,
-            (spawn_asteroid_on_interval, rotate_asteroids)
-                .in_set(InGameSet::EntityUpdates)
-                .run_if(in_state(AppState::InGame)),
-        );
+        app.init_resource::<SpawnTimer>()
+            .init_resource::<Wave>()
+            .init_resource::<SpawnRng>()
+            .add_console_command(ConsoleCommand::new(
+                "spawn asteroid",
+                "spawn asteroid <count>",
+                "Spawn asteroids away from the spaceships",
+                spawn_asteroids_command,
+            ))
+            .add_console_command(ConsoleCommand::new(
+                "wave",
+                "wave <number>",
+                "Jump to a wave",
+                set_wave,
+            ))
+            .add_console_command(ConsoleCommand::new(
+                "seed",
+                "seed <number>",
+                "Seed the spawn randomness, to repeat a run",
+                set_seed,
+            ))
+            .add_event::<WaveCompleted>()
+            .register_type::<Asteroid>()
+            .register_type::<AsteroidSize>()
+            .register_type::<SpawnTimer>()
+            .register_type::<Wave>()
+            .add_systems(
+                OnTransition {
+                    from: AppState::MainMenu,
+                    to: AppState::InGame,
+                },
+                (spawn_initial_asteroids.after(spawn_spaceships), reset_waves)
+                    .run_if(not(resource_exists::<ContinueGame>)),
+            )
+            .add_systems(
+                Update,
+                (
+                    // A boss fight is a wave of its own.
+                    (spawn_asteroid_on_interval, advance_wave)
+                        .run_if(not(any_with_component::<Boss>)),
+                    rotate_asteroids,
+                )
+                    .in_set(InGameSet::EntityUpdates)
+                    .run_if(in_state(AppState::InGame)),
+            );
     }
 }
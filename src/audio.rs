@@ -0,0 +1,459 @@
+pub mod synth;
+
+use bevy::{
+    audio::{DefaultSpatialScale, SpatialScale, Volume},
+    prelude::*,
+    utils::HashMap,
+};
+use serde::Deserialize;
+
+use crate::{
+    application::AppState,
+    asset_loader::SceneAssets,
+    collision_detection::{HazardDestroyed, SpaceshipDamaged},
+    hazards::MineDetonated,
+    schedule::InGameSet,
+    settings::Settings,
+    spaceship::{lives::SpaceshipDestroyed, shield::SpaceshipShield, MissileFired},
+    ui::RadarBlip,
+};
+
+use self::synth::{SynthPresets, SynthPresetsLoader};
+
+/// Seconds for one music track to fade out and the next to fade in.
+const CROSSFADE_TIME: f32 = 1.5;
+
+/// Sounds this far from the listener play at full volume, and fall off with
+/// the square of the distance beyond it. About the height of the camera, so
+/// anything on screen is loud and anything well off it is faint.
+const FULL_VOLUME_DISTANCE: f32 = 60.;
+/// How far apart the listener's ears are, which sets how hard sounds pan.
+const EAR_GAP: f32 = 8.;
+/// Hazards hum quietly, so a field of them doesn't drown everything out.
+const HUM_VOLUME: f32 = 0.3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+pub enum Sfx {
+    Fire,
+    /// Something hit a spaceship.
+    Hit,
+    ShieldUp,
+    ShieldDown,
+    SpaceshipDestroyed,
+    AsteroidDestroyed,
+    MineDetonated,
+    GameOver,
+    /// Loops on every hazard, so they can be heard coming.
+    HazardHum,
+}
+
+/// The sound for each gameplay event. Events without one are silent.
+#[derive(Resource, Debug, Default)]
+pub struct SoundEffects {
+    sounds: HashMap<Sfx, Handle<AudioSource>>,
+    /// Most sounds are rendered from these.
+    presets: Handle<SynthPresets>,
+}
+
+impl SoundEffects {
+    pub fn set(&mut self, sfx: Sfx, sound: Handle<AudioSource>) {
+        self.sounds.insert(sfx, sound);
+    }
+
+    pub fn play(&self, commands: &mut Commands, sfx: Sfx, settings: &Settings) {
+        let Some(sound) = self.sounds.get(&sfx) else {
+            return;
+        };
+
+        commands.spawn(AudioBundle {
+            source: sound.clone(),
+            settings: PlaybackSettings::DESPAWN
+                .with_volume(Volume::new(settings.audio.sfx_volume())),
+        });
+    }
+
+    /// Plays a sound from a point in the arena, heard from the camera.
+    pub fn play_at(&self, commands: &mut Commands, sfx: Sfx, position: Vec3, settings: &Settings) {
+        let Some(sound) = self.sounds.get(&sfx) else {
+            return;
+        };
+
+        commands.spawn((
+            AudioBundle {
+                source: sound.clone(),
+                settings: PlaybackSettings::DESPAWN
+                    .with_volume(Volume::new(settings.audio.sfx_volume()))
+                    .with_spatial(true),
+            },
+            SpatialBundle::from_transform(Transform::from_translation(position)),
+        ));
+    }
+}
+
+/// A sound that loops on a hazard for as long as it's around.
+#[derive(Component, Debug)]
+struct Hum;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+pub enum Track {
+    Menu,
+    Game,
+    GameOver,
+}
+
+impl Track {
+    fn for_state(state: &AppState) -> Option<Track> {
+        match state {
+            AppState::Loading => None,
+            AppState::MainMenu | AppState::Settings => Some(Track::Menu),
+            AppState::InGame | AppState::Paused | AppState::Online => Some(Track::Game),
+            AppState::EndGame => Some(Track::GameOver),
+        }
+    }
+}
+
+/// Background music, rendered from the tunes in the synth presets. A track
+/// without a tune just leaves that state quiet.
+#[derive(Resource, Debug, Default)]
+struct Music {
+    tracks: HashMap<Track, Handle<AudioSource>>,
+}
+
+/// A playing music track, fading in or out. `fade` goes from 0 to 1.
+#[derive(Component, Debug)]
+struct MusicTrack {
+    track: Track,
+    fade: f32,
+    fading_in: bool,
+}
+
+/// The handles are filled in once the tunes are rendered. Until then a track
+/// waits to start, so the menu music can be asked for before it exists.
+fn reserve_music(mut commands: Commands, audio: Res<Assets<AudioSource>>) {
+    let tracks = [Track::Menu, Track::Game, Track::GameOver]
+        .into_iter()
+        .map(|track| (track, audio.reserve_handle()))
+        .collect();
+
+    commands.insert_resource(Music { tracks });
+}
+
+fn load_synth_presets(mut sounds: ResMut<SoundEffects>, asset_server: Res<AssetServer>) {
+    sounds.presets = asset_server.load(SynthPresets::PATH);
+}
+
+fn load_sound_effects(mut sounds: ResMut<SoundEffects>, assets: Res<SceneAssets>) {
+    sounds.set(Sfx::SpaceshipDestroyed, assets.explosion.clone());
+    sounds.set(Sfx::MineDetonated, assets.explosion.clone());
+}
+
+/// Renders every synthesized sound and tune again whenever the presets load
+/// or change.
+fn render_synth_sounds(
+    mut events: EventReader<AssetEvent<SynthPresets>>,
+    presets: Res<Assets<SynthPresets>>,
+    mut audio: ResMut<Assets<AudioSource>>,
+    mut sounds: ResMut<SoundEffects>,
+    music: Res<Music>,
+) {
+    for event in events.read() {
+        let (AssetEvent::LoadedWithDependencies { id } | AssetEvent::Modified { id }) = event
+        else {
+            continue;
+        };
+        if *id != sounds.presets.id() {
+            continue;
+        }
+        let Some(presets) = presets.get(*id) else {
+            continue;
+        };
+
+        for &sfx in presets.sounds.keys() {
+            let Some(params) = presets.sound(sfx) else {
+                continue;
+            };
+
+            let bytes = synth::to_wav(&params.render());
+            sounds.set(
+                sfx,
+                audio.add(AudioSource {
+                    bytes: bytes.into(),
+                }),
+            );
+        }
+
+        for (track, tune) in presets.music.iter() {
+            let Some(handle) = music.tracks.get(track) else {
+                continue;
+            };
+
+            let bytes = synth::to_wav(&tune.render(&presets.presets));
+            audio.insert(
+                handle.id(),
+                AudioSource {
+                    bytes: bytes.into(),
+                },
+            );
+        }
+    }
+}
+
+/// The camera hears everything, with its ears to the left and right of the
+/// screen.
+fn attach_listener(
+    mut commands: Commands,
+    cameras: Query<Entity, (With<Camera3d>, Without<SpatialListener>)>,
+) {
+    for camera in cameras.iter() {
+        commands
+            .entity(camera)
+            .insert(SpatialListener::new(EAR_GAP));
+    }
+}
+
+fn attach_hums(
+    mut commands: Commands,
+    sounds: Res<SoundEffects>,
+    settings: Res<Settings>,
+    hazards: Query<(Entity, &RadarBlip), Added<RadarBlip>>,
+) {
+    let Some(hum) = sounds.sounds.get(&Sfx::HazardHum) else {
+        return;
+    };
+
+    for (entity, blip) in hazards.iter() {
+        if !blip.0.is_hazard() {
+            continue;
+        }
+
+        commands.entity(entity).with_children(|builder| {
+            builder.spawn((
+                Hum,
+                AudioBundle {
+                    source: hum.clone(),
+                    settings: PlaybackSettings::LOOP
+                        .with_volume(Volume::new(HUM_VOLUME * settings.audio.sfx_volume()))
+                        .with_spatial(true),
+                },
+                SpatialBundle::default(),
+            ));
+        });
+    }
+}
+
+fn update_hums(
+    hums: Query<&SpatialAudioSink, With<Hum>>,
+    settings: Res<Settings>,
+    state: Res<State<AppState>>,
+) {
+    for hum in hums.iter() {
+        hum.set_volume(HUM_VOLUME * settings.audio.sfx_volume());
+
+        if *state.get() == AppState::Paused {
+            hum.pause();
+        } else {
+            hum.play();
+        }
+    }
+}
+
+/// Turns whatever is already playing up or down with the volume sliders,
+/// music by the music volume and everything else by the sound effects one.
+/// Hums have their own level and are kept up to date by [`update_hums`].
+fn apply_audio_settings(
+    music: Query<(&AudioSink, &MusicTrack)>,
+    sounds: Query<&AudioSink, Without<MusicTrack>>,
+    spatial_sounds: Query<&SpatialAudioSink, Without<Hum>>,
+    settings: Res<Settings>,
+) {
+    for (sink, track) in music.iter() {
+        sink.set_volume(track.fade * settings.audio.music_volume());
+    }
+
+    for sink in sounds.iter() {
+        sink.set_volume(settings.audio.sfx_volume());
+    }
+
+    for sink in spatial_sounds.iter() {
+        sink.set_volume(settings.audio.sfx_volume());
+    }
+}
+
+fn play_spaceship_sounds(
+    mut commands: Commands,
+    sounds: Res<SoundEffects>,
+    settings: Res<Settings>,
+    spaceships: Query<&GlobalTransform>,
+    mut fired: EventReader<MissileFired>,
+    mut damaged: EventReader<SpaceshipDamaged>,
+    mut destroyed: EventReader<SpaceshipDestroyed>,
+) {
+    let events = fired
+        .read()
+        .map(|fired| (fired.spaceship, Sfx::Fire))
+        .chain(damaged.read().map(|hit| (hit.spaceship, Sfx::Hit)))
+        .chain(
+            destroyed
+                .read()
+                .map(|destroyed| (destroyed.spaceship, Sfx::SpaceshipDestroyed)),
+        );
+
+    for (spaceship, sfx) in events {
+        let Ok(transform) = spaceships.get(spaceship) else {
+            continue;
+        };
+
+        sounds.play_at(&mut commands, sfx, transform.translation(), &settings);
+    }
+}
+
+fn play_hazard_sounds(
+    mut commands: Commands,
+    sounds: Res<SoundEffects>,
+    settings: Res<Settings>,
+    mut hazards: EventReader<HazardDestroyed>,
+    mut mines: EventReader<MineDetonated>,
+) {
+    for hazard in hazards.read() {
+        sounds.play_at(
+            &mut commands,
+            Sfx::AsteroidDestroyed,
+            hazard.position,
+            &settings,
+        );
+    }
+
+    for mine in mines.read() {
+        sounds.play_at(&mut commands, Sfx::MineDetonated, mine.position, &settings);
+    }
+}
+
+fn play_shield_sounds(
+    mut commands: Commands,
+    sounds: Res<SoundEffects>,
+    settings: Res<Settings>,
+    raised: Query<(), Added<SpaceshipShield>>,
+    mut dropped: RemovedComponents<SpaceshipShield>,
+) {
+    if !raised.is_empty() {
+        sounds.play(&mut commands, Sfx::ShieldUp, &settings);
+    }
+
+    if dropped.read().count() > 0 {
+        sounds.play(&mut commands, Sfx::ShieldDown, &settings);
+    }
+}
+
+fn play_game_over(mut commands: Commands, sounds: Res<SoundEffects>, settings: Res<Settings>) {
+    sounds.play(&mut commands, Sfx::GameOver, &settings);
+}
+
+/// Fades out whatever is playing and fades in the new state's track.
+fn switch_music(
+    mut commands: Commands,
+    state: Res<State<AppState>>,
+    music: Res<Music>,
+    mut playing: Query<&mut MusicTrack>,
+) {
+    let wanted = Track::for_state(state.get());
+    let mut found = false;
+
+    for mut track in playing.iter_mut() {
+        track.fading_in = Some(track.track) == wanted;
+        found |= track.fading_in;
+    }
+
+    let Some(track) = wanted.filter(|_| !found) else {
+        return;
+    };
+
+    commands.spawn((
+        MusicTrack {
+            track,
+            fade: 0.,
+            fading_in: true,
+        },
+        AudioBundle {
+            source: music.tracks[&track].clone(),
+            settings: PlaybackSettings::LOOP.with_volume(Volume::new(0.)),
+        },
+    ));
+}
+
+/// Runs on real time, so music doesn't stall during a hit-stop.
+fn crossfade_music(
+    mut commands: Commands,
+    mut playing: Query<(Entity, &mut MusicTrack, Option<&AudioSink>)>,
+    settings: Res<Settings>,
+    time: Res<Time<Real>>,
+) {
+    let step = time.delta_seconds() / CROSSFADE_TIME;
+
+    for (entity, mut track, sink) in playing.iter_mut() {
+        track.fade = if track.fading_in {
+            (track.fade + step).min(1.)
+        } else {
+            track.fade - step
+        };
+
+        if track.fade <= 0. {
+            commands.entity(entity).despawn_recursive();
+            continue;
+        }
+
+        // The sink only turns up once the track has loaded.
+        if let Some(sink) = sink {
+            sink.set_volume(track.fade * settings.audio.music_volume());
+        }
+    }
+}
+
+pub struct AudioPlugin;
+
+impl Plugin for AudioPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<SynthPresets>()
+            .init_asset_loader::<SynthPresetsLoader>()
+            .init_resource::<SoundEffects>()
+            .insert_resource(DefaultSpatialScale(SpatialScale::new(
+                1. / FULL_VOLUME_DISTANCE,
+            )))
+            .add_systems(Startup, (reserve_music, load_synth_presets))
+            .add_systems(OnExit(AppState::Loading), load_sound_effects)
+            .add_systems(
+                Update,
+                (
+                    play_spaceship_sounds,
+                    play_hazard_sounds,
+                    play_shield_sounds,
+                )
+                    .after(InGameSet::EntityUpdates)
+                    .run_if(in_state(AppState::InGame)),
+            )
+            .add_systems(
+                Update,
+                attach_hums.run_if(in_state(AppState::InGame).or_else(in_state(AppState::Online))),
+            )
+            .add_systems(
+                Update,
+                update_hums.run_if(resource_changed::<Settings>.or_else(state_changed::<AppState>)),
+            )
+            .add_systems(
+                Update,
+                apply_audio_settings.run_if(resource_changed::<Settings>),
+            )
+            .add_systems(Update, render_synth_sounds)
+            .add_systems(OnEnter(AppState::EndGame), play_game_over)
+            .add_systems(
+                Update,
+                (
+                    attach_listener,
+                    (
+                        switch_music.run_if(state_changed::<AppState>),
+                        crossfade_music,
+                    )
+                        .chain(),
+                ),
+            );
+    }
+}
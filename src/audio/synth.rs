@@ -0,0 +1,417 @@
+use bevy::{
+    asset::{io::Reader, ron, AssetLoader, AsyncReadExt, LoadContext},
+    prelude::*,
+    utils::{BoxedFuture, HashMap},
+};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::Deserialize;
+use std::{f32::consts::TAU, fs, io, path::Path};
+
+use super::{Sfx, Track};
+
+pub const SAMPLE_RATE: u32 = 44_100;
+/// Noise is seeded, so a preset always renders the same sound.
+const NOISE_SEED: u64 = 0x5f3759df;
+/// Times noise changes value over one period of the wave.
+const NOISE_STEPS: f32 = 32.;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+pub enum Waveform {
+    #[default]
+    Square,
+    Sawtooth,
+    Sine,
+    Triangle,
+    Noise,
+}
+
+/// Jumps the pitch once, part way through, for the two-note blips of pickups.
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct Arpeggio {
+    pub multiplier: f32,
+    /// Seconds in.
+    pub time: f32,
+}
+
+/// An sfxr-style sound. Times are in seconds, frequencies in Hz and slides in
+/// octaves per second.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct SynthParams {
+    pub wave: Waveform,
+    pub frequency: f32,
+    /// The sound cuts off once its pitch slides below this.
+    pub min_frequency: f32,
+    pub frequency_slide: f32,
+    /// Change in `frequency_slide` per second.
+    pub frequency_slide_change: f32,
+    /// Fraction of the pitch to wobble by.
+    pub vibrato_depth: f32,
+    pub vibrato_speed: f32,
+    pub arpeggio: Option<Arpeggio>,
+    /// Fraction of each period a square wave spends high.
+    pub duty: f32,
+    pub duty_slide: f32,
+    pub attack: f32,
+    pub sustain: f32,
+    /// Extra volume at the start of the sustain, fading over it.
+    pub punch: f32,
+    pub decay: f32,
+    pub low_pass: Option<f32>,
+    pub high_pass: Option<f32>,
+    pub volume: f32,
+}
+
+impl Default for SynthParams {
+    fn default() -> Self {
+        Self {
+            wave: Waveform::Square,
+            frequency: 440.,
+            min_frequency: 20.,
+            frequency_slide: 0.,
+            frequency_slide_change: 0.,
+            vibrato_depth: 0.,
+            vibrato_speed: 0.,
+            arpeggio: None,
+            duty: 0.5,
+            duty_slide: 0.,
+            attack: 0.,
+            sustain: 0.1,
+            punch: 0.,
+            decay: 0.2,
+            low_pass: None,
+            high_pass: None,
+            volume: 0.5,
+        }
+    }
+}
+
+impl SynthParams {
+    fn envelope(&self, time: f32) -> f32 {
+        if time < self.attack {
+            return time / self.attack;
+        }
+
+        let time = time - self.attack;
+        if time < self.sustain {
+            return 1. + self.punch * (1. - time / self.sustain);
+        }
+
+        let time = time - self.sustain;
+        (1. - time / self.decay).max(0.)
+    }
+
+    /// Samples in `-1.0..=1.0` at [`SAMPLE_RATE`].
+    pub fn render(&self) -> Vec<f32> {
+        let delta = 1. / SAMPLE_RATE as f32;
+        let length = ((self.attack + self.sustain + self.decay) * SAMPLE_RATE as f32) as usize;
+
+        let mut rng = StdRng::seed_from_u64(NOISE_SEED);
+        let mut noise: f32 = rng.gen_range(-1.0..1.0);
+        let mut noise_step = 0;
+
+        let mut frequency = self.frequency;
+        let mut slide = self.frequency_slide;
+        let mut duty = self.duty;
+        let mut arpeggio = self.arpeggio;
+        let mut phase: f32 = 0.;
+
+        let mut low_passed: f32 = 0.;
+        let mut high_passed: f32 = 0.;
+        let mut last_input: f32 = 0.;
+
+        let mut samples = Vec::with_capacity(length);
+
+        for index in 0..length {
+            let time = index as f32 * delta;
+
+            if let Some(jump) = arpeggio.filter(|jump| time >= jump.time) {
+                frequency *= jump.multiplier;
+                arpeggio = None;
+            }
+
+            slide += self.frequency_slide_change * delta;
+            frequency *= (slide * delta).exp2();
+            if frequency < self.min_frequency {
+                break;
+            }
+
+            let vibrato = 1. + self.vibrato_depth * (TAU * self.vibrato_speed * time).sin();
+            phase = (phase + frequency * vibrato * delta).fract();
+            duty = (duty + self.duty_slide * delta).clamp(0.05, 0.95);
+
+            let step = (phase * NOISE_STEPS) as u32;
+            if step != noise_step {
+                noise_step = step;
+                noise = rng.gen_range(-1.0..1.0);
+            }
+
+            let mut sample = match self.wave {
+                Waveform::Square if phase < duty => 1.,
+                Waveform::Square => -1.,
+                Waveform::Sawtooth => 1. - 2. * phase,
+                Waveform::Sine => (TAU * phase).sin(),
+                Waveform::Triangle => 1. - 4. * (phase - 0.5).abs(),
+                Waveform::Noise => noise,
+            };
+
+            if let Some(cutoff) = self.low_pass {
+                let smoothing = delta / (1. / (TAU * cutoff) + delta);
+                low_passed += (sample - low_passed) * smoothing;
+                sample = low_passed;
+            }
+
+            if let Some(cutoff) = self.high_pass {
+                let rc = 1. / (TAU * cutoff);
+                high_passed = rc / (rc + delta) * (high_passed + sample - last_input);
+                last_input = sample;
+                sample = high_passed;
+            }
+
+            samples.push((sample * self.envelope(time) * self.volume).clamp(-1., 1.));
+        }
+
+        samples
+    }
+}
+
+/// One line of a [`Tune`], played with a preset.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Part {
+    pub voice: String,
+    /// Semitones above the voice's frequency for each step, or `None` to rest.
+    pub notes: Vec<Option<i32>>,
+}
+
+/// A loop of music. Parts shorter than the longest one repeat to fill it.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Tune {
+    /// Seconds per note.
+    pub step: f32,
+    pub parts: Vec<Part>,
+}
+
+impl Tune {
+    /// Samples for one time round the loop. Notes still ringing at the end
+    /// carry on at the start, so it loops without a click.
+    pub fn render(&self, presets: &HashMap<String, SynthParams>) -> Vec<f32> {
+        let step = (self.step * SAMPLE_RATE as f32) as usize;
+        let steps = self
+            .parts
+            .iter()
+            .map(|part| part.notes.len())
+            .max()
+            .unwrap_or_default();
+        let length = step * steps;
+
+        let mut samples = vec![0.; length];
+        if length == 0 {
+            return samples;
+        }
+
+        for part in self.parts.iter() {
+            let Some(voice) = presets.get(&part.voice) else {
+                warn!("No synth preset called {} for a tune", part.voice);
+                continue;
+            };
+
+            let notes = part.notes.iter().cycle().take(steps).enumerate();
+            for (index, &note) in notes {
+                let Some(semitones) = note else {
+                    continue;
+                };
+
+                let params = SynthParams {
+                    frequency: voice.frequency * (semitones as f32 / 12.).exp2(),
+                    ..voice.clone()
+                };
+
+                for (offset, sample) in params.render().into_iter().enumerate() {
+                    samples[(index * step + offset) % length] += sample;
+                }
+            }
+        }
+
+        for sample in samples.iter_mut() {
+            *sample = sample.clamp(-1., 1.);
+        }
+
+        samples
+    }
+}
+
+/// 16-bit mono PCM in a WAV file.
+pub fn to_wav(samples: &[f32]) -> Vec<u8> {
+    let data_length = samples.len() as u32 * 2;
+    let mut wav = Vec::with_capacity(44 + data_length as usize);
+
+    wav.extend_from_slice(b"RIFF");
+    wav.extend_from_slice(&(36 + data_length).to_le_bytes());
+    wav.extend_from_slice(b"WAVE");
+
+    wav.extend_from_slice(b"fmt ");
+    wav.extend_from_slice(&16u32.to_le_bytes());
+    // PCM, one channel.
+    wav.extend_from_slice(&1u16.to_le_bytes());
+    wav.extend_from_slice(&1u16.to_le_bytes());
+    wav.extend_from_slice(&SAMPLE_RATE.to_le_bytes());
+    wav.extend_from_slice(&(SAMPLE_RATE * 2).to_le_bytes());
+    wav.extend_from_slice(&2u16.to_le_bytes());
+    wav.extend_from_slice(&16u16.to_le_bytes());
+
+    wav.extend_from_slice(b"data");
+    wav.extend_from_slice(&data_length.to_le_bytes());
+    for sample in samples {
+        wav.extend_from_slice(&((sample * i16::MAX as f32) as i16).to_le_bytes());
+    }
+
+    wav
+}
+
+/// Named sounds, which of them plays for each gameplay event, and the music
+/// they play.
+#[derive(Asset, TypePath, Debug, Deserialize)]
+pub struct SynthPresets {
+    pub presets: HashMap<String, SynthParams>,
+    pub sounds: HashMap<Sfx, String>,
+    #[serde(default)]
+    pub music: HashMap<Track, Tune>,
+}
+
+impl SynthPresets {
+    pub const PATH: &'static str = "sounds.sfx.ron";
+
+    pub fn sound(&self, sfx: Sfx) -> Option<&SynthParams> {
+        let name = self.sounds.get(&sfx)?;
+        let params = self.presets.get(name);
+
+        if params.is_none() {
+            warn!("No synth preset called {name} for {sfx:?}");
+        }
+
+        params
+    }
+}
+
+#[derive(Default)]
+pub struct SynthPresetsLoader;
+
+impl AssetLoader for SynthPresetsLoader {
+    type Asset = SynthPresets;
+    type Settings = ();
+    type Error = Box<dyn std::error::Error + Send + Sync>;
+
+    fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader,
+        _settings: &'a (),
+        _load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<SynthPresets, Self::Error>> {
+        Box::pin(async move {
+            let mut bytes = Vec::new();
+            reader.read_to_end(&mut bytes).await?;
+            Ok(ron::de::from_bytes(&bytes)?)
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["sfx.ron"]
+    }
+}
+
+/// Writes every preset and tune to `directory` as a WAV file, to listen to or
+/// check them outside the game.
+pub fn render_presets(directory: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let file = fs::read_to_string(Path::new("assets").join(SynthPresets::PATH))?;
+    let presets: SynthPresets = ron::from_str(&file)?;
+
+    fs::create_dir_all(directory)?;
+
+    let sounds = presets
+        .presets
+        .iter()
+        .map(|(name, params)| (name.clone(), params.render()));
+    let tunes = presets.music.iter().map(|(track, tune)| {
+        (
+            format!("music-{track:?}").to_lowercase(),
+            tune.render(&presets.presets),
+        )
+    });
+
+    for (name, samples) in sounds.chain(tunes) {
+        if samples.is_empty() {
+            return Err(io::Error::other(format!("{name} renders to silence")).into());
+        }
+
+        let path = directory.join(format!("{name}.wav"));
+        fs::write(&path, to_wav(&samples))?;
+        println!(
+            "{}: {:.2}s",
+            path.display(),
+            samples.len() as f32 / SAMPLE_RATE as f32
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn presets() -> SynthPresets {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("assets")
+            .join(SynthPresets::PATH);
+        let file = fs::read_to_string(path).expect("couldn't read the presets");
+        ron::from_str(&file).expect("couldn't parse the presets")
+    }
+
+    #[test]
+    fn every_preset_renders_to_a_wav() {
+        let presets = presets();
+        assert!(!presets.presets.is_empty());
+
+        for (name, params) in presets.presets.iter() {
+            let samples = params.render();
+            assert!(!samples.is_empty(), "{name} renders to silence");
+
+            let wav = to_wav(&samples);
+            assert_eq!(&wav[0..4], b"RIFF", "{name}");
+            assert_eq!(&wav[8..12], b"WAVE", "{name}");
+            assert_eq!(wav.len(), 44 + samples.len() * 2, "{name}");
+        }
+    }
+
+    #[test]
+    fn every_sound_has_a_preset() {
+        let presets = presets();
+
+        for (sfx, name) in presets.sounds.iter() {
+            assert!(presets.presets.contains_key(name), "{sfx:?} plays {name}");
+        }
+    }
+
+    #[test]
+    fn every_track_has_a_tune_that_loops() {
+        let presets = presets();
+
+        for track in [Track::Menu, Track::Game, Track::GameOver] {
+            let tune = &presets.music[&track];
+
+            for part in tune.parts.iter() {
+                assert!(
+                    presets.presets.contains_key(&part.voice),
+                    "{track:?} plays {}",
+                    part.voice
+                );
+            }
+
+            let samples = tune.render(&presets.presets);
+            let steps = tune.parts.iter().map(|part| part.notes.len()).max();
+            let step = (tune.step * SAMPLE_RATE as f32) as usize;
+            assert_eq!(Some(samples.len()), steps.map(|steps| steps * step));
+            assert!(samples.iter().any(|&sample| sample != 0.), "{track:?}");
+        }
+    }
+}
@@ -0,0 +1,582 @@
+use bevy::{ecs::system::SystemState, prelude::*, utils::HashSet};
+use bevy_health_bar3d::configuration::{BarHeight, BarSettings};
+use std::{f32::consts::TAU, time::Duration};
+
+use crate::{
+    application::AppState,
+    asset_loader::SceneAssets,
+    asteroid::{self, AsteroidSize, WaveCompleted},
+    collision_detection::{Collider, ColliderFit, ColliderShape, Hazard, HazardDestroyed},
+    console::{ConsoleAppExt, ConsoleCommand},
+    end_game::DespawnAtEndgame,
+    movement::{Acceleration, MovingObjectBundle, Velocity},
+    player::PlayerId,
+    schedule::InGameSet,
+    scoring::PointValue,
+    spaceship::{health::Health, Missile, Spaceship},
+    ui::{BlipKind, RadarBlip},
+};
+
+/// A boss turns up at the end of every this many waves.
+const BOSS_WAVE_INTERVAL: u32 = 5;
+
+const BOSS_SCALE: f32 = 2.5;
+/// Until the model loads and [`ColliderFit`] takes over.
+const BOSS_RADIUS: f32 = 8.0;
+const BOSS_COLLIDER_SIDES: usize = 8;
+const BOSS_COLLIDER_FIT: f32 = 0.9;
+const BOSS_POINTS: usize = 25;
+
+/// It flies in from the top of the arena and hangs about here.
+const BOSS_ENTRY: Vec3 = Vec3::new(0., 0., -45.);
+const BOSS_STATION: Vec3 = Vec3::new(0., 0., -22.);
+const BOSS_SPEED: f32 = 8.0;
+const BOSS_SWAY_DISTANCE: f32 = 18.0;
+const BOSS_SWAY_SPEED: f32 = 0.4;
+const BOSS_SPIN_SPEED: f32 = 0.3;
+
+/// Where the weak points sit around the boss, in arena units.
+const WEAK_POINTS: [Vec3; 4] = [
+    Vec3::new(7., 0., 0.),
+    Vec3::new(-7., 0., 0.),
+    Vec3::new(0., 0., 7.),
+    Vec3::new(0., 0., -7.),
+];
+const WEAK_POINT_RADIUS: f32 = 1.5;
+const WEAK_POINT_HEALTH: u32 = 6;
+const WEAK_POINT_POINTS: usize = 5;
+
+/// Every hit on a weak point hurts the boss, so it goes down with the last
+/// of them.
+const BOSS_HEALTH: u32 = WEAK_POINTS.len() as u32 * WEAK_POINT_HEALTH;
+const PHASE_TWO_HEALTH: u32 = BOSS_HEALTH * 2 / 3;
+const PHASE_THREE_HEALTH: u32 = BOSS_HEALTH / 3;
+
+const BULLET_RADIUS: f32 = 0.8;
+const BULLET_SPEED: f32 = 12.0;
+
+const MINION_RADIUS: f32 = 1.5;
+const MINION_COLLIDER_FIT: f32 = 0.9;
+const MINION_SPEED: f32 = 9.0;
+/// How quickly minions turn towards their target, per second.
+const MINION_TURNING: f32 = 1.5;
+const MINION_POINTS: usize = 2;
+
+const BARRAGE_SPEED: f32 = 15.0;
+/// Radians either side of the nearest spaceship the barrage fans out over.
+const BARRAGE_SPREAD: f32 = 0.5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BossPhase {
+    One,
+    Two,
+    Three,
+}
+
+impl BossPhase {
+    fn for_health(health: &Health) -> Self {
+        if *health > PHASE_TWO_HEALTH {
+            BossPhase::One
+        } else if *health > PHASE_THREE_HEALTH {
+            BossPhase::Two
+        } else {
+            BossPhase::Three
+        }
+    }
+
+    /// Taken in turn, one every [`BossPhase::attack_interval`].
+    fn attacks(&self) -> &'static [Attack] {
+        match self {
+            BossPhase::One => &[Attack::BulletRing],
+            BossPhase::Two => &[Attack::BulletRing, Attack::Minions],
+            BossPhase::Three => &[
+                Attack::BulletRing,
+                Attack::AsteroidBarrage,
+                Attack::BulletRing,
+                Attack::Minions,
+            ],
+        }
+    }
+
+    fn attack_interval(&self) -> Duration {
+        Duration::from_secs_f32(match self {
+            BossPhase::One => 3.0,
+            BossPhase::Two => 2.5,
+            BossPhase::Three => 1.6,
+        })
+    }
+
+    fn bullets(&self) -> usize {
+        match self {
+            BossPhase::One => 10,
+            BossPhase::Two => 14,
+            BossPhase::Three => 18,
+        }
+    }
+
+    fn minions(&self) -> usize {
+        match self {
+            BossPhase::One | BossPhase::Two => 2,
+            BossPhase::Three => 3,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Attack {
+    BulletRing,
+    Minions,
+    AsteroidBarrage,
+}
+
+#[derive(Component, Debug)]
+pub struct Boss {
+    phase: BossPhase,
+    attack_timer: Timer,
+    attacks_made: usize,
+    /// Seconds since it turned up, for swaying about.
+    age: f32,
+    /// Whoever hit it last gets the points for finishing it off.
+    last_hit_by: Option<PlayerId>,
+}
+
+impl Default for Boss {
+    fn default() -> Self {
+        let phase = BossPhase::One;
+
+        Self {
+            phase,
+            attack_timer: Timer::new(phase.attack_interval(), TimerMode::Repeating),
+            attacks_made: 0,
+            age: 0.,
+            last_hit_by: None,
+        }
+    }
+}
+
+/// Part of a boss that missiles can damage. The rest of it is armoured.
+#[derive(Component, Debug)]
+struct WeakPoint;
+
+/// Chases the nearest spaceship.
+#[derive(Component, Debug)]
+struct Minion;
+
+#[derive(Resource, Debug)]
+struct BossAssets {
+    weak_point_mesh: Handle<Mesh>,
+    weak_point_material: Handle<StandardMaterial>,
+}
+
+fn load_boss_assets(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    let color = Color::rgb(1., 0.2, 0.1);
+
+    commands.insert_resource(BossAssets {
+        weak_point_mesh: meshes.add(Sphere::new(WEAK_POINT_RADIUS)),
+        weak_point_material: materials.add(StandardMaterial {
+            base_color: color,
+            emissive: color * 3.,
+            ..default()
+        }),
+    });
+}
+
+fn health_bar() -> BarSettings<Health> {
+    // In the boss's own units, which are scaled up with it.
+    BarSettings::<Health> {
+        offset: -14. / BOSS_SCALE,
+        height: BarHeight::Static(2. / BOSS_SCALE),
+        width: 30. / BOSS_SCALE,
+        ..default()
+    }
+}
+
+fn spawn_boss(commands: &mut Commands, assets: &SceneAssets, boss_assets: &BossAssets) {
+    info!("Spawning boss");
+
+    commands
+        .spawn((
+            Boss::default(),
+            MovingObjectBundle {
+                velocity: Velocity::new(Vec3::ZERO),
+                acceleration: Acceleration::new(Vec3::ZERO),
+                model: SceneBundle {
+                    scene: assets.boss.clone(),
+                    transform: Transform::from_translation(BOSS_ENTRY)
+                        .with_scale(Vec3::splat(BOSS_SCALE)),
+                    ..default()
+                },
+                collider: Collider::with_shape(ColliderShape::regular_polygon(
+                    BOSS_COLLIDER_SIDES,
+                    BOSS_RADIUS,
+                )),
+            },
+            ColliderFit::new(BOSS_COLLIDER_FIT),
+            Hazard::SOLID,
+            Health::new(BOSS_HEALTH),
+            health_bar(),
+            PointValue(BOSS_POINTS),
+            RadarBlip(BlipKind::Enemy),
+            DespawnAtEndgame,
+        ))
+        .with_children(|builder| {
+            for offset in WEAK_POINTS {
+                builder.spawn((
+                    WeakPoint,
+                    PbrBundle {
+                        mesh: boss_assets.weak_point_mesh.clone(),
+                        material: boss_assets.weak_point_material.clone(),
+                        // Undoes the boss's scale, so weak points are sized
+                        // and placed in arena units.
+                        transform: Transform::from_translation(offset / BOSS_SCALE)
+                            .with_scale(Vec3::splat(1. / BOSS_SCALE)),
+                        ..default()
+                    },
+                    Collider::new(WEAK_POINT_RADIUS),
+                    Hazard::SOLID,
+                    Health::new(WEAK_POINT_HEALTH),
+                    PointValue(WEAK_POINT_POINTS),
+                ));
+            }
+        });
+}
+
+fn spawn_boss_after_waves(
+    mut commands: Commands,
+    mut completed: EventReader<WaveCompleted>,
+    bosses: Query<(), With<Boss>>,
+    assets: Res<SceneAssets>,
+    boss_assets: Res<BossAssets>,
+) {
+    for &WaveCompleted { wave } in completed.read() {
+        if wave % BOSS_WAVE_INTERVAL == 0 && bosses.is_empty() {
+            spawn_boss(&mut commands, &assets, &boss_assets);
+        }
+    }
+}
+
+fn spawn_boss_command(world: &mut World, _arguments: &[&str]) -> Result<String, String> {
+    if !world.contains_resource::<SceneAssets>() {
+        return Err("Still loading".to_string());
+    }
+
+    let mut state: SystemState<(Commands, Res<SceneAssets>, Res<BossAssets>)> =
+        SystemState::new(world);
+    let (mut commands, assets, boss_assets) = state.get_mut(world);
+    spawn_boss(&mut commands, &assets, &boss_assets);
+
+    state.apply(world);
+    Ok("Spawned a boss".to_string())
+}
+
+/// Flies to its station, then sways from side to side, turning slowly.
+fn move_boss(mut bosses: Query<(&mut Boss, &mut Velocity, &mut Transform)>, time: Res<Time>) {
+    for (mut boss, mut velocity, mut transform) in bosses.iter_mut() {
+        boss.age += time.delta_seconds();
+
+        let sway = (boss.age * BOSS_SWAY_SPEED * TAU).sin() * BOSS_SWAY_DISTANCE;
+        let target = BOSS_STATION + Vec3::X * sway;
+        velocity.value = (target - transform.translation).clamp_length_max(BOSS_SPEED);
+
+        transform.rotate_y(BOSS_SPIN_SPEED * time.delta_seconds());
+    }
+}
+
+fn nearest_spaceship(
+    position: Vec3,
+    spaceships: &Query<&GlobalTransform, With<Spaceship>>,
+) -> Option<Vec3> {
+    spaceships
+        .iter()
+        .map(|transform| transform.translation())
+        .min_by(|a, b| a.distance(position).total_cmp(&b.distance(position)))
+}
+
+fn spawn_bullet_ring(commands: &mut Commands, assets: &SceneAssets, boss: &Boss, position: Vec3) {
+    let count = boss.phase.bullets();
+    // Every other ring is turned half a gap, so standing still isn't safe.
+    let offset = (boss.attacks_made % 2) as f32 * 0.5;
+
+    for index in 0..count {
+        let angle = TAU * (index as f32 + offset) / count as f32;
+        let direction = Vec3::new(angle.cos(), 0., angle.sin());
+
+        commands.spawn((
+            MovingObjectBundle {
+                velocity: Velocity::new(direction * BULLET_SPEED),
+                acceleration: Acceleration::new(Vec3::ZERO),
+                model: SceneBundle {
+                    scene: assets.missiles.clone(),
+                    transform: Transform::from_translation(position + direction * BOSS_RADIUS),
+                    ..default()
+                },
+                collider: Collider::new(BULLET_RADIUS),
+            },
+            Hazard::default(),
+            DespawnAtEndgame,
+        ));
+    }
+}
+
+fn spawn_minions(commands: &mut Commands, assets: &SceneAssets, boss: &Boss, position: Vec3) {
+    let count = boss.phase.minions();
+
+    for index in 0..count {
+        let angle = TAU * index as f32 / count as f32;
+        let direction = Vec3::new(angle.cos(), 0., angle.sin());
+
+        commands.spawn((
+            Minion,
+            MovingObjectBundle {
+                velocity: Velocity::new(direction * MINION_SPEED),
+                acceleration: Acceleration::new(Vec3::ZERO),
+                model: SceneBundle {
+                    scene: assets.minion.clone(),
+                    transform: Transform::from_translation(position + direction * BOSS_RADIUS),
+                    ..default()
+                },
+                collider: Collider::new(MINION_RADIUS),
+            },
+            ColliderFit::new(MINION_COLLIDER_FIT),
+            Hazard::default(),
+            PointValue(MINION_POINTS),
+            RadarBlip(BlipKind::Enemy),
+            DespawnAtEndgame,
+        ));
+    }
+}
+
+fn spawn_asteroid_barrage(
+    commands: &mut Commands,
+    assets: &SceneAssets,
+    position: Vec3,
+    target: Vec3,
+) {
+    let aim = (target - position).normalize_or_zero();
+    if aim == Vec3::ZERO {
+        return;
+    }
+
+    let count = 5;
+    for index in 0..count {
+        let angle = BARRAGE_SPREAD * (2. * index as f32 / (count - 1) as f32 - 1.);
+        let direction = Quat::from_rotation_y(angle) * aim;
+
+        asteroid::spawn_asteroid_at(
+            commands,
+            assets,
+            AsteroidSize::Small,
+            position + direction * BOSS_RADIUS,
+            direction * BARRAGE_SPEED,
+        );
+    }
+}
+
+fn boss_attacks(
+    mut commands: Commands,
+    mut bosses: Query<(&mut Boss, &GlobalTransform)>,
+    spaceships: Query<&GlobalTransform, With<Spaceship>>,
+    assets: Res<SceneAssets>,
+    time: Res<Time>,
+) {
+    for (mut boss, transform) in bosses.iter_mut() {
+        if !boss.attack_timer.tick(time.delta()).just_finished() {
+            continue;
+        }
+
+        let position = transform.translation();
+        let attacks = boss.phase.attacks();
+        let attack = attacks[boss.attacks_made % attacks.len()];
+
+        match attack {
+            Attack::BulletRing => spawn_bullet_ring(&mut commands, &assets, &boss, position),
+            Attack::Minions => spawn_minions(&mut commands, &assets, &boss, position),
+            Attack::AsteroidBarrage => {
+                if let Some(target) = nearest_spaceship(position, &spaceships) {
+                    spawn_asteroid_barrage(&mut commands, &assets, position, target);
+                }
+            }
+        }
+
+        boss.attacks_made += 1;
+    }
+}
+
+/// Minions turn gradually, so they can be dodged. Like spaceships, their
+/// models face +Z.
+fn steer_minions(
+    mut minions: Query<(&mut Velocity, &mut Transform), With<Minion>>,
+    spaceships: Query<&GlobalTransform, With<Spaceship>>,
+    time: Res<Time>,
+) {
+    for (mut velocity, mut transform) in minions.iter_mut() {
+        if let Some(target) = nearest_spaceship(transform.translation, &spaceships) {
+            let wanted = (target - transform.translation).normalize_or_zero() * MINION_SPEED;
+            let turning = (MINION_TURNING * time.delta_seconds()).min(1.);
+            velocity.value = velocity.value.lerp(wanted, turning);
+        }
+
+        if let Some(direction) = velocity.value.try_normalize() {
+            transform.look_to(-direction, Vec3::Y);
+        }
+    }
+}
+
+type WeakPoints<'w, 's> = Query<
+    'w,
+    's,
+    (
+        Entity,
+        &'static Parent,
+        &'static GlobalTransform,
+        &'static Collider,
+        &'static PointValue,
+        &'static mut Health,
+    ),
+    (With<WeakPoint>, Without<Boss>),
+>;
+
+/// Missiles chip away at weak points, and through them the boss. Anywhere
+/// else on the boss just stops them.
+fn handle_boss_hits(
+    mut commands: Commands,
+    mut weak_points: WeakPoints,
+    mut bosses: Query<(&mut Boss, &mut Health, &Collider), Without<WeakPoint>>,
+    minions: Query<(Entity, &GlobalTransform, &Collider, &PointValue), With<Minion>>,
+    missiles: Query<Option<&PlayerId>, With<Missile>>,
+    mut destroyed: EventWriter<HazardDestroyed>,
+) {
+    let mut spent = HashSet::new();
+
+    for (weak_point, parent, transform, collider, points, mut health) in weak_points.iter_mut() {
+        for &missile in collider.colliding_entities.iter() {
+            let Ok(player) = missiles.get(missile) else {
+                continue;
+            };
+            if !spent.insert(missile) {
+                continue;
+            }
+
+            commands.entity(missile).despawn_recursive();
+            *health -= 1;
+
+            if let Ok((mut boss, mut boss_health, _)) = bosses.get_mut(parent.get()) {
+                *boss_health -= 1;
+                boss.last_hit_by = player.copied();
+            }
+
+            if *health < 1 {
+                commands.entity(weak_point).despawn_recursive();
+                destroyed.send(HazardDestroyed {
+                    position: transform.translation(),
+                    radius: collider.radius,
+                    points: points.0,
+                    player: player.copied(),
+                });
+                break;
+            }
+        }
+    }
+
+    for (_, _, collider) in bosses.iter() {
+        for &missile in collider.colliding_entities.iter() {
+            if missiles.contains(missile) && spent.insert(missile) {
+                commands.entity(missile).despawn_recursive();
+            }
+        }
+    }
+
+    for (minion, transform, collider, points) in minions.iter() {
+        for &missile in collider.colliding_entities.iter() {
+            let Ok(player) = missiles.get(missile) else {
+                continue;
+            };
+            if !spent.insert(missile) {
+                continue;
+            }
+
+            commands.entity(missile).despawn_recursive();
+            commands.entity(minion).despawn_recursive();
+            destroyed.send(HazardDestroyed {
+                position: transform.translation(),
+                radius: collider.radius,
+                points: points.0,
+                player: player.copied(),
+            });
+            break;
+        }
+    }
+}
+
+/// Moves the boss on to its next phase as its health drops, and blows it up
+/// once it's gone.
+fn update_boss_phase(
+    mut commands: Commands,
+    mut bosses: Query<(
+        Entity,
+        &mut Boss,
+        &Health,
+        &GlobalTransform,
+        &Collider,
+        &PointValue,
+    )>,
+    mut destroyed: EventWriter<HazardDestroyed>,
+) {
+    for (entity, mut boss, health, transform, collider, points) in bosses.iter_mut() {
+        if *health < 1 {
+            info!("Boss defeated");
+            commands.entity(entity).despawn_recursive();
+            destroyed.send(HazardDestroyed {
+                position: transform.translation(),
+                radius: collider.radius,
+                points: points.0,
+                player: boss.last_hit_by,
+            });
+            continue;
+        }
+
+        let phase = BossPhase::for_health(health);
+        if phase != boss.phase {
+            info!("Boss entering phase {phase:?}");
+            boss.phase = phase;
+            boss.attack_timer = Timer::new(phase.attack_interval(), TimerMode::Repeating);
+        }
+    }
+}
+
+/// Multi-phase bosses with weak points, every few waves.
+pub struct BossPlugin;
+
+impl Plugin for BossPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_console_command(ConsoleCommand::new(
+            "spawn boss",
+            "spawn boss",
+            "Bring in a boss now",
+            spawn_boss_command,
+        ))
+        .add_systems(Startup, load_boss_assets)
+        .add_systems(
+            Update,
+            (
+                spawn_boss_after_waves,
+                move_boss,
+                boss_attacks,
+                steer_minions,
+            )
+                .in_set(InGameSet::EntityUpdates)
+                .run_if(in_state(AppState::InGame)),
+        )
+        .add_systems(
+            Update,
+            (handle_boss_hits, update_boss_phase)
+                .chain()
+                .in_set(InGameSet::DespawnEntities)
+                .run_if(in_state(AppState::InGame)),
+        );
+    }
+}
@@ -1,22 +1,247 @@
+mod shake;
+
 use std::f32::consts::PI;
 
-use bevy::{pbr::CascadeShadowConfigBuilder, prelude::*};
+use bevy::{
+    input::mouse::{MouseMotion, MouseWheel},
+    pbr::CascadeShadowConfigBuilder,
+    prelude::*,
+    transform::TransformSystem,
+};
+
+use crate::{
+    asteroid::Asteroid,
+    console::{ConsoleAppExt, ConsoleCommand},
+    movement::Velocity,
+    network::{NetworkServer, Spectator},
+    schedule::InGameSet,
+    settings::{CameraMode, Settings},
+};
+
+use self::shake::{
+    react_to_hits, run_hit_stop, set_time_scale, shake_camera, CameraShake, HitStop,
+};
 
 const CAMERA_DISTANCE: f32 = 120.;
 
+const FOLLOW_DISTANCE: f32 = 70.;
+const MIN_DISTANCE: f32 = 30.;
+const MAX_DISTANCE: f32 = 220.;
+/// Roughly how long the camera takes to settle on a new goal.
+const SMOOTH_TIME: f32 = 0.35;
+/// How many seconds of flight ahead of the spaceships the camera looks.
+const LOOK_AHEAD_TIME: f32 = 0.6;
+const MAX_LOOK_AHEAD: f32 = 25.;
+/// Extra distance per unit of speed, nearby asteroid and spread between players.
+const SPEED_ZOOM: f32 = 1.5;
+const ASTEROID_ZOOM: f32 = 4.;
+const SPREAD_ZOOM: f32 = 1.6;
+const NEARBY_RADIUS: f32 = 45.;
+const FREE_PAN_SPEED: f32 = 0.0025;
+const FREE_ZOOM_STEP: f32 = 0.1;
+
+/// Looking straight down on the arena.
+pub fn overhead_transform() -> Transform {
+    looking_down_from(Vec3::new(0., CAMERA_DISTANCE, 0.))
+}
+
+fn looking_down_from(eye: Vec3) -> Transform {
+    Transform::from_translation(eye).looking_at(Vec3::new(eye.x, 0., eye.z), Vec3::Z)
+}
+
+/// What the follow camera keeps in view.
+#[derive(Component, Debug, Default)]
+pub struct CameraTarget;
+
+/// Moves the camera over the arena, always looking straight down. Its goal is
+/// the point above the arena it's heading for.
+#[derive(Component, Debug)]
+pub struct CameraController {
+    eye: Vec3,
+    goal: Vec3,
+    velocity: Vec3,
+}
+
+impl Default for CameraController {
+    fn default() -> Self {
+        let eye = overhead_transform().translation;
+
+        Self {
+            eye,
+            goal: eye,
+            velocity: Vec3::ZERO,
+        }
+    }
+}
+
+/// A critically damped spring: it settles on `target` in about `smooth_time`
+/// without overshooting, however the target moves.
+fn smooth_damp(
+    current: Vec3,
+    target: Vec3,
+    velocity: &mut Vec3,
+    smooth_time: f32,
+    delta: f32,
+) -> Vec3 {
+    let omega = 2. / smooth_time;
+    let x = omega * delta;
+    let decay = 1. / (1. + x + 0.48 * x * x + 0.235 * x * x * x);
+    let offset = current - target;
+    let change = (*velocity + omega * offset) * delta;
+
+    *velocity = (*velocity - omega * change) * decay;
+    target + (offset + change) * decay
+}
+
+fn follow_targets(
+    settings: Res<Settings>,
+    mut cameras: Query<&mut CameraController>,
+    targets: Query<(&Transform, Option<&Velocity>), With<CameraTarget>>,
+    asteroids: Query<&Transform, With<Asteroid>>,
+) {
+    let goal = match settings.camera.mode {
+        CameraMode::Free => return,
+        CameraMode::Fixed => overhead_transform().translation,
+        CameraMode::Follow if targets.is_empty() => overhead_transform().translation,
+        CameraMode::Follow => {
+            let count = targets.iter().count() as f32;
+            let center = targets
+                .iter()
+                .map(|(transform, _)| transform.translation)
+                .sum::<Vec3>()
+                / count;
+            let velocity = targets
+                .iter()
+                .filter_map(|(_, velocity)| velocity)
+                .map(|velocity| velocity.value)
+                .sum::<Vec3>()
+                / count;
+            let spread = targets
+                .iter()
+                .map(|(transform, _)| transform.translation.distance(center))
+                .fold(0., f32::max);
+            let nearby = asteroids
+                .iter()
+                .filter(|asteroid| asteroid.translation.distance(center) < NEARBY_RADIUS)
+                .count() as f32;
+
+            let look_ahead = (velocity * LOOK_AHEAD_TIME).clamp_length_max(MAX_LOOK_AHEAD);
+            let distance = FOLLOW_DISTANCE
+                + velocity.length() * SPEED_ZOOM
+                + nearby * ASTEROID_ZOOM
+                + spread * SPREAD_ZOOM;
+
+            let focus = center + look_ahead;
+            Vec3::new(focus.x, distance.clamp(MIN_DISTANCE, MAX_DISTANCE), focus.z)
+        }
+    };
+
+    for mut camera in cameras.iter_mut() {
+        camera.goal = goal;
+    }
+}
+
+fn free_camera(
+    settings: Res<Settings>,
+    mut cameras: Query<(&Transform, &mut CameraController)>,
+    mouse_buttons: Res<ButtonInput<MouseButton>>,
+    mut motion: EventReader<MouseMotion>,
+    mut wheel: EventReader<MouseWheel>,
+) {
+    let dragged: Vec2 = motion.read().map(|motion| motion.delta).sum();
+    let scrolled: f32 = wheel.read().map(|wheel| wheel.y).sum();
+
+    if settings.camera.mode != CameraMode::Free {
+        return;
+    }
+
+    for (transform, mut camera) in cameras.iter_mut() {
+        if mouse_buttons.pressed(MouseButton::Right) {
+            // Dragging pulls the arena along with the mouse.
+            let pan = transform.right() * -dragged.x + transform.up() * dragged.y;
+            let height = camera.goal.y;
+            camera.goal += pan * height * FREE_PAN_SPEED;
+        }
+
+        camera.goal.y =
+            (camera.goal.y * (1. - scrolled * FREE_ZOOM_STEP)).clamp(MIN_DISTANCE, MAX_DISTANCE);
+    }
+}
+
+fn move_camera(mut cameras: Query<(&mut Transform, &mut CameraController)>, time: Res<Time>) {
+    for (mut transform, mut camera) in cameras.iter_mut() {
+        let camera = &mut *camera;
+        camera.eye = smooth_damp(
+            camera.eye,
+            camera.goal,
+            &mut camera.velocity,
+            SMOOTH_TIME,
+            time.delta_seconds(),
+        );
+
+        *transform = looking_down_from(camera.eye);
+    }
+}
+
 pub struct CameraPlugin;
 
 impl Plugin for CameraPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Startup, spawn_camera);
+        app.init_resource::<HitStop>()
+            .add_console_command(ConsoleCommand::new(
+                "timescale",
+                "timescale <factor>",
+                "Speed the game up or slow it down",
+                set_time_scale,
+            ))
+            .add_systems(Startup, spawn_camera)
+            .add_systems(
+                Update,
+                apply_shadow_settings.run_if(resource_changed::<Settings>),
+            )
+            // Slowing a server down would stall the game for every client.
+            .add_systems(
+                Update,
+                (
+                    react_to_hits.after(InGameSet::CollisionDetection),
+                    run_hit_stop,
+                )
+                    .run_if(not(resource_exists::<NetworkServer>)),
+            )
+            // Spectators fly their own camera.
+            .add_systems(
+                PostUpdate,
+                (follow_targets, free_camera, move_camera, shake_camera)
+                    .chain()
+                    .before(TransformSystem::TransformPropagate)
+                    .run_if(not(resource_exists::<Spectator>)),
+            );
+    }
+}
+
+fn apply_shadow_settings(
+    settings: Res<Settings>,
+    mut point_lights: Query<&mut PointLight>,
+    mut directional_lights: Query<&mut DirectionalLight>,
+) {
+    for mut light in point_lights.iter_mut() {
+        light.shadows_enabled = settings.video.point_light_shadows;
+    }
+
+    for mut light in directional_lights.iter_mut() {
+        light.shadows_enabled = settings.video.directional_light_shadows;
     }
 }
 
 fn spawn_camera(mut commands: Commands) {
-    commands.spawn(Camera3dBundle {
-        transform: Transform::from_xyz(0.0, CAMERA_DISTANCE, 0.).looking_at(Vec3::ZERO, Vec3::Z),
-        ..default()
-    });
+    commands.spawn((
+        Camera3dBundle {
+            transform: overhead_transform(),
+            ..default()
+        },
+        CameraController::default(),
+        CameraShake::default(),
+    ));
     commands.spawn(PointLightBundle {
         // transform: Transform::from_xyz(5.0, 8.0, 2.0),
         transform: Transform::from_xyz(1.0, -1.0, 0.0).looking_at(Vec3::ZERO, Vec3::Z),
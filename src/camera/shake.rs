@@ -0,0 +1,172 @@
+use bevy::prelude::*;
+
+use crate::{
+    collision_detection::{HazardDestroyed, SpaceshipDamaged},
+    console::parse_argument,
+    hazards::MineDetonated,
+    settings::Settings,
+    spaceship::lives::SpaceshipDestroyed,
+};
+
+/// Trauma lost per second.
+const TRAUMA_DECAY: f32 = 1.2;
+/// How far the camera moves at full trauma, as a fraction of its height.
+const MAX_OFFSET: f32 = 0.04;
+const MAX_ROLL: f32 = 0.05;
+const NOISE_SPEED: f32 = 20.;
+
+const DAMAGE_TRAUMA: f32 = 0.3;
+const TRAUMA_PER_DAMAGE: f32 = 0.1;
+const DESTROYED_TRAUMA: f32 = 0.8;
+const TRAUMA_PER_HAZARD_RADIUS: f32 = 0.1;
+const MINE_TRAUMA: f32 = 0.2;
+/// Hazards at least this big stop the game for a moment when destroyed.
+const BIG_HAZARD_RADIUS: f32 = 1.5;
+
+const DAMAGE_HIT_STOP: f32 = 0.06;
+const DESTROYED_HIT_STOP: f32 = 0.15;
+const BIG_HAZARD_HIT_STOP: f32 = 0.04;
+/// How fast the game runs during a hit-stop.
+const HIT_STOP_SPEED: f32 = 0.05;
+
+/// Shakes the camera by the square of its trauma, so knocks barely register
+/// and big hits really land. Trauma goes from 0 to 1 and wears off over time.
+#[derive(Component, Debug, Default)]
+pub struct CameraShake {
+    trauma: f32,
+    time: f32,
+}
+
+impl CameraShake {
+    pub fn add_trauma(&mut self, amount: f32) {
+        self.trauma = (self.trauma + amount).clamp(0., 1.);
+    }
+}
+
+/// Slows the game right down for a moment on big hits.
+#[derive(Resource, Debug)]
+pub struct HitStop {
+    remaining: f32,
+    /// How fast the game runs the rest of the time, changed from the console.
+    time_scale: f32,
+}
+
+impl Default for HitStop {
+    fn default() -> Self {
+        Self {
+            remaining: 0.,
+            time_scale: 1.,
+        }
+    }
+}
+
+impl HitStop {
+    pub fn start(&mut self, seconds: f32) {
+        self.remaining = self.remaining.max(seconds);
+    }
+}
+
+pub fn set_time_scale(world: &mut World, arguments: &[&str]) -> Result<String, String> {
+    let time_scale: f32 = parse_argument(arguments)?;
+    if !time_scale.is_finite() || time_scale <= 0. {
+        return Err("The time scale has to be above 0".to_string());
+    }
+
+    world.resource_mut::<HitStop>().time_scale = time_scale;
+    world
+        .resource_mut::<Time<Virtual>>()
+        .set_relative_speed(time_scale);
+    Ok(format!("Time runs at {time_scale}x"))
+}
+
+/// Smooth noise in `-1.0..=1.0` from a few sine waves out of step with each other.
+fn noise(time: f32, seed: f32) -> f32 {
+    let waves = (time + seed * 12.9).sin()
+        + (time * 2.3 + seed * 78.2).sin() * 0.5
+        + (time * 4.7 + seed * 37.7).sin() * 0.25;
+
+    waves / 1.75
+}
+
+pub fn react_to_hits(
+    settings: Res<Settings>,
+    mut shakes: Query<&mut CameraShake>,
+    mut hit_stop: ResMut<HitStop>,
+    mut damaged: EventReader<SpaceshipDamaged>,
+    mut destroyed: EventReader<SpaceshipDestroyed>,
+    mut hazards: EventReader<HazardDestroyed>,
+    mut mines: EventReader<MineDetonated>,
+) {
+    let mut trauma = 0.;
+    let mut stop: f32 = 0.;
+
+    for hit in damaged.read() {
+        trauma += DAMAGE_TRAUMA + hit.damage as f32 * TRAUMA_PER_DAMAGE;
+        stop = stop.max(DAMAGE_HIT_STOP);
+    }
+
+    for _ in destroyed.read() {
+        trauma += DESTROYED_TRAUMA;
+        stop = stop.max(DESTROYED_HIT_STOP);
+    }
+
+    for hazard in hazards.read() {
+        trauma += hazard.radius * TRAUMA_PER_HAZARD_RADIUS;
+
+        if hazard.radius >= BIG_HAZARD_RADIUS {
+            stop = stop.max(BIG_HAZARD_HIT_STOP);
+        }
+    }
+
+    trauma += mines.read().count() as f32 * MINE_TRAUMA;
+
+    let camera = &settings.camera;
+    if camera.reduce_motion {
+        return;
+    }
+
+    for mut shake in shakes.iter_mut() {
+        shake.add_trauma(trauma * camera.shake_intensity);
+    }
+
+    if stop > 0. {
+        hit_stop.start(stop * camera.hit_stop_intensity);
+    }
+}
+
+/// Runs on real time, so shaking carries on through a hit-stop.
+pub fn shake_camera(mut cameras: Query<(&mut Transform, &mut CameraShake)>, time: Res<Time<Real>>) {
+    for (mut transform, mut shake) in cameras.iter_mut() {
+        shake.time += time.delta_seconds() * NOISE_SPEED;
+        shake.trauma = (shake.trauma - TRAUMA_DECAY * time.delta_seconds()).max(0.);
+
+        let amount = shake.trauma * shake.trauma;
+        if amount == 0. {
+            continue;
+        }
+
+        let offset = MAX_OFFSET * transform.translation.y * amount;
+        transform.translation.x += noise(shake.time, 0.) * offset;
+        transform.translation.z += noise(shake.time, 1.) * offset;
+        transform.rotate_local_z(noise(shake.time, 2.) * MAX_ROLL * amount);
+    }
+}
+
+pub fn run_hit_stop(
+    mut hit_stop: ResMut<HitStop>,
+    mut virtual_time: ResMut<Time<Virtual>>,
+    real_time: Res<Time<Real>>,
+) {
+    if hit_stop.remaining <= 0. {
+        return;
+    }
+
+    hit_stop.remaining -= real_time.delta_seconds();
+
+    let speed = if hit_stop.remaining > 0. {
+        HIT_STOP_SPEED * hit_stop.time_scale
+    } else {
+        hit_stop.time_scale
+    };
+    virtual_time.set_relative_speed(speed);
+}
@@ -1,125 +1,469 @@
-use bevy::{prelude::*, utils::HashMap};
+mod shape;
+
+use bevy::{prelude::*, render::primitives::Aabb, utils::HashMap};
 
 use crate::{
     application::AppState,
-    asteroid::Asteroid,
+    asteroid::{Asteroid, AsteroidSize},
+    difficulty::Difficulty,
+    player::{PlayerId, PlayerMode, PlayerSetup},
     schedule::InGameSet,
-    score::Score,
+    scoring::PointValue,
     spaceship::{
         health::Health,
-        shield::{ShieldDisplay, SpaceshipShield},
+        lives::{GodMode, Invulnerable},
+        shield::{remove_shield, ShieldDisplay, SpaceshipShield},
         Missile, Spaceship,
     },
+    stats::RunStats,
 };
 
+pub use self::shape::{ColliderShape, WorldShape};
+
 #[derive(Component, Debug)]
 pub struct Collider {
+    /// The bounding circle of `shape`, for quickly ruling out far apart pairs.
     pub radius: f32,
+    pub shape: ColliderShape,
     pub colliding_entities: Vec<Entity>,
 }
 
+/// Hurts spaceships that run into it, like asteroids and missiles do.
+#[derive(Component, Debug, Clone, Copy, Default)]
+pub struct Hazard {
+    /// Still there after the hit, like the hull of a boss, instead of
+    /// breaking up on the spaceship.
+    pub solid: bool,
+}
+
+impl Hazard {
+    pub const SOLID: Hazard = Hazard { solid: true };
+}
+
+/// Sent when a missile destroys something worth points.
+#[derive(Event, Debug)]
+pub struct HazardDestroyed {
+    pub position: Vec3,
+    pub radius: f32,
+    pub points: usize,
+    /// Whoever fired the missile.
+    pub player: Option<PlayerId>,
+}
+
+/// Sent when an unshielded spaceship takes a hit, before checking if it was
+/// destroyed.
+#[derive(Event, Debug)]
+pub struct SpaceshipDamaged {
+    pub spaceship: Entity,
+    pub damage: u32,
+}
+
 impl Collider {
+    /// A circle.
     pub fn new(radius: f32) -> Self {
+        Self::with_shape(ColliderShape::Circle { radius })
+    }
+
+    pub fn with_shape(shape: ColliderShape) -> Self {
         Self {
-            radius,
+            radius: shape.bounding_radius(),
+            shape,
             colliding_entities: vec![],
         }
     }
+
+    /// Sizes the shape to the model, scaled by `scale`. Capsules take the
+    /// model's width and length, and polygons become its outline.
+    fn fit(&mut self, bounds: &ModelBounds, scale: f32) {
+        match &mut self.shape {
+            ColliderShape::Circle { radius } => *radius = bounds.reach * scale,
+            ColliderShape::Capsule {
+                half_length,
+                radius,
+            } => {
+                *radius = bounds.half_extents.x * scale;
+                *half_length = (bounds.half_extents.y * scale - *radius).max(0.);
+            }
+            ColliderShape::ConvexPolygon { points } if bounds.hull.len() >= 3 => {
+                *points = bounds.hull.iter().map(|&point| point * scale).collect();
+            }
+            // Too flat to be a polygon, so keep the shape and just resize it.
+            ColliderShape::ConvexPolygon { .. } => {
+                let bounding_radius = self.shape.bounding_radius();
+                if bounding_radius > 0. {
+                    self.shape.scale(bounds.reach * scale / bounding_radius);
+                }
+            }
+        }
+
+        self.radius = self.shape.bounding_radius();
+    }
+
+    pub fn in_world(&self, transform: &GlobalTransform) -> WorldShape {
+        self.shape.in_world(transform)
+    }
 }
 
-fn collision_detection(mut query: Query<(Entity, &GlobalTransform, &mut Collider)>) {
-    let mut colliding_entities: HashMap<Entity, Vec<Entity>> = HashMap::new();
+/// Checked along the whole path it took since the last check, not just
+/// where it ended up, so it can't pass through something between frames
+/// however long they take.
+#[derive(Component, Debug, Default)]
+pub struct FastMover {
+    previous: Option<Vec2>,
+}
 
-    for (a, transform_a, collider_a) in query.iter() {
-        for (b, transform_b, collider_b) in query.iter() {
-            if transform_a == transform_b {
-                continue;
-            }
+/// A collider where it is this frame, stretched back along its path if it's
+/// a [`FastMover`].
+struct PlacedCollider {
+    entity: Entity,
+    center: Vec2,
+    /// The bounding circle around `center`.
+    reach: f32,
+    circle: bool,
+    shape: WorldShape,
+}
+
+impl PlacedCollider {
+    fn new(
+        entity: Entity,
+        transform: &GlobalTransform,
+        collider: &Collider,
+        fast_mover: Option<&FastMover>,
+    ) -> Self {
+        let position = transform.translation();
+        let position = Vec2::new(position.x, position.z);
+        let shape = collider.in_world(transform);
+        let moved = fast_mover
+            .and_then(|fast_mover| fast_mover.previous)
+            .map_or(Vec2::ZERO, |previous| position - previous);
+
+        if moved == Vec2::ZERO {
+            return Self {
+                entity,
+                center: position,
+                reach: collider.radius,
+                circle: matches!(collider.shape, ColliderShape::Circle { .. }),
+                shape,
+            };
+        }
+
+        Self {
+            entity,
+            center: position - moved / 2.,
+            reach: collider.radius + moved.length() / 2.,
+            circle: false,
+            shape: shape.swept(moved),
+        }
+    }
+
+    /// Two circles touch when their bounding circles do, anything else needs
+    /// the full test.
+    fn touches(&self, other: &PlacedCollider) -> bool {
+        self.center.distance(other.center) < self.reach + other.reach
+            && ((self.circle && other.circle) || self.shape.overlaps(&other.shape))
+    }
+}
+
+/// The outline of a model on the XZ plane, in the entity's own space.
+#[derive(Debug, Clone, Reflect)]
+struct ModelBounds {
+    reach: f32,
+    /// Half its width along X and length along Z.
+    half_extents: Vec2,
+    hull: Vec<Vec2>,
+}
+
+impl ModelBounds {
+    fn new(hull: Vec<Vec2>) -> Self {
+        let reach = hull.iter().map(|point| point.length()).fold(0., f32::max);
+        let half_extents = hull
+            .iter()
+            .fold(Vec2::ZERO, |extents, point| extents.max(point.abs()));
+
+        Self {
+            reach,
+            half_extents,
+            hull,
+        }
+    }
+}
+
+/// Resizes the [`Collider`] to fit its model once the scene has loaded, until
+/// then it keeps the size it was made with. `scale` shrinks or grows the
+/// fitted shape, e.g. so grazing a tapered wing tip isn't a hit.
+#[derive(Component, Debug, Clone, Reflect)]
+#[reflect(Component)]
+pub struct ColliderFit {
+    pub scale: f32,
+    bounds: Option<ModelBounds>,
+}
+
+impl ColliderFit {
+    pub fn new(scale: f32) -> Self {
+        Self {
+            scale,
+            bounds: None,
+        }
+    }
+}
 
-            let distance = transform_a
-                .translation()
-                .distance(transform_b.translation());
+type ModelMeshes<'w, 's> = Query<
+    'w,
+    's,
+    (
+        &'static Aabb,
+        &'static Handle<Mesh>,
+        &'static GlobalTransform,
+    ),
+    With<Handle<StandardMaterial>>,
+>;
 
-            if distance < collider_a.radius + collider_b.radius {
-                colliding_entities.entry(a).or_insert_with(Vec::new).push(b);
+type ExcludedFromBounds<'w, 's> = Query<'w, 's, (), Or<(With<ShieldDisplay>, With<Collider>)>>;
+
+/// The outline of the model's meshes seen from above, from their vertices
+/// or, if those aren't kept around, their bounding boxes. Shield bubbles and
+/// parts with colliders of their own aren't part of the model, and health
+/// bars aren't lit so don't have a [`StandardMaterial`].
+fn scene_bounds(
+    entity: Entity,
+    entity_transform: &GlobalTransform,
+    children: &Query<&Children>,
+    meshes: &ModelMeshes,
+    mesh_assets: &Assets<Mesh>,
+    excluded: &ExcludedFromBounds,
+) -> Option<ModelBounds> {
+    let mut points = Vec::new();
+
+    for child in children.iter_descendants(entity) {
+        if excluded.contains(child) {
+            continue;
+        }
+
+        let Ok((aabb, mesh, transform)) = meshes.get(child) else {
+            continue;
+        };
+
+        let vertices = mesh_assets
+            .get(mesh)
+            .and_then(|mesh| mesh.attribute(Mesh::ATTRIBUTE_POSITION))
+            .and_then(|positions| positions.as_float3());
+
+        let corners: Vec<Vec3> = match vertices {
+            Some(vertices) => vertices.iter().map(|&vertex| vertex.into()).collect(),
+            None => (0..8)
+                .map(|corner| {
+                    let sign = Vec3::new(
+                        if corner & 1 == 0 { -1. } else { 1. },
+                        if corner & 2 == 0 { -1. } else { 1. },
+                        if corner & 4 == 0 { -1. } else { 1. },
+                    );
+                    Vec3::from(aabb.center) + Vec3::from(aabb.half_extents) * sign
+                })
+                .collect(),
+        };
+
+        points.extend(
+            corners
+                .into_iter()
+                .map(|corner| shape::to_local(transform.transform_point(corner), entity_transform)),
+        );
+    }
+
+    let hull = shape::convex_hull(points);
+    (!hull.is_empty()).then(|| ModelBounds::new(hull))
+}
+
+fn fit_colliders(
+    mut fits: Query<(Entity, &GlobalTransform, &mut ColliderFit, &mut Collider)>,
+    children: Query<&Children>,
+    meshes: ModelMeshes,
+    mesh_assets: Res<Assets<Mesh>>,
+    excluded: ExcludedFromBounds,
+) {
+    for (entity, transform, mut fit, mut collider) in fits.iter_mut() {
+        if fit.bounds.is_none() {
+            fit.bounds = scene_bounds(
+                entity,
+                transform,
+                &children,
+                &meshes,
+                &mesh_assets,
+                &excluded,
+            );
+        }
+
+        // Also picks up the scale being tuned while the game runs.
+        if let Some(bounds) = fit.bounds.as_ref().filter(|_| fit.is_changed()) {
+            collider.fit(bounds, fit.scale);
+        }
+    }
+}
+
+type Colliders<'w, 's> = Query<
+    'w,
+    's,
+    (
+        Entity,
+        &'static GlobalTransform,
+        &'static mut Collider,
+        Option<&'static mut FastMover>,
+    ),
+>;
+
+fn collision_detection(mut query: Colliders) {
+    let placed: Vec<PlacedCollider> = query
+        .iter()
+        .map(|(entity, transform, collider, fast_mover)| {
+            PlacedCollider::new(entity, transform, collider, fast_mover)
+        })
+        .collect();
+
+    let mut colliding_entities: HashMap<Entity, Vec<Entity>> = HashMap::new();
+
+    for (index, a) in placed.iter().enumerate() {
+        for b in placed[index + 1..].iter() {
+            if a.touches(b) {
+                colliding_entities
+                    .entry(a.entity)
+                    .or_default()
+                    .push(b.entity);
+                colliding_entities
+                    .entry(b.entity)
+                    .or_default()
+                    .push(a.entity);
             }
         }
     }
 
-    for (entity, _, mut collider) in query.iter_mut() {
+    for (entity, transform, mut collider, fast_mover) in query.iter_mut() {
         collider.colliding_entities.clear();
 
         if let Some(collisions) = colliding_entities.get(&entity) {
             collider.colliding_entities.extend(collisions);
         }
+
+        if let Some(mut fast_mover) = fast_mover {
+            let position = transform.translation();
+            fast_mover.previous = Some(Vec2::new(position.x, position.z));
+        }
     }
 }
 
+/// Knocks out a spaceship's shield, or damages it if it doesn't have one.
+pub fn hurt_spaceship(
+    commands: &mut Commands,
+    spaceship: Entity,
+    health: &mut Health,
+    has_shield: bool,
+    damage: u32,
+    shield_displays: &Query<(Entity, &Parent), With<ShieldDisplay>>,
+    damaged: &mut EventWriter<SpaceshipDamaged>,
+) {
+    if has_shield {
+        info!("Despawning shield");
+        remove_shield(commands, spaceship, shield_displays);
+        return;
+    }
+
+    *health -= damage;
+    damaged.send(SpaceshipDamaged { spaceship, damage });
+}
+
 fn handle_spaceship_collision(
     mut commands: Commands,
     mut spaceship_query: Query<
-        (Entity, &mut Health, &Collider, Option<&SpaceshipShield>),
-        With<Spaceship>,
+        (
+            Entity,
+            &PlayerId,
+            &mut Health,
+            &Collider,
+            Has<SpaceshipShield>,
+        ),
+        (With<Spaceship>, Without<Invulnerable>, Without<GodMode>),
+    >,
+    mut damaged: EventWriter<SpaceshipDamaged>,
+    shield_displays: Query<(Entity, &Parent), With<ShieldDisplay>>,
+    hazards: Query<
+        (Entity, Option<&PlayerId>, Option<&Hazard>),
+        Or<(With<Asteroid>, With<Missile>, With<Hazard>)>,
     >,
-    mut app_state: ResMut<NextState<AppState>>,
-    spaceship_shield_query: Query<Entity, With<ShieldDisplay>>,
-    asteroids: Query<Entity, With<Asteroid>>,
+    difficulty: Res<Difficulty>,
+    players: Res<PlayerSetup>,
 ) {
-    let Ok((spaceship_entity, mut spaceship_health, spaceship_collider, maybe_shield)) =
-        spaceship_query.get_single_mut()
-    else {
-        return;
-    };
-
-    for &collided_entity in spaceship_collider.colliding_entities.iter() {
-        let Ok(asteroid) = asteroids.get(collided_entity) else {
-            continue;
-        };
-
-        commands.entity(asteroid).despawn_recursive();
-
-        if maybe_shield.is_some() {
-            info!("Despawning shield");
-
-            commands
-                .entity(spaceship_entity)
-                .remove::<SpaceshipShield>();
-
-            let Ok(shield) = spaceship_shield_query.get_single() else {
-                error!("No shield found");
-                break;
+    for (spaceship_entity, &player, mut spaceship_health, spaceship_collider, has_shield) in
+        spaceship_query.iter_mut()
+    {
+        for &collided_entity in spaceship_collider.colliding_entities.iter() {
+            let Ok((hazard, owner, kind)) = hazards.get(collided_entity) else {
+                continue;
             };
 
-            commands.entity(shield).despawn_recursive();
-        } else {
-            *spaceship_health -= 1;
+            // Missiles only hurt other players, and only in versus.
+            if let Some(&owner) = owner {
+                if players.mode != PlayerMode::Versus || owner == player {
+                    continue;
+                }
+            }
 
-            if *spaceship_health < 1 {
-                warn!("Time to die!");
-                app_state.set(AppState::EndGame);
+            // Solid hazards stay put, so the spaceship gets a moment to move
+            // off them rather than being hit again every frame.
+            if kind.is_some_and(|kind| kind.solid) {
+                commands
+                    .entity(spaceship_entity)
+                    .insert(Invulnerable::after_hit());
+            } else {
+                commands.entity(hazard).despawn_recursive();
             }
-        }
 
-        break;
+            hurt_spaceship(
+                &mut commands,
+                spaceship_entity,
+                &mut spaceship_health,
+                has_shield,
+                difficulty.preset().damage,
+                &shield_displays,
+                &mut damaged,
+            );
+
+            break;
+        }
     }
 }
 
 fn handle_asteroid_collision(
     mut commands: Commands,
-    query: Query<(Entity, &Collider), With<Asteroid>>,
-    missiles: Query<Entity, With<Missile>>,
-    mut score: ResMut<Score>,
+    query: Query<
+        (
+            Entity,
+            &GlobalTransform,
+            &Collider,
+            &PointValue,
+            &AsteroidSize,
+        ),
+        With<Asteroid>,
+    >,
+    missiles: Query<(Entity, Option<&PlayerId>), With<Missile>>,
+    mut destroyed: EventWriter<HazardDestroyed>,
+    mut stats: ResMut<RunStats>,
 ) {
-    for (asteroid_entity, asteroid_collider) in query.iter() {
+    for (asteroid_entity, asteroid_transform, asteroid_collider, points, &size) in query.iter() {
         for &colliding_entity in asteroid_collider.colliding_entities.iter() {
-            let Ok(missile_entity) = missiles.get(colliding_entity) else {
+            let Ok((missile_entity, player)) = missiles.get(colliding_entity) else {
                 continue;
             };
 
             commands.entity(missile_entity).despawn_recursive();
             commands.entity(asteroid_entity).despawn_recursive();
 
-            score.score(1);
+            destroyed.send(HazardDestroyed {
+                position: asteroid_transform.translation(),
+                radius: asteroid_collider.radius,
+                points: points.0,
+                player: player.copied(),
+            });
+            stats.asteroid_destroyed(size);
+
+            break;
         }
     }
 }
@@ -128,17 +472,21 @@ pub struct CollisionDetectionPlugin;
 
 impl Plugin for CollisionDetectionPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(
-            Update,
-            collision_detection
-                .in_set(InGameSet::CollisionDetection)
-                .run_if(in_state(AppState::InGame)),
-        )
-        .add_systems(
-            Update,
-            (handle_spaceship_collision, handle_asteroid_collision)
-                .in_set(InGameSet::DespawnEntities)
-                .run_if(in_state(AppState::InGame)),
-        );
+        app.register_type::<ColliderFit>()
+            .add_event::<HazardDestroyed>()
+            .add_event::<SpaceshipDamaged>()
+            .add_systems(
+                Update,
+                (fit_colliders, collision_detection)
+                    .chain()
+                    .in_set(InGameSet::CollisionDetection)
+                    .run_if(in_state(AppState::InGame)),
+            )
+            .add_systems(
+                Update,
+                (handle_spaceship_collision, handle_asteroid_collision)
+                    .in_set(InGameSet::DespawnEntities)
+                    .run_if(in_state(AppState::InGame)),
+            );
     }
 }
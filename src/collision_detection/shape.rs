@@ -0,0 +1,221 @@
+use bevy::prelude::*;
+use std::f32::consts::TAU;
+
+/// The outline of a [`Collider`](super::Collider) on the XZ plane, relative
+/// to its entity and turning as it heads round. Points are `(x, z)`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ColliderShape {
+    Circle {
+        radius: f32,
+    },
+    /// A rounded line along the entity's Z axis, for long things.
+    Capsule {
+        half_length: f32,
+        radius: f32,
+    },
+    /// Has to be convex, wound either way.
+    ConvexPolygon {
+        points: Vec<Vec2>,
+    },
+}
+
+impl ColliderShape {
+    pub fn regular_polygon(sides: usize, radius: f32) -> Self {
+        let points = (0..sides)
+            .map(|side| Vec2::from_angle(TAU * side as f32 / sides as f32) * radius)
+            .collect();
+
+        ColliderShape::ConvexPolygon { points }
+    }
+
+    /// The smallest circle around the entity that holds the whole shape.
+    pub fn bounding_radius(&self) -> f32 {
+        match self {
+            ColliderShape::Circle { radius } => *radius,
+            ColliderShape::Capsule {
+                half_length,
+                radius,
+            } => half_length + radius,
+            ColliderShape::ConvexPolygon { points } => {
+                points.iter().map(|point| point.length()).fold(0., f32::max)
+            }
+        }
+    }
+
+    /// Grows or shrinks the whole shape evenly.
+    pub fn scale(&mut self, by: f32) {
+        match self {
+            ColliderShape::Circle { radius } => *radius *= by,
+            ColliderShape::Capsule {
+                half_length,
+                radius,
+            } => {
+                *half_length *= by;
+                *radius *= by;
+            }
+            ColliderShape::ConvexPolygon { points } => {
+                for point in points.iter_mut() {
+                    *point *= by;
+                }
+            }
+        }
+    }
+
+    pub fn in_world(&self, transform: &GlobalTransform) -> WorldShape {
+        let (heading, center) = heading(transform);
+        let right = Vec2::new(heading.y, -heading.x);
+        let place = |point: Vec2| center + right * point.x + heading * point.y;
+
+        match self {
+            ColliderShape::Circle { radius } => WorldShape {
+                points: vec![place(Vec2::ZERO)],
+                radius: *radius,
+            },
+            ColliderShape::Capsule {
+                half_length,
+                radius,
+            } => WorldShape {
+                points: vec![
+                    place(Vec2::new(0., -half_length)),
+                    place(Vec2::new(0., *half_length)),
+                ],
+                radius: *radius,
+            },
+            ColliderShape::ConvexPolygon { points } => WorldShape {
+                points: points.iter().copied().map(place).collect(),
+                radius: 0.,
+            },
+        }
+    }
+}
+
+/// Where the entity's Z axis points on the XZ plane, and where it is. Only
+/// turning about Y counts, so asteroids tumbling about their Z axis keep
+/// the same outline.
+pub fn heading(transform: &GlobalTransform) -> (Vec2, Vec2) {
+    let (_, rotation, translation) = transform.to_scale_rotation_translation();
+    let forward = rotation * Vec3::Z;
+    let heading = Vec2::new(forward.x, forward.z)
+        .try_normalize()
+        .unwrap_or(Vec2::Y);
+
+    (heading, Vec2::new(translation.x, translation.z))
+}
+
+/// Turns a point on the XZ plane into the space [`ColliderShape`]s are in.
+pub fn to_local(point: Vec3, transform: &GlobalTransform) -> Vec2 {
+    let (heading, center) = heading(transform);
+    let offset = Vec2::new(point.x, point.z) - center;
+
+    Vec2::new(
+        offset.dot(Vec2::new(heading.y, -heading.x)),
+        offset.dot(heading),
+    )
+}
+
+/// The corners of the smallest convex polygon around `points`, going
+/// anticlockwise.
+pub fn convex_hull(mut points: Vec<Vec2>) -> Vec<Vec2> {
+    points.sort_by(|a, b| a.x.total_cmp(&b.x).then(a.y.total_cmp(&b.y)));
+    points.dedup();
+
+    if points.len() < 3 {
+        return points;
+    }
+
+    // Andrew's monotone chain: the lower half, then the upper half, each
+    // dropping corners that turn the wrong way.
+    let mut hull: Vec<Vec2> = Vec::with_capacity(points.len() * 2);
+    for pass in 0..2 {
+        let start = hull.len();
+        for &point in points.iter() {
+            while hull.len() >= start + 2 {
+                let [a, b] = [hull[hull.len() - 2], hull[hull.len() - 1]];
+                if (b - a).perp_dot(point - a) > 0. {
+                    break;
+                }
+                hull.pop();
+            }
+            hull.push(point);
+        }
+        hull.pop();
+
+        if pass == 0 {
+            points.reverse();
+        }
+    }
+
+    hull
+}
+
+/// A shape placed in the arena, as a convex core of one or more `(x, z)`
+/// points rounded off by `radius`.
+#[derive(Debug, Clone)]
+pub struct WorldShape {
+    pub points: Vec<Vec2>,
+    pub radius: f32,
+}
+
+impl WorldShape {
+    /// Normals of the core's edges. A single point has none, and a line's
+    /// one edge is enough.
+    fn edge_normals(&self) -> impl Iterator<Item = Vec2> + '_ {
+        let edges = match self.points.len() {
+            0 | 1 => 0,
+            2 => 1,
+            count => count,
+        };
+
+        (0..edges).map(|index| {
+            let edge = self.points[(index + 1) % self.points.len()] - self.points[index];
+            edge.perp()
+        })
+    }
+
+    fn project(&self, axis: Vec2) -> (f32, f32) {
+        let (min, max) = self.points.iter().map(|point| point.dot(axis)).fold(
+            (f32::INFINITY, f32::NEG_INFINITY),
+            |(min, max), distance| (min.min(distance), max.max(distance)),
+        );
+
+        (min - self.radius, max + self.radius)
+    }
+
+    /// Everywhere the shape touched moving `offset` to get here.
+    pub fn swept(&self, offset: Vec2) -> WorldShape {
+        let points = self
+            .points
+            .iter()
+            .flat_map(|&point| [point - offset, point])
+            .collect();
+
+        WorldShape {
+            points: convex_hull(points),
+            radius: self.radius,
+        }
+    }
+
+    /// Separating axis test. Two convex shapes miss each other exactly when
+    /// some edge normal, or with rounded shapes some line between a pair of
+    /// their points, has a gap between them.
+    pub fn overlaps(&self, other: &WorldShape) -> bool {
+        let rounded = self.radius > 0. || other.radius > 0.;
+        let between_points = self
+            .points
+            .iter()
+            .flat_map(|&a| other.points.iter().map(move |&b| b - a))
+            .filter(|_| rounded);
+
+        let mut axes = self
+            .edge_normals()
+            .chain(other.edge_normals())
+            .chain(between_points)
+            .filter_map(|axis| axis.try_normalize());
+
+        !axes.any(|axis| {
+            let (min_a, max_a) = self.project(axis);
+            let (min_b, max_b) = other.project(axis);
+            max_a < min_b || max_b < min_a
+        })
+    }
+}
@@ -0,0 +1,334 @@
+use bevy::prelude::*;
+use bevy_inspector_egui::{
+    bevy_egui::EguiContexts,
+    egui::{self, Color32, Key, Modifiers, RichText},
+};
+
+const TOGGLE_CONSOLE: KeyCode = KeyCode::Backquote;
+const TOGGLE_CONSOLE_CHARACTER: char = '`';
+const MAX_LOG_LINES: usize = 200;
+const MAX_HISTORY: usize = 50;
+
+/// Runs a command with whatever followed its name, returning what to print.
+pub type CommandHandler = fn(&mut World, &[&str]) -> Result<String, String>;
+
+#[derive(Debug, Clone)]
+pub struct ConsoleCommand {
+    /// One or more words, e.g. `set health`.
+    pub name: &'static str,
+    pub usage: &'static str,
+    pub help: &'static str,
+    /// Values the first argument can take, offered by autocomplete.
+    pub arguments: &'static [&'static str],
+    handler: CommandHandler,
+}
+
+impl ConsoleCommand {
+    pub fn new(
+        name: &'static str,
+        usage: &'static str,
+        help: &'static str,
+        handler: CommandHandler,
+    ) -> Self {
+        Self {
+            name,
+            usage,
+            help,
+            arguments: &[],
+            handler,
+        }
+    }
+
+    pub fn with_arguments(mut self, arguments: &'static [&'static str]) -> Self {
+        self.arguments = arguments;
+        self
+    }
+}
+
+/// Every command plugins have registered, whether or not the console is
+/// there to run them.
+#[derive(Resource, Debug, Default)]
+pub struct ConsoleCommands {
+    commands: Vec<ConsoleCommand>,
+}
+
+impl ConsoleCommands {
+    /// The command with the longest name that starts the line, and the
+    /// arguments after it.
+    fn find<'a>(&self, line: &'a str) -> Option<(&ConsoleCommand, Vec<&'a str>)> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+
+        self.commands
+            .iter()
+            .filter_map(|command| {
+                let name: Vec<&str> = command.name.split_whitespace().collect();
+                words
+                    .starts_with(&name)
+                    .then(|| (command, words[name.len()..].to_vec()))
+            })
+            .max_by_key(|(command, _)| command.name.len())
+    }
+
+    /// Everything the line could be completed to, sorted.
+    fn completions(&self, line: &str) -> Vec<String> {
+        let mut completions: Vec<String> = self
+            .commands
+            .iter()
+            .flat_map(|command| {
+                std::iter::once(command.name.to_string()).chain(
+                    command
+                        .arguments
+                        .iter()
+                        .map(move |argument| format!("{} {argument}", command.name)),
+                )
+            })
+            .filter(|completion| completion.starts_with(line) && completion != line)
+            .collect();
+
+        completions.sort();
+        completions
+    }
+}
+
+pub trait ConsoleAppExt {
+    fn add_console_command(&mut self, command: ConsoleCommand) -> &mut Self;
+}
+
+impl ConsoleAppExt for App {
+    fn add_console_command(&mut self, command: ConsoleCommand) -> &mut Self {
+        self.world
+            .get_resource_or_insert_with(ConsoleCommands::default)
+            .commands
+            .push(command);
+        self
+    }
+}
+
+/// Parses a command's only argument.
+pub fn parse_argument<T: std::str::FromStr>(arguments: &[&str]) -> Result<T, String> {
+    match arguments {
+        [argument] => argument
+            .parse()
+            .map_err(|_| format!("Couldn't understand {argument}")),
+        _ => Err("Expected one argument".to_string()),
+    }
+}
+
+#[derive(Resource, Debug, Default)]
+pub struct Console {
+    open: bool,
+    input: String,
+    log: Vec<(String, bool)>,
+    history: Vec<String>,
+    /// How far back through the history the up arrow has gone.
+    browsing: Option<usize>,
+    submitted: Vec<String>,
+}
+
+impl Console {
+    fn print(&mut self, line: String, error: bool) {
+        self.log.push((line, error));
+
+        let excess = self.log.len().saturating_sub(MAX_LOG_LINES);
+        self.log.drain(..excess);
+    }
+
+    fn browse(&mut self, older: bool) {
+        let newest = self.history.len().checked_sub(1);
+        self.browsing = match (self.browsing, older) {
+            (None, true) => newest,
+            (None, false) => None,
+            (Some(index), true) => Some(index.saturating_sub(1)),
+            (Some(index), false) => Some(index + 1).filter(|&index| index < self.history.len()),
+        };
+
+        self.input = self
+            .browsing
+            .map(|index| self.history[index].clone())
+            .unwrap_or_default();
+    }
+}
+
+/// Whether gameplay should listen to the keyboard, which it shouldn't while a
+/// command is being typed. Always true in builds without the console.
+pub fn console_closed(console: Option<Res<Console>>) -> bool {
+    !console.is_some_and(|console| console.open)
+}
+
+fn toggle_console(mut console: ResMut<Console>, keyboard_input: Res<ButtonInput<KeyCode>>) {
+    if keyboard_input.just_pressed(TOGGLE_CONSOLE) {
+        console.open = !console.open;
+    }
+}
+
+fn draw_console(
+    mut contexts: EguiContexts,
+    mut console: ResMut<Console>,
+    commands: Res<ConsoleCommands>,
+) {
+    let Some(ctx) = contexts.try_ctx_mut() else {
+        return;
+    };
+
+    if !console.open {
+        return;
+    }
+
+    let (tab, up, down) = ctx.input_mut(|input| {
+        (
+            input.consume_key(Modifiers::NONE, Key::Tab),
+            input.consume_key(Modifiers::NONE, Key::ArrowUp),
+            input.consume_key(Modifiers::NONE, Key::ArrowDown),
+        )
+    });
+
+    if up || down {
+        console.browse(up);
+    }
+
+    let completions = commands.completions(console.input.trim_start());
+
+    // Tab fills in as much as all the completions agree on.
+    if tab {
+        if let Some(first) = completions.first() {
+            let shared = completions.iter().fold(first.len(), |shared, completion| {
+                first
+                    .chars()
+                    .zip(completion.chars())
+                    .take(shared)
+                    .take_while(|(a, b)| a == b)
+                    .count()
+            });
+            console.input = first[..shared].to_string();
+            if completions.len() == 1 {
+                console.input.push(' ');
+            }
+        }
+    }
+
+    egui::TopBottomPanel::top("Console").show(ctx, |ui| {
+        egui::ScrollArea::vertical()
+            .max_height(200.)
+            .stick_to_bottom(true)
+            .show(ui, |ui| {
+                for (line, error) in console.log.iter() {
+                    let color = if *error {
+                        Color32::LIGHT_RED
+                    } else {
+                        Color32::LIGHT_GRAY
+                    };
+                    ui.label(RichText::new(line).color(color).monospace());
+                }
+            });
+
+        let input = ui.add(
+            egui::TextEdit::singleline(&mut console.input)
+                .desired_width(f32::INFINITY)
+                .font(egui::TextStyle::Monospace)
+                .lock_focus(true),
+        );
+        input.request_focus();
+
+        if input.lost_focus() && ui.input(|input| input.key_pressed(Key::Enter)) {
+            let line = std::mem::take(&mut console.input)
+                .trim()
+                .replace(TOGGLE_CONSOLE_CHARACTER, "");
+            console.browsing = None;
+
+            if !line.is_empty() {
+                console.history.push(line.clone());
+                let excess = console.history.len().saturating_sub(MAX_HISTORY);
+                console.history.drain(..excess);
+                console.submitted.push(line);
+            }
+        }
+
+        if !completions.is_empty() {
+            ui.label(RichText::new(completions.join("   ")).weak().monospace());
+        }
+    });
+
+    // The key that opened the console shouldn't end up typed into it.
+    console.input = console.input.replace(TOGGLE_CONSOLE_CHARACTER, "");
+}
+
+fn run_submitted_commands(world: &mut World) {
+    let submitted = std::mem::take(&mut world.resource_mut::<Console>().submitted);
+
+    for line in submitted {
+        world
+            .resource_mut::<Console>()
+            .print(format!("> {line}"), false);
+
+        let found = world
+            .resource::<ConsoleCommands>()
+            .find(&line)
+            .map(|(command, arguments)| {
+                (
+                    command.handler,
+                    command.usage,
+                    arguments
+                        .into_iter()
+                        .map(str::to_string)
+                        .collect::<Vec<_>>(),
+                )
+            });
+
+        let result = match found {
+            Some((handler, usage, arguments)) => {
+                let arguments: Vec<&str> = arguments.iter().map(String::as_str).collect();
+                handler(world, &arguments).map_err(|error| format!("{error}\nUsage: {usage}"))
+            }
+            None => Err(format!("Unknown command: {line}. Try `help`")),
+        };
+
+        let mut console = world.resource_mut::<Console>();
+        match result {
+            Ok(output) if output.is_empty() => {}
+            Ok(output) => console.print(output, false),
+            Err(error) => console.print(error, true),
+        }
+    }
+}
+
+fn help(world: &mut World, _arguments: &[&str]) -> Result<String, String> {
+    let mut commands: Vec<String> = world
+        .resource::<ConsoleCommands>()
+        .commands
+        .iter()
+        .map(|command| format!("{:<24} {}", command.usage, command.help))
+        .collect();
+
+    commands.sort();
+    Ok(commands.join("\n"))
+}
+
+fn clear(world: &mut World, _arguments: &[&str]) -> Result<String, String> {
+    world.resource_mut::<Console>().log.clear();
+    Ok(String::new())
+}
+
+pub struct ConsolePlugin;
+
+impl Plugin for ConsolePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Console>()
+            .init_resource::<ConsoleCommands>()
+            .add_console_command(ConsoleCommand::new(
+                "help",
+                "help",
+                "List every command",
+                help,
+            ))
+            .add_console_command(ConsoleCommand::new(
+                "clear",
+                "clear",
+                "Clear the console",
+                clear,
+            ))
+            .add_systems(
+                Update,
+                (toggle_console, draw_console, run_submitted_commands).chain(),
+            );
+    }
+}
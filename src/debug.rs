@@ -1,10 +1,20 @@
+mod colliders;
+mod perf;
+
 use bevy::prelude::*;
 use bevy_inspector_egui::quick::WorldInspectorPlugin;
 
+use crate::console::ConsolePlugin;
+
+use self::{colliders::ColliderGizmosPlugin, perf::PerfPlugin};
+
 pub struct DebugPlugin;
 
 impl Plugin for DebugPlugin {
     fn build(&self, app: &mut App) {
-        app.add_plugins(WorldInspectorPlugin::new());
+        app.add_plugins(WorldInspectorPlugin::new())
+            .add_plugins(ConsolePlugin)
+            .add_plugins(PerfPlugin)
+            .add_plugins(ColliderGizmosPlugin);
     }
 }
@@ -0,0 +1,163 @@
+use bevy::prelude::*;
+
+use crate::{
+    asteroid::Asteroid,
+    collision_detection::{Collider, WorldShape},
+    console::{ConsoleAppExt, ConsoleCommand},
+    despawn::DESPAWN_DISTANCE,
+    movement::Velocity,
+    spaceship::{
+        shield::{SpaceshipShield, SHIELD_RADIUS},
+        Missile, Spaceship,
+    },
+};
+
+const TOGGLE_GIZMOS: KeyCode = KeyCode::F4;
+/// Velocity arrows show where something will be this many seconds from now.
+const VELOCITY_ARROW_TIME: f32 = 0.5;
+const CIRCLE_SEGMENTS: usize = 48;
+
+/// Draws colliders, velocities and the despawn boundary.
+#[derive(Resource, Debug, Default)]
+struct ColliderGizmos {
+    visible: bool,
+}
+
+fn layer_color(spaceship: bool, missile: bool, asteroid: bool) -> Color {
+    match (spaceship, missile, asteroid) {
+        (true, ..) => Color::GREEN,
+        (_, true, _) => Color::YELLOW,
+        (.., true) => Color::ORANGE,
+        _ => Color::WHITE,
+    }
+}
+
+/// The outline of a shape, grown by `margin`. Rounded shapes are drawn as a
+/// circle at each corner joined by their outer edges.
+fn draw_shape(gizmos: &mut Gizmos, shape: &WorldShape, height: f32, margin: f32, color: Color) {
+    let radius = shape.radius + margin;
+    let point = |point: Vec2| Vec3::new(point.x, height, point.y);
+
+    if shape.points.len() == 1 || radius > 0. {
+        for &corner in shape.points.iter() {
+            gizmos
+                .circle(point(corner), Direction3d::Y, radius, color)
+                .segments(CIRCLE_SEGMENTS);
+        }
+    }
+
+    if shape.points.len() < 2 {
+        return;
+    }
+
+    let mut line = |start: Vec2, end: Vec2, offset: Vec2| {
+        gizmos.line(point(start + offset), point(end + offset), color);
+    };
+
+    // A capsule's core is a single edge, with a side either way.
+    if let [start, end] = shape.points[..] {
+        let offset = (end - start).perp().normalize_or_zero() * radius;
+        line(start, end, offset);
+        line(start, end, -offset);
+        return;
+    }
+
+    // Polygons can be wound either way, so push edges out from the middle.
+    let middle = shape.points.iter().copied().sum::<Vec2>() / shape.points.len() as f32;
+    for (index, &start) in shape.points.iter().enumerate() {
+        let end = shape.points[(index + 1) % shape.points.len()];
+        let mut normal = (end - start).perp().normalize_or_zero();
+        if normal.dot(start - middle) < 0. {
+            normal = -normal;
+        }
+
+        line(start, end, normal * radius);
+    }
+}
+
+fn toggle_gizmos(mut gizmos: ResMut<ColliderGizmos>, keyboard_input: Res<ButtonInput<KeyCode>>) {
+    if keyboard_input.just_pressed(TOGGLE_GIZMOS) {
+        gizmos.visible = !gizmos.visible;
+    }
+}
+
+fn toggle_gizmos_command(world: &mut World, _arguments: &[&str]) -> Result<String, String> {
+    let mut gizmos = world.resource_mut::<ColliderGizmos>();
+    gizmos.visible = !gizmos.visible;
+
+    Ok(format!(
+        "Collider gizmos {}",
+        if gizmos.visible { "on" } else { "off" }
+    ))
+}
+
+fn draw_colliders(
+    mut gizmos: Gizmos,
+    colliders: Query<(
+        &GlobalTransform,
+        &Collider,
+        Option<&Velocity>,
+        Has<Spaceship>,
+        Has<Missile>,
+        Has<Asteroid>,
+        Has<SpaceshipShield>,
+    )>,
+) {
+    for (transform, collider, velocity, spaceship, missile, asteroid, shield) in colliders.iter() {
+        let position = transform.translation();
+        let shape = collider.in_world(transform);
+
+        // Anything touching something else this frame is drawn in red, with
+        // a second outline so it stands out.
+        let color = if collider.colliding_entities.is_empty() {
+            layer_color(spaceship, missile, asteroid)
+        } else {
+            let margin = collider.radius * 0.1;
+            draw_shape(&mut gizmos, &shape, position.y, margin, Color::RED);
+            Color::RED
+        };
+
+        draw_shape(&mut gizmos, &shape, position.y, 0., color);
+
+        if shield {
+            gizmos
+                .circle(position, Direction3d::Y, SHIELD_RADIUS, Color::BLUE)
+                .segments(CIRCLE_SEGMENTS);
+        }
+
+        if let Some(velocity) = velocity.filter(|velocity| velocity.value != Vec3::ZERO) {
+            gizmos.arrow(
+                position,
+                position + velocity.value * VELOCITY_ARROW_TIME,
+                Color::CYAN,
+            );
+        }
+    }
+
+    gizmos
+        .circle(Vec3::ZERO, Direction3d::Y, DESPAWN_DISTANCE, Color::GRAY)
+        .segments(CIRCLE_SEGMENTS * 2);
+}
+
+/// Colliders on the XZ plane, coloured by what they belong to, shown with F4.
+pub struct ColliderGizmosPlugin;
+
+impl Plugin for ColliderGizmosPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ColliderGizmos>()
+            .add_console_command(ConsoleCommand::new(
+                "colliders",
+                "colliders",
+                "Toggle collider gizmos",
+                toggle_gizmos_command,
+            ))
+            .add_systems(
+                Update,
+                (
+                    toggle_gizmos,
+                    draw_colliders.run_if(|gizmos: Res<ColliderGizmos>| gizmos.visible),
+                )
+                    .chain(),
+            );
+    }
+}
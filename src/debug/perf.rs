@@ -0,0 +1,346 @@
+use bevy::{
+    app::AppExit,
+    diagnostic::{
+        Diagnostic, DiagnosticPath, Diagnostics, DiagnosticsStore, EntityCountDiagnosticsPlugin,
+        FrameTimeDiagnosticsPlugin, RegisterDiagnostic,
+    },
+    prelude::*,
+    utils::{HashMap, Instant},
+};
+use bevy_inspector_egui::{
+    bevy_egui::EguiContexts,
+    egui::{self, Color32, Pos2, Sense, Stroke, Vec2},
+};
+use std::{collections::VecDeque, fmt::Write, time::SystemTime};
+
+use crate::{collision_detection::Collider, persistence, schedule::InGameSet};
+
+const TOGGLE_HUD: KeyCode = KeyCode::F3;
+/// Seconds between rows of the exported session log.
+const SAMPLE_INTERVAL: f64 = 1.0;
+const FRAME_TIME_HISTORY: usize = 240;
+/// The top of the frame time graph, in milliseconds; two 60Hz frames.
+const GRAPH_MAX_FRAME_TIME: f32 = 1000. / 30.;
+
+const COLLIDER_COUNT: DiagnosticPath = DiagnosticPath::const_new("colliders");
+
+/// Where a timed set runs. Fixed-step sets can run any number of times a
+/// frame, each run measured on its own.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum TimedSchedule {
+    Update,
+    FixedUpdate,
+}
+
+/// Each set in the order it runs in, and where its timing is recorded.
+const SET_TIMINGS: [(TimedSchedule, InGameSet, DiagnosticPath); 6] = [
+    (
+        TimedSchedule::Update,
+        InGameSet::DespawnEntities,
+        DiagnosticPath::const_new("in_game_set/despawn_entities"),
+    ),
+    (
+        TimedSchedule::Update,
+        InGameSet::UserInput,
+        DiagnosticPath::const_new("in_game_set/user_input"),
+    ),
+    (
+        TimedSchedule::Update,
+        InGameSet::EntityUpdates,
+        DiagnosticPath::const_new("in_game_set/entity_updates"),
+    ),
+    (
+        TimedSchedule::Update,
+        InGameSet::CollisionDetection,
+        DiagnosticPath::const_new("in_game_set/collision_detection"),
+    ),
+    (
+        TimedSchedule::FixedUpdate,
+        InGameSet::UserInput,
+        DiagnosticPath::const_new("in_game_set/fixed_user_input"),
+    ),
+    (
+        TimedSchedule::FixedUpdate,
+        InGameSet::EntityUpdates,
+        DiagnosticPath::const_new("in_game_set/fixed_entity_updates"),
+    ),
+];
+
+fn set_label(schedule: TimedSchedule, set: &InGameSet) -> String {
+    match schedule {
+        TimedSchedule::Update => format!("{set:?}"),
+        TimedSchedule::FixedUpdate => format!("Fixed{set:?}"),
+    }
+}
+
+/// When each set last started. Other systems can run in parallel, so these
+/// are wall-clock times for the set, not its own CPU time.
+#[derive(Resource, Debug, Default)]
+struct SetTimers {
+    started: HashMap<(TimedSchedule, InGameSet), Instant>,
+}
+
+#[derive(Debug, Clone)]
+struct PerfSample {
+    time: f64,
+    fps: f64,
+    frame_time: f64,
+    entities: f64,
+    colliders: f64,
+    /// Milliseconds, in the order of [`SET_TIMINGS`].
+    sets: [f64; 6],
+}
+
+/// The overlay, and everything measured this session for exporting.
+#[derive(Resource, Debug, Default)]
+struct PerfHud {
+    visible: bool,
+    frame_times: VecDeque<f32>,
+    samples: Vec<PerfSample>,
+    last_sample: f64,
+}
+
+impl PerfHud {
+    fn to_csv(&self) -> String {
+        let mut csv = "time,fps,frame_time_ms,entities,colliders".to_string();
+        for (_, _, path) in SET_TIMINGS.iter() {
+            write!(csv, ",{}_ms", path.as_str().replace('/', "_")).unwrap();
+        }
+        csv.push('\n');
+
+        for sample in self.samples.iter() {
+            write!(
+                csv,
+                "{:.3},{:.2},{:.3},{},{}",
+                sample.time, sample.fps, sample.frame_time, sample.entities, sample.colliders
+            )
+            .unwrap();
+            for set in sample.sets {
+                write!(csv, ",{set:.3}").unwrap();
+            }
+            csv.push('\n');
+        }
+
+        csv
+    }
+
+    fn to_json(&self) -> String {
+        let samples: Vec<String> = self
+            .samples
+            .iter()
+            .map(|sample| {
+                let sets: Vec<String> = SET_TIMINGS
+                    .iter()
+                    .zip(sample.sets)
+                    .map(|((schedule, set, _), time)| {
+                        format!("\"{}\": {time:.3}", set_label(*schedule, set))
+                    })
+                    .collect();
+
+                format!(
+                    "    {{\"time\": {:.3}, \"fps\": {:.2}, \"frame_time_ms\": {:.3}, \
+                     \"entities\": {}, \"colliders\": {}, \"set_timings_ms\": {{{}}}}}",
+                    sample.time,
+                    sample.fps,
+                    sample.frame_time,
+                    sample.entities,
+                    sample.colliders,
+                    sets.join(", ")
+                )
+            })
+            .collect();
+
+        format!("[\n{}\n]\n", samples.join(",\n"))
+    }
+
+    fn export(&self) {
+        if self.samples.is_empty() {
+            return;
+        }
+
+        let started = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_or(0, |since| since.as_secs());
+
+        persistence::write(&format!("perf-{started}.csv"), &self.to_csv());
+        persistence::write(&format!("perf-{started}.json"), &self.to_json());
+        info!("Exported {} performance samples", self.samples.len());
+    }
+}
+
+fn start_timing(
+    schedule: TimedSchedule,
+    set: InGameSet,
+) -> impl FnMut(ResMut<SetTimers>) + Send + Sync + 'static {
+    move |mut timers: ResMut<SetTimers>| {
+        timers
+            .started
+            .insert((schedule, set.clone()), Instant::now());
+    }
+}
+
+fn end_timing(
+    schedule: TimedSchedule,
+    set: InGameSet,
+    path: DiagnosticPath,
+) -> impl FnMut(Res<SetTimers>, Diagnostics) + Send + Sync + 'static {
+    move |timers: Res<SetTimers>, mut diagnostics: Diagnostics| {
+        if let Some(started) = timers.started.get(&(schedule, set.clone())) {
+            diagnostics.add_measurement(&path, || started.elapsed().as_secs_f64() * 1000.);
+        }
+    }
+}
+
+fn count_colliders(colliders: Query<(), With<Collider>>, mut diagnostics: Diagnostics) {
+    diagnostics.add_measurement(&COLLIDER_COUNT, || colliders.iter().count() as f64);
+}
+
+fn record_performance(
+    mut hud: ResMut<PerfHud>,
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    diagnostics: Res<DiagnosticsStore>,
+    time: Res<Time<Real>>,
+) {
+    if keyboard_input.just_pressed(TOGGLE_HUD) {
+        hud.visible = !hud.visible;
+    }
+
+    hud.frame_times.push_back(time.delta_seconds() * 1000.);
+    if hud.frame_times.len() > FRAME_TIME_HISTORY {
+        hud.frame_times.pop_front();
+    }
+
+    let now = time.elapsed_seconds_f64();
+    if now - hud.last_sample < SAMPLE_INTERVAL {
+        return;
+    }
+    hud.last_sample = now;
+
+    let value = |path: &DiagnosticPath| {
+        diagnostics
+            .get(path)
+            .and_then(|diagnostic| diagnostic.smoothed())
+            .unwrap_or_default()
+    };
+
+    let sample = PerfSample {
+        time: now,
+        fps: value(&FrameTimeDiagnosticsPlugin::FPS),
+        frame_time: value(&FrameTimeDiagnosticsPlugin::FRAME_TIME),
+        entities: value(&EntityCountDiagnosticsPlugin::ENTITY_COUNT),
+        colliders: value(&COLLIDER_COUNT),
+        sets: SET_TIMINGS.map(|(_, _, path)| value(&path)),
+    };
+    hud.samples.push(sample);
+}
+
+fn draw_hud(mut contexts: EguiContexts, hud: Res<PerfHud>) {
+    let Some(ctx) = contexts.try_ctx_mut() else {
+        return;
+    };
+
+    if !hud.visible {
+        return;
+    }
+
+    egui::Window::new("Performance")
+        .default_pos((10., 200.))
+        .resizable(false)
+        .show(ctx, |ui| {
+            let Some(sample) = hud.samples.last() else {
+                ui.label("Measuring...");
+                return;
+            };
+
+            ui.label(format!(
+                "{:.0} FPS, {:.2} ms",
+                sample.fps, sample.frame_time
+            ));
+
+            let (response, painter) = ui.allocate_painter(Vec2::new(240., 60.), Sense::hover());
+            let rect = response.rect;
+            painter.rect_filled(rect, 0., Color32::from_black_alpha(120));
+
+            let step = rect.width() / FRAME_TIME_HISTORY as f32;
+            let points: Vec<Pos2> = hud
+                .frame_times
+                .iter()
+                .enumerate()
+                .map(|(index, frame_time)| {
+                    let height = (frame_time / GRAPH_MAX_FRAME_TIME).min(1.) * rect.height();
+                    Pos2::new(rect.left() + index as f32 * step, rect.bottom() - height)
+                })
+                .collect();
+            painter.add(egui::Shape::line(points, Stroke::new(1., Color32::GREEN)));
+
+            // A line at 60 FPS.
+            let target = rect.bottom() - rect.height() * (1000. / 60.) / GRAPH_MAX_FRAME_TIME;
+            painter.hline(
+                rect.x_range(),
+                target,
+                Stroke::new(1., Color32::from_white_alpha(60)),
+            );
+
+            egui::Grid::new("Counts").show(ui, |ui| {
+                ui.label("Entities");
+                ui.label(format!("{:.0}", sample.entities));
+                ui.end_row();
+
+                ui.label("Colliders");
+                ui.label(format!("{:.0}", sample.colliders));
+                ui.end_row();
+
+                for ((schedule, set, _), time) in SET_TIMINGS.iter().zip(sample.sets) {
+                    ui.label(set_label(*schedule, set));
+                    ui.label(format!("{time:.3} ms"));
+                    ui.end_row();
+                }
+            });
+
+            if ui.button("Export").clicked() {
+                hud.export();
+            }
+        });
+}
+
+fn export_on_exit(hud: Res<PerfHud>, mut exits: EventReader<AppExit>) {
+    if exits.read().count() > 0 {
+        hud.export();
+    }
+}
+
+/// FPS, frame times, entity counts and set timings, in both [`Update`] and
+/// [`FixedUpdate`], shown with F3 and exported as CSV and JSON on exit.
+pub struct PerfPlugin;
+
+impl Plugin for PerfPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins(EntityCountDiagnosticsPlugin)
+            .init_resource::<SetTimers>()
+            .init_resource::<PerfHud>()
+            .register_diagnostic(Diagnostic::new(COLLIDER_COUNT))
+            .add_systems(Update, count_colliders)
+            .add_systems(Update, (record_performance, draw_hud).chain())
+            .add_systems(Last, export_on_exit);
+
+        let mut previous = None;
+        for (schedule, set, path) in SET_TIMINGS {
+            app.register_diagnostic(Diagnostic::new(path.clone()).with_suffix("ms"));
+
+            // Each start waits for the set before it in the same schedule.
+            let start = start_timing(schedule, set.clone()).before(set.clone());
+            let start = match previous.replace((schedule, set.clone())) {
+                Some((previous_schedule, previous)) if previous_schedule == schedule => {
+                    start.after(previous)
+                }
+                _ => start,
+            };
+            let end = end_timing(schedule, set.clone(), path).after(set);
+
+            match schedule {
+                TimedSchedule::Update => app.add_systems(Update, (start, end)),
+                TimedSchedule::FixedUpdate => app.add_systems(FixedUpdate, (start, end)),
+            };
+        }
+    }
+}
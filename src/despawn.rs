@@ -3,19 +3,26 @@ use bevy::prelude::*;
 use crate::{
     application::AppState,
     asteroid::Asteroid,
+    collision_detection::Hazard,
+    player::PlayerId,
     schedule::InGameSet,
     spaceship::{AlreadyFired, Missile, Spaceship},
 };
 
-const DESPAWN_DISTANCE: f32 = 50.0;
+pub const DESPAWN_DISTANCE: f32 = 50.0;
 
-fn despawn_far_away_asteroids(
+/// Solid hazards, like bosses, are left to leave the arena on their own.
+fn despawn_far_away_hazards(
     mut commands: Commands,
-    query: Query<(Entity, &GlobalTransform), With<Asteroid>>,
+    query: Query<(Entity, &GlobalTransform, Option<&Hazard>), Or<(With<Asteroid>, With<Hazard>)>>,
 ) {
-    for (entity, transform) in query.iter() {
+    for (entity, transform, hazard) in query.iter() {
+        if hazard.is_some_and(|hazard| hazard.solid) {
+            continue;
+        }
+
         if transform.translation().distance(Vec3::ZERO) > DESPAWN_DISTANCE {
-            info!("Despawning asteroid");
+            info!("Despawning hazard");
             commands.entity(entity).despawn_recursive();
         }
     }
@@ -23,15 +30,18 @@ fn despawn_far_away_asteroids(
 
 fn despawn_far_away_missiles(
     mut commands: Commands,
-    query: Query<(Entity, &GlobalTransform), With<Missile>>,
-    spaceship_query: Query<Entity, With<Spaceship>>,
+    query: Query<(Entity, &GlobalTransform, Option<&PlayerId>), With<Missile>>,
+    spaceship_query: Query<(Entity, &PlayerId), With<Spaceship>>,
 ) {
-    for (entity, transform) in query.iter() {
+    for (entity, transform, owner) in query.iter() {
         if transform.translation().distance(Vec3::ZERO) > DESPAWN_DISTANCE {
             info!("Despawning missiles");
 
             commands.entity(entity).despawn_recursive();
-            if let Ok(spaceship) = spaceship_query.get_single() {
+            if let Some((spaceship, _)) = spaceship_query
+                .iter()
+                .find(|(_, player)| Some(*player) == owner)
+            {
                 commands.entity(spaceship).remove::<AlreadyFired>();
             }
         }
@@ -44,7 +54,7 @@ impl Plugin for DespawnPlugin {
     fn build(&self, app: &mut App) {
         app.add_systems(
             Update,
-            (despawn_far_away_asteroids, despawn_far_away_missiles)
+            (despawn_far_away_hazards, despawn_far_away_missiles)
                 .chain()
                 .in_set(InGameSet::DespawnEntities)
                 .run_if(in_state(AppState::InGame)),
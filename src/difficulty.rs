@@ -0,0 +1,96 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// The difficulty of the current run. New games take it from the main menu,
+/// continued games restore it from the save.
+#[derive(
+    Resource, Reflect, Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize,
+)]
+#[reflect(Resource)]
+pub enum Difficulty {
+    Easy,
+    #[default]
+    Normal,
+    Hard,
+    Insane,
+}
+
+/// Multipliers applied to the base constants in each module, plus how much a
+/// hit hurts.
+#[derive(Debug, Clone, Copy)]
+pub struct DifficultyPreset {
+    pub health: f32,
+    pub spawn_interval: f32,
+    pub asteroid_speed: f32,
+    pub initial_asteroids: f32,
+    pub damage: u32,
+    pub score_multiplier: f32,
+}
+
+impl Difficulty {
+    pub const ALL: [Difficulty; 4] = [
+        Difficulty::Easy,
+        Difficulty::Normal,
+        Difficulty::Hard,
+        Difficulty::Insane,
+    ];
+
+    pub fn preset(&self) -> DifficultyPreset {
+        match self {
+            Difficulty::Easy => DifficultyPreset {
+                health: 1.6,
+                spawn_interval: 1.5,
+                asteroid_speed: 0.7,
+                initial_asteroids: 0.6,
+                damage: 1,
+                score_multiplier: 0.5,
+            },
+            Difficulty::Normal => DifficultyPreset {
+                health: 1.0,
+                spawn_interval: 1.0,
+                asteroid_speed: 1.0,
+                initial_asteroids: 1.0,
+                damage: 1,
+                score_multiplier: 1.0,
+            },
+            Difficulty::Hard => DifficultyPreset {
+                health: 1.0,
+                spawn_interval: 0.75,
+                asteroid_speed: 1.3,
+                initial_asteroids: 1.4,
+                damage: 2,
+                score_multiplier: 1.5,
+            },
+            Difficulty::Insane => DifficultyPreset {
+                health: 0.8,
+                spawn_interval: 0.5,
+                asteroid_speed: 1.6,
+                initial_asteroids: 2.0,
+                damage: 2,
+                score_multiplier: 3.0,
+            },
+        }
+    }
+}
+
+impl DifficultyPreset {
+    /// Scales a count, never dropping below one.
+    pub fn scale_count(multiplier: f32, base: u32) -> u32 {
+        ((base as f32 * multiplier).round() as u32).max(1)
+    }
+}
+
+impl std::fmt::Display for Difficulty {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{self:?}")
+    }
+}
+
+pub struct DifficultyPlugin;
+
+impl Plugin for DifficultyPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Difficulty>()
+            .register_type::<Difficulty>();
+    }
+}
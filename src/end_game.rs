@@ -1,7 +1,14 @@
 use bevy::prelude::*;
 use bevy_inspector_egui::egui;
 
-use crate::application::AppState;
+use crate::{
+    application::AppState,
+    asteroid::{AsteroidSize, Wave},
+    score::{HighScores, Score},
+    stats::RunStats,
+};
+
+const LEADERBOARD_LENGTH: usize = 5;
 
 #[derive(Debug, Component)]
 pub struct DespawnAtEndgame;
@@ -48,11 +55,71 @@ fn keyboad_controls(
 fn end_game(
     mut contexts: bevy_inspector_egui::bevy_egui::EguiContexts,
     mut app_state: ResMut<NextState<AppState>>,
+    score: Res<Score>,
+    wave: Res<Wave>,
+    stats: Res<RunStats>,
+    high_scores: Res<HighScores>,
     #[cfg(not(target_arch = "wasm32"))] mut exit: EventWriter<bevy::app::AppExit>,
 ) {
-    egui::CentralPanel::default().show(contexts.ctx_mut(), |ui| {
+    let Some(ctx) = contexts.try_ctx_mut() else {
+        return;
+    };
+
+    egui::CentralPanel::default().show(ctx, |ui| {
         ui.allocate_space(egui::Vec2::new(1.0, 300.0));
         ui.label("End game");
+        ui.label(format!("Score: {} on {}", *score, score.difficulty()));
+        match stats.previous_best {
+            Some(best) if score.points() > best => {
+                ui.label(format!("New personal best! (was {best})"));
+            }
+            Some(best) => {
+                ui.label(format!("Personal best: {best}"));
+            }
+            None => {
+                ui.label("First run on this difficulty");
+            }
+        }
+
+        let seconds = stats.time_survived as u32;
+        ui.label(format!("Survived {}:{:02}", seconds / 60, seconds % 60));
+        ui.label(format!("Reached wave {}", *wave));
+
+        egui::Grid::new("run_stats").show(ui, |ui| {
+            for size in AsteroidSize::ALL {
+                ui.label(format!("{size:?} asteroids"));
+                ui.label(stats.destroyed(size).to_string());
+                ui.end_row();
+            }
+
+            ui.label("Shots fired");
+            ui.label(stats.shots_fired.to_string());
+            ui.end_row();
+
+            ui.label("Accuracy");
+            ui.label(stats.accuracy().map_or("-".to_string(), |accuracy| {
+                format!("{:.0}%", accuracy * 100.)
+            }));
+            ui.end_row();
+
+            ui.label("Shields used");
+            ui.label(stats.shields_used.to_string());
+            ui.end_row();
+
+            ui.label("Damage taken");
+            ui.label(stats.damage_taken.to_string());
+            ui.end_row();
+        });
+
+        ui.label(format!("Best on {}:", score.difficulty()));
+        for (rank, entry) in high_scores
+            .for_difficulty(score.difficulty())
+            .take(LEADERBOARD_LENGTH)
+            .enumerate()
+        {
+            ui.label(format!("{}. {}", rank + 1, entry.points));
+        }
+
         if ui.button("[M]ain Menu").clicked() {
             app_state.set(AppState::MainMenu);
         };
@@ -0,0 +1,573 @@
+use bevy::{ecs::system::SystemState, prelude::*};
+use rand::{rngs::StdRng, Rng};
+use std::{ops::Range, time::Duration};
+
+use crate::{
+    application::AppState,
+    asset_loader::SceneAssets,
+    asteroid::{self, SpawnRng, SpawnTimer, Wave},
+    boss::Boss,
+    collision_detection::{
+        hurt_spaceship, Collider, FastMover, Hazard, HazardDestroyed, SpaceshipDamaged,
+    },
+    console::{ConsoleAppExt, ConsoleCommand},
+    despawn::DESPAWN_DISTANCE,
+    difficulty::Difficulty,
+    end_game::DespawnAtEndgame,
+    movement::{Acceleration, MovingObjectBundle, Velocity},
+    player::PlayerId,
+    schedule::InGameSet,
+    scoring::PointValue,
+    spaceship::{
+        health::Health,
+        lives::{GodMode, Invulnerable, Respawning},
+        shield::{ShieldDisplay, SpaceshipShield},
+        Missile, Spaceship,
+    },
+    ui::{BlipKind, RadarBlip},
+};
+
+const SPAWN_RANGE: Range<f32> = -40.0..40.0;
+/// Wells and mines don't turn up right on top of a spaceship.
+const SPAWN_CLEARANCE: f32 = 20.0;
+const SPAWN_ATTEMPTS: usize = 20;
+/// Chance each asteroid spawn also brings one of these, growing each wave.
+const HAZARD_CHANCE: f32 = 0.05;
+const HAZARD_CHANCE_PER_WAVE: f32 = 0.02;
+const MAX_HAZARD_CHANCE: f32 = 0.3;
+
+const WELL_RADIUS: f32 = 3.0;
+const WELL_SCALE: f32 = 0.8;
+/// Things further away than this don't feel the pull.
+const WELL_RANGE: f32 = 30.0;
+/// Pull at distance `d` is this over `d²`, up to what it would be at the
+/// edge of the well.
+const WELL_STRENGTH: f32 = 900.0;
+const WELL_LIFETIME: f32 = 20.0;
+const MAX_WELLS: usize = 2;
+
+const MINE_RADIUS: f32 = 1.2;
+const MINE_ARM_TIME: f32 = 1.5;
+/// An armed mine lights its fuse when a spaceship comes this close.
+const MINE_TRIGGER_RADIUS: f32 = 8.0;
+const MINE_FUSE_TIME: f32 = 0.6;
+/// Spaceships this close when it goes off lose their shield or some health.
+pub const MINE_BLAST_RADIUS: f32 = 10.0;
+const MINE_POINTS: usize = 2;
+const MINE_PULSE_SPEED: f32 = 20.0;
+
+const COMET_RADIUS: f32 = 1.2;
+const COMET_SCALE: f32 = 0.6;
+const COMET_SPEED: f32 = 45.0;
+const COMET_POINTS: usize = 4;
+/// Seconds the path is marked out before the comet comes through.
+const COMET_WARNING_TIME: f32 = 1.5;
+/// Comets are aimed somewhere this close to the middle of the arena.
+const COMET_AIM: f32 = 15.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HazardKind {
+    GravityWell,
+    Mine,
+    Comet,
+}
+
+impl HazardKind {
+    const ALL: [HazardKind; 3] = [HazardKind::GravityWell, HazardKind::Mine, HazardKind::Comet];
+    const NAMES: [&'static str; 3] = ["well", "mine", "comet"];
+
+    /// The first wave it can turn up in.
+    fn first_wave(&self) -> u32 {
+        match self {
+            HazardKind::Mine => 2,
+            HazardKind::GravityWell => 3,
+            HazardKind::Comet => 4,
+        }
+    }
+
+    fn parse(name: &str) -> Option<Self> {
+        Self::NAMES
+            .iter()
+            .position(|&known| known == name)
+            .map(|index| Self::ALL[index])
+    }
+}
+
+/// Pulls everything with an [`Acceleration`] towards it, until it collapses.
+#[derive(Component, Debug)]
+pub struct GravityWell {
+    lifetime: Timer,
+}
+
+/// What each well added to an [`Acceleration`] this step, taken off again
+/// once velocities have been updated.
+#[derive(Resource, Debug, Default)]
+struct GravityPulls(Vec<(Entity, Vec3)>);
+
+#[derive(Debug)]
+enum MineState {
+    Arming(Timer),
+    Armed,
+    Fusing(Timer),
+}
+
+#[derive(Component, Debug)]
+pub struct Mine {
+    state: MineState,
+}
+
+/// Sent when a mine goes off, shot or not. Shot mines don't also send a
+/// [`HazardDestroyed`], so they only explode once.
+#[derive(Event, Debug)]
+pub struct MineDetonated {
+    pub position: Vec3,
+    /// Mines that go off by themselves aren't worth anything.
+    pub shot: Option<MineShot>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct MineShot {
+    pub points: usize,
+    /// Whoever fired the missile.
+    pub player: Option<PlayerId>,
+}
+
+#[derive(Component, Debug)]
+pub struct Comet;
+
+/// Marks out where a comet is about to come through.
+#[derive(Component, Debug)]
+struct CometWarning {
+    timer: Timer,
+    velocity: Vec3,
+}
+
+type SpaceshipPositions<'w, 's> =
+    Query<'w, 's, &'static GlobalTransform, (With<Spaceship>, Without<Respawning>)>;
+
+/// Somewhere in the arena clear of the spaceships, or wherever it got to
+/// if there's nowhere clear.
+fn spawn_point(rng: &mut StdRng, spaceships: &SpaceshipPositions) -> Vec3 {
+    let mut point = Vec3::ZERO;
+
+    for _ in 0..SPAWN_ATTEMPTS {
+        point = Vec3::new(rng.gen_range(SPAWN_RANGE), 0., rng.gen_range(SPAWN_RANGE));
+
+        if spaceships
+            .iter()
+            .all(|spaceship| spaceship.translation().distance(point) > SPAWN_CLEARANCE)
+        {
+            break;
+        }
+    }
+
+    point
+}
+
+fn spawn_hazard(
+    commands: &mut Commands,
+    assets: &SceneAssets,
+    kind: HazardKind,
+    rng: &mut StdRng,
+    spaceships: &SpaceshipPositions,
+) {
+    info!("Spawning {kind:?}");
+
+    match kind {
+        HazardKind::GravityWell => {
+            commands.spawn((
+                GravityWell {
+                    lifetime: Timer::from_seconds(WELL_LIFETIME, TimerMode::Once),
+                },
+                SceneBundle {
+                    scene: assets.gravity_well.clone(),
+                    transform: Transform::from_translation(spawn_point(rng, spaceships))
+                        .with_scale(Vec3::splat(WELL_SCALE)),
+                    ..default()
+                },
+                Collider::new(WELL_RADIUS),
+                Hazard::SOLID,
+                RadarBlip(BlipKind::Enemy),
+                DespawnAtEndgame,
+            ));
+        }
+        HazardKind::Mine => {
+            commands.spawn((
+                Mine {
+                    state: MineState::Arming(Timer::from_seconds(MINE_ARM_TIME, TimerMode::Once)),
+                },
+                SceneBundle {
+                    scene: assets.mine.clone(),
+                    transform: Transform::from_translation(spawn_point(rng, spaceships)),
+                    ..default()
+                },
+                Collider::new(MINE_RADIUS),
+                PointValue(MINE_POINTS),
+                RadarBlip(BlipKind::Enemy),
+                DespawnAtEndgame,
+            ));
+        }
+        HazardKind::Comet => {
+            // From just inside the edge of the arena, across the middle.
+            let angle = rng.gen_range(0.0..std::f32::consts::TAU);
+            let start = Vec3::new(angle.cos(), 0., angle.sin()) * (DESPAWN_DISTANCE - 1.);
+            let aim = Vec3::new(
+                rng.gen_range(-COMET_AIM..COMET_AIM),
+                0.,
+                rng.gen_range(-COMET_AIM..COMET_AIM),
+            );
+
+            commands.spawn((
+                CometWarning {
+                    timer: Timer::from_seconds(COMET_WARNING_TIME, TimerMode::Once),
+                    velocity: (aim - start).normalize_or_zero() * COMET_SPEED,
+                },
+                TransformBundle::from_transform(Transform::from_translation(start)),
+                DespawnAtEndgame,
+            ));
+        }
+    }
+}
+
+/// Every so often an asteroid spawn brings something nastier with it, more
+/// often as the waves go on.
+fn spawn_hazards_on_interval(
+    mut commands: Commands,
+    spawn_timer: Res<SpawnTimer>,
+    wave: Res<Wave>,
+    mut rng: ResMut<SpawnRng>,
+    assets: Res<SceneAssets>,
+    spaceships: SpaceshipPositions,
+    wells: Query<(), With<GravityWell>>,
+) {
+    if !spawn_timer.just_finished() {
+        return;
+    }
+
+    let chance =
+        (HAZARD_CHANCE + HAZARD_CHANCE_PER_WAVE * wave.number as f32).min(MAX_HAZARD_CHANCE);
+    if !rng.gen_bool(chance as f64) {
+        return;
+    }
+
+    let available: Vec<HazardKind> = HazardKind::ALL
+        .into_iter()
+        .filter(|kind| wave.number >= kind.first_wave())
+        .filter(|kind| *kind != HazardKind::GravityWell || wells.iter().count() < MAX_WELLS)
+        .collect();
+    if available.is_empty() {
+        return;
+    }
+
+    let kind = available[rng.gen_range(0..available.len())];
+    spawn_hazard(&mut commands, &assets, kind, &mut rng, &spaceships);
+}
+
+fn spawn_hazard_command(world: &mut World, arguments: &[&str]) -> Result<String, String> {
+    let [name] = arguments else {
+        return Err("Expected one argument".to_string());
+    };
+    let kind = HazardKind::parse(name).ok_or_else(|| format!("There's no hazard called {name}"))?;
+    if !world.contains_resource::<SceneAssets>() {
+        return Err("Still loading".to_string());
+    }
+
+    let mut state: SystemState<(
+        Commands,
+        Res<SceneAssets>,
+        ResMut<SpawnRng>,
+        SpaceshipPositions,
+    )> = SystemState::new(world);
+    let (mut commands, assets, mut rng, spaceships) = state.get_mut(world);
+    spawn_hazard(&mut commands, &assets, kind, &mut rng, &spaceships);
+
+    state.apply(world);
+    Ok(format!("Spawned a {name}"))
+}
+
+fn gravity(well: Vec3, position: Vec3) -> Vec3 {
+    let offset = well - position;
+    let distance = offset.length();
+    if distance > WELL_RANGE || distance < f32::EPSILON {
+        return Vec3::ZERO;
+    }
+
+    offset / distance * WELL_STRENGTH / distance.max(WELL_RADIUS).powi(2)
+}
+
+/// Adds each well's pull on top of whatever else is accelerating things,
+/// such as a spaceship's thrust, so `update_velocity` applies it.
+fn apply_gravity_wells(
+    wells: Query<&Transform, With<GravityWell>>,
+    mut pulled: Query<(Entity, &Transform, &mut Acceleration), Without<GravityWell>>,
+    mut pulls: ResMut<GravityPulls>,
+) {
+    if wells.is_empty() {
+        return;
+    }
+
+    for (entity, transform, mut acceleration) in pulled.iter_mut() {
+        let pull: Vec3 = wells
+            .iter()
+            .map(|well| gravity(well.translation, transform.translation))
+            .sum();
+
+        if pull != Vec3::ZERO {
+            acceleration.value += pull;
+            pulls.0.push((entity, pull));
+        }
+    }
+}
+
+fn release_gravity_wells(mut pulled: Query<&mut Acceleration>, mut pulls: ResMut<GravityPulls>) {
+    for (entity, pull) in pulls.0.drain(..) {
+        if let Ok(mut acceleration) = pulled.get_mut(entity) {
+            acceleration.value -= pull;
+        }
+    }
+}
+
+/// Wells shrink away over the last second of their life.
+fn collapse_gravity_wells(
+    mut commands: Commands,
+    mut wells: Query<(Entity, &mut GravityWell, &mut Transform)>,
+    time: Res<Time>,
+) {
+    for (entity, mut well, mut transform) in wells.iter_mut() {
+        if well.lifetime.tick(time.delta()).finished() {
+            commands.entity(entity).despawn_recursive();
+            continue;
+        }
+
+        let remaining = well.lifetime.remaining_secs().min(1.);
+        transform.scale = Vec3::splat(WELL_SCALE * remaining);
+    }
+}
+
+fn update_mines(
+    mut commands: Commands,
+    mut mines: Query<(Entity, &mut Mine, &mut Transform)>,
+    spaceships: SpaceshipPositions,
+    mut detonated: EventWriter<MineDetonated>,
+    time: Res<Time>,
+) {
+    for (entity, mut mine, mut transform) in mines.iter_mut() {
+        match &mut mine.state {
+            MineState::Arming(timer) => {
+                if timer.tick(time.delta()).finished() {
+                    mine.state = MineState::Armed;
+                }
+            }
+            MineState::Armed => {
+                let triggered = spaceships.iter().any(|spaceship| {
+                    spaceship.translation().distance(transform.translation) < MINE_TRIGGER_RADIUS
+                });
+
+                if triggered {
+                    mine.state =
+                        MineState::Fusing(Timer::from_seconds(MINE_FUSE_TIME, TimerMode::Once));
+                }
+            }
+            MineState::Fusing(timer) => {
+                if timer.tick(time.delta()).finished() {
+                    commands.entity(entity).despawn_recursive();
+                    detonated.send(MineDetonated {
+                        position: transform.translation,
+                        shot: None,
+                    });
+                    continue;
+                }
+
+                // Throbs while the fuse burns, as a last warning.
+                let pulse = (timer.elapsed_secs() * MINE_PULSE_SPEED).sin();
+                transform.scale = Vec3::splat(1. + 0.3 * pulse);
+            }
+        }
+    }
+}
+
+type BlastTargets<'w, 's> = Query<
+    'w,
+    's,
+    (
+        Entity,
+        &'static GlobalTransform,
+        &'static mut Health,
+        Has<SpaceshipShield>,
+    ),
+    (With<Spaceship>, Without<Invulnerable>, Without<GodMode>),
+>;
+
+fn mine_blasts(
+    mut commands: Commands,
+    mut detonated: EventReader<MineDetonated>,
+    mut spaceships: BlastTargets,
+    shield_displays: Query<(Entity, &Parent), With<ShieldDisplay>>,
+    mut damaged: EventWriter<SpaceshipDamaged>,
+    difficulty: Res<Difficulty>,
+) {
+    for detonation in detonated.read() {
+        for (spaceship, transform, mut health, has_shield) in spaceships.iter_mut() {
+            if transform.translation().distance(detonation.position) > MINE_BLAST_RADIUS {
+                continue;
+            }
+
+            hurt_spaceship(
+                &mut commands,
+                spaceship,
+                &mut health,
+                has_shield,
+                difficulty.preset().damage,
+                &shield_displays,
+                &mut damaged,
+            );
+        }
+    }
+}
+
+fn warn_of_comets(
+    mut commands: Commands,
+    mut warnings: Query<(Entity, &mut CometWarning, &Transform)>,
+    assets: Res<SceneAssets>,
+    time: Res<Time>,
+) {
+    for (entity, mut warning, transform) in warnings.iter_mut() {
+        if !warning.timer.tick(time.delta()).finished() {
+            continue;
+        }
+
+        commands.entity(entity).despawn_recursive();
+        commands.spawn((
+            Comet,
+            MovingObjectBundle {
+                velocity: Velocity::new(warning.velocity),
+                acceleration: Acceleration::new(Vec3::ZERO),
+                model: SceneBundle {
+                    scene: assets.asteroids.clone(),
+                    transform: Transform::from_translation(transform.translation)
+                        .with_scale(Vec3::splat(COMET_SCALE)),
+                    ..default()
+                },
+                collider: Collider::new(COMET_RADIUS),
+            },
+            FastMover::default(),
+            Hazard::default(),
+            PointValue(COMET_POINTS),
+            RadarBlip(BlipKind::Enemy),
+            DespawnAtEndgame,
+        ));
+    }
+}
+
+/// A line across the arena along the comet's path, flashing faster as it
+/// gets closer.
+fn draw_comet_warnings(mut gizmos: Gizmos, warnings: Query<(&CometWarning, &Transform)>) {
+    for (warning, transform) in warnings.iter() {
+        let flashes = 2. + 6. * warning.timer.fraction();
+        if (warning.timer.elapsed_secs() * flashes).fract() > 0.5 {
+            continue;
+        }
+
+        let start = transform.translation;
+        let direction = warning.velocity.normalize_or_zero();
+        let color = Color::rgb(1., 0.3, 0.1);
+
+        gizmos.line(start, start + direction * DESPAWN_DISTANCE * 2., color);
+        gizmos.circle(start, Direction3d::Y, COMET_RADIUS * 2., color);
+    }
+}
+
+/// Missiles set mines off and knock comets out of the sky.
+fn handle_hazard_hits(
+    mut commands: Commands,
+    mines: Query<(Entity, &Transform, &Collider, &PointValue), With<Mine>>,
+    comets: Query<(Entity, &Transform, &Collider, &PointValue), With<Comet>>,
+    missiles: Query<Option<&PlayerId>, With<Missile>>,
+    mut destroyed: EventWriter<HazardDestroyed>,
+    mut detonated: EventWriter<MineDetonated>,
+) {
+    for (hazard, transform, collider, points) in mines.iter().chain(comets.iter()) {
+        let Some((missile, player)) = collider
+            .colliding_entities
+            .iter()
+            .find_map(|&entity| missiles.get(entity).ok().map(|player| (entity, player)))
+        else {
+            continue;
+        };
+
+        commands.entity(missile).despawn_recursive();
+        commands.entity(hazard).despawn_recursive();
+
+        if mines.contains(hazard) {
+            detonated.send(MineDetonated {
+                position: transform.translation,
+                shot: Some(MineShot {
+                    points: points.0,
+                    player: player.copied(),
+                }),
+            });
+        } else {
+            destroyed.send(HazardDestroyed {
+                position: transform.translation,
+                radius: collider.radius,
+                points: points.0,
+                player: player.copied(),
+            });
+        }
+    }
+}
+
+/// Gravity wells, proximity mines and comets, brought in by the asteroid
+/// spawner as the waves go on.
+pub struct HazardsPlugin;
+
+impl Plugin for HazardsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<GravityPulls>()
+            .add_event::<MineDetonated>()
+            .add_console_command(
+                ConsoleCommand::new(
+                    "spawn hazard",
+                    "spawn hazard <well|mine|comet>",
+                    "Spawn a gravity well, mine or comet",
+                    spawn_hazard_command,
+                )
+                .with_arguments(&HazardKind::NAMES),
+            )
+            .add_systems(
+                FixedUpdate,
+                (
+                    apply_gravity_wells
+                        .after(InGameSet::UserInput)
+                        .before(InGameSet::EntityUpdates),
+                    release_gravity_wells.after(InGameSet::EntityUpdates),
+                )
+                    .run_if(in_state(AppState::InGame)),
+            )
+            .add_systems(
+                Update,
+                (
+                    spawn_hazards_on_interval
+                        .after(asteroid::spawn_asteroid_on_interval)
+                        .run_if(not(any_with_component::<Boss>)),
+                    collapse_gravity_wells,
+                    update_mines,
+                    warn_of_comets,
+                )
+                    .in_set(InGameSet::EntityUpdates)
+                    .run_if(in_state(AppState::InGame)),
+            )
+            .add_systems(
+                Update,
+                (handle_hazard_hits, mine_blasts)
+                    .chain()
+                    .in_set(InGameSet::DespawnEntities)
+                    .run_if(in_state(AppState::InGame)),
+            )
+            .add_systems(
+                Update,
+                draw_comet_warnings
+                    .run_if(in_state(AppState::InGame).or_else(in_state(AppState::Paused))),
+            );
+    }
+}
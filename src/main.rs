@@ -5,23 +5,57 @@ use bevy::prelude::*;
 mod application;
 mod asset_loader;
 mod asteroid;
+mod audio;
+mod boss;
 mod camera;
 mod collision_detection;
+mod console;
 mod debug;
 mod despawn;
+mod difficulty;
 mod end_game;
+mod hazards;
 mod movement;
+mod network;
+mod particles;
 mod pausemenu;
+mod persistence;
+mod player;
+mod save_game;
 mod schedule;
 mod score;
+mod scoring;
+mod settings;
 mod spaceship;
+mod stats;
 mod ui;
 
 fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    if let Some(directory) = args
+        .iter()
+        .position(|arg| arg == "--render-sfx")
+        .and_then(|index| args.get(index + 1))
+    {
+        if let Err(error) = audio::synth::render_presets(std::path::Path::new(directory)) {
+            eprintln!("Couldn't render the sound presets: {error}");
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    let role = match network::NetworkRole::from_args() {
+        Ok(role) => role,
+        Err(error) => {
+            eprintln!("{error}");
+            std::process::exit(2);
+        }
+    };
+    let headless = role.is_headless();
     let mut application = App::new();
 
     application
-        .add_plugins(application::AppPlugin)
+        .add_plugins(application::AppPlugin { headless })
         .add_plugins(pausemenu::PauseMenuPlugin)
         .add_plugins(movement::MovementPlugin)
         .add_plugins(spaceship::SpaceshipPlugin)
@@ -34,7 +68,25 @@ fn main() {
         .add_plugins(ui::UiPlugin)
         .add_plugins(end_game::EndGamePlugin)
         .add_plugins(bevy::diagnostic::FrameTimeDiagnosticsPlugin)
-        .add_plugins(score::ScorePlugin);
+        .add_plugins(score::ScorePlugin)
+        .add_plugins(scoring::ScoringPlugin)
+        .add_plugins(stats::StatsPlugin)
+        .add_plugins(save_game::SaveGamePlugin)
+        .add_plugins(settings::SettingsPlugin)
+        .add_plugins(difficulty::DifficultyPlugin)
+        .add_plugins(player::PlayerPlugin)
+        .add_plugins(network::NetworkPlugin { role });
+
+    // Nobody sees or hears anything on a headless server, and clients can
+    // only be sent spaceships, asteroids and missiles, and only predict plain
+    // movement without any gravity wells.
+    if !headless {
+        application
+            .add_plugins(boss::BossPlugin)
+            .add_plugins(hazards::HazardsPlugin)
+            .add_plugins(particles::ParticlesPlugin)
+            .add_plugins(audio::AudioPlugin);
+    }
 
     if cfg!(feature = "diagnostics") {
         application.add_plugins(debug::DebugPlugin);
@@ -2,7 +2,8 @@ use bevy::prelude::*;
 
 use crate::{application::AppState, collision_detection::Collider, schedule::InGameSet};
 
-#[derive(Component, Debug)]
+#[derive(Component, Debug, Reflect)]
+#[reflect(Component)]
 pub struct Velocity {
     pub value: Vec3,
 }
@@ -12,7 +13,8 @@ impl Velocity {
     }
 }
 
-#[derive(Component, Debug)]
+#[derive(Component, Debug, Reflect)]
+#[reflect(Component)]
 pub struct Acceleration {
     pub value: Vec3,
 }
@@ -31,6 +33,13 @@ pub struct MovingObjectBundle {
     pub collider: Collider,
 }
 
+/// One fixed step of [`update_position`] then [`update_velocity`], for
+/// re-simulating an entity outside of the schedule.
+pub fn integrate(translation: &mut Vec3, velocity: &mut Vec3, acceleration: Vec3, delta: f32) {
+    *translation += *velocity * delta;
+    *velocity += acceleration * delta;
+}
+
 fn update_velocity(mut query: Query<(&Acceleration, &mut Velocity)>, time: Res<Time>) {
     for (acceleration, mut velocity) in query.iter_mut() {
         velocity.value += acceleration.value * time.delta_seconds();
@@ -47,12 +56,14 @@ pub struct MovementPlugin;
 
 impl Plugin for MovementPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(
-            Update,
-            (update_position, update_velocity)
-                .chain()
-                .in_set(InGameSet::EntityUpdates)
-                .run_if(in_state(AppState::InGame)),
-        );
+        app.register_type::<Velocity>()
+            .register_type::<Acceleration>()
+            .add_systems(
+                FixedUpdate,
+                (update_position, update_velocity)
+                    .chain()
+                    .in_set(InGameSet::EntityUpdates)
+                    .run_if(in_state(AppState::InGame)),
+            );
     }
 }
@@ -0,0 +1,308 @@
+mod client;
+mod prediction;
+mod protocol;
+mod replay;
+mod server;
+mod spectator;
+#[cfg(test)]
+mod tests;
+
+use bevy::prelude::*;
+use std::{net::SocketAddr, path::PathBuf};
+
+use crate::{
+    application::AppState,
+    console::console_closed,
+    player::{PlayerMode, PlayerSetup},
+    schedule::InGameSet,
+};
+
+use self::{
+    client::{
+        despawn_networked_entities, interpolate_entities, join_server, leave_server,
+        receive_server_messages, retry_join, NetworkClient,
+    },
+    prediction::{
+        expire_unclaimed_missiles, move_predicted_missiles, predict_local_spaceship,
+        predict_missiles, Prediction,
+    },
+    protocol::LinkConditions,
+    replay::{play_replay, ReplayPlayer, ReplayRecorder},
+    server::{
+        apply_client_inputs, assign_network_ids, receive_client_messages, record_replay,
+        restart_server_game, send_game_over, send_snapshots, start_server_game, take_snapshot,
+        SnapshotTaken,
+    },
+    spectator::SpectatorPlugin,
+};
+
+pub use self::{server::NetworkServer, spectator::Spectator};
+
+const DEFAULT_ADDRESS: &str = "0.0.0.0:7777";
+
+/// How this instance takes part in a game, picked on the command line:
+///
+/// - `--server [ADDRESS] [--players N] [--versus] [--record FILE]` runs a
+///   headless server. Bosses, gravity wells, mines and comets never turn up
+///   in network games, since clients can only be sent spaceships, asteroids
+///   and missiles, and only predict plain movement.
+/// - `--connect ADDRESS` joins one.
+/// - `--spectate ADDRESS` watches one.
+/// - `--replay FILE` watches a recording.
+///
+/// Any of the networked ones can add [`LinkConditions`] to try out a poor
+/// connection.
+#[derive(Debug, Clone, PartialEq)]
+pub enum NetworkRole {
+    Offline,
+    Server {
+        address: SocketAddr,
+        players: PlayerSetup,
+        conditions: LinkConditions,
+        record: Option<PathBuf>,
+    },
+    Client {
+        server: SocketAddr,
+        conditions: LinkConditions,
+    },
+    Spectator {
+        server: SocketAddr,
+        conditions: LinkConditions,
+    },
+    Replay {
+        path: PathBuf,
+    },
+}
+
+impl NetworkRole {
+    /// Errors say which argument was wrong, since carrying on offline
+    /// instead would hide the mistake.
+    pub fn from_args() -> Result<Self, String> {
+        let args: Vec<String> = std::env::args().skip(1).collect();
+        let given = |flag: &str| args.iter().any(|arg| arg == flag);
+        let value_after = |flag: &str| {
+            args.iter()
+                .position(|arg| arg == flag)
+                .and_then(|index| args.get(index + 1))
+                .filter(|value| !value.starts_with("--"))
+        };
+        let required =
+            |flag: &str| value_after(flag).ok_or_else(|| format!("{flag} needs a value after it"));
+        let parse_address = |address: &str| {
+            address
+                .parse::<SocketAddr>()
+                .map_err(|error| format!("Invalid address {address}: {error}"))
+        };
+        let conditions = LinkConditions::from_args(&args);
+
+        if given("--server") {
+            let address = value_after("--server").map_or(DEFAULT_ADDRESS, |value| value.as_str());
+            let address = parse_address(address)?;
+
+            let mode = if given("--versus") {
+                PlayerMode::Versus
+            } else {
+                PlayerMode::Coop
+            };
+            let count = match value_after("--players") {
+                Some(count) => count
+                    .parse()
+                    .map_err(|_| format!("Invalid player count {count}"))?,
+                None => 2,
+            };
+
+            return Ok(NetworkRole::Server {
+                address,
+                players: PlayerSetup { mode, count },
+                conditions,
+                record: value_after("--record").map(PathBuf::from),
+            });
+        }
+
+        if given("--connect") {
+            let server = parse_address(required("--connect")?)?;
+            return Ok(NetworkRole::Client { server, conditions });
+        }
+
+        if given("--spectate") {
+            let server = parse_address(required("--spectate")?)?;
+            return Ok(NetworkRole::Spectator { server, conditions });
+        }
+
+        if given("--replay") {
+            return Ok(NetworkRole::Replay {
+                path: PathBuf::from(required("--replay")?),
+            });
+        }
+
+        Ok(NetworkRole::Offline)
+    }
+
+    pub fn is_headless(&self) -> bool {
+        matches!(self, NetworkRole::Server { .. })
+    }
+}
+
+/// Nothing is left to run when the network can't be set up; a server in
+/// particular would sit there with no window and no socket.
+fn exit_with_error(message: String) -> ! {
+    error!("{message}");
+    std::process::exit(1);
+}
+
+fn go_online(mut app_state: ResMut<NextState<AppState>>) {
+    app_state.set(AppState::Online);
+}
+
+pub struct NetworkPlugin {
+    pub role: NetworkRole,
+}
+
+impl NetworkPlugin {
+    /// What clients, spectators and replays have in common: they show a game
+    /// simulated somewhere else.
+    fn add_online_systems(app: &mut App) {
+        app.add_systems(
+            OnEnter(AppState::MainMenu),
+            go_online
+                .run_if(resource_exists::<NetworkClient>.or_else(resource_exists::<ReplayPlayer>)),
+        )
+        .add_systems(
+            OnEnter(AppState::Online),
+            join_server.run_if(resource_exists::<NetworkClient>),
+        )
+        .add_systems(
+            Update,
+            (
+                (retry_join, receive_server_messages)
+                    .chain()
+                    .run_if(resource_exists::<NetworkClient>),
+                play_replay.run_if(resource_exists::<ReplayPlayer>),
+                expire_unclaimed_missiles,
+                interpolate_entities,
+                leave_server.run_if(console_closed),
+            )
+                .chain()
+                .run_if(in_state(AppState::Online)),
+        )
+        .add_systems(OnExit(AppState::Online), despawn_networked_entities);
+    }
+}
+
+impl Plugin for NetworkPlugin {
+    fn build(&self, app: &mut App) {
+        match &self.role {
+            NetworkRole::Offline => {}
+            NetworkRole::Server {
+                address,
+                players,
+                conditions,
+                record,
+            } => {
+                match NetworkServer::bind(*address, *conditions) {
+                    Ok(server) => {
+                        let address = server.local_addr().unwrap_or(*address);
+                        info!("Serving {} players on {address}", players.players());
+                        info!("Bosses and hazards are left out of network games");
+                        app.insert_resource(server);
+                    }
+                    Err(error) => exit_with_error(format!("Couldn't listen on {address}: {error}")),
+                }
+
+                if let Some(path) = record {
+                    match ReplayRecorder::create(path) {
+                        Ok(recorder) => {
+                            info!("Recording a replay to {}", path.display());
+                            app.insert_resource(recorder);
+                        }
+                        Err(error) => exit_with_error(format!(
+                            "Couldn't record to {}: {error}",
+                            path.display()
+                        )),
+                    }
+                }
+
+                app.insert_resource(*players)
+                    .add_event::<SnapshotTaken>()
+                    .add_systems(OnEnter(AppState::MainMenu), start_server_game)
+                    .add_systems(
+                        OnEnter(AppState::EndGame),
+                        (send_game_over, restart_server_game).chain(),
+                    )
+                    .add_systems(
+                        Update,
+                        receive_client_messages.before(InGameSet::DespawnEntities),
+                    )
+                    .add_systems(
+                        FixedUpdate,
+                        apply_client_inputs
+                            .before(InGameSet::UserInput)
+                            .run_if(in_state(AppState::InGame)),
+                    )
+                    .add_systems(
+                        Update,
+                        (
+                            assign_network_ids,
+                            take_snapshot,
+                            send_snapshots,
+                            record_replay.run_if(resource_exists::<ReplayRecorder>),
+                        )
+                            .chain()
+                            .after(InGameSet::CollisionDetection)
+                            .run_if(in_state(AppState::InGame)),
+                    );
+            }
+            NetworkRole::Client { server, conditions } => {
+                match NetworkClient::connect(*server, *conditions) {
+                    Ok(client) => {
+                        app.insert_resource(client);
+                    }
+                    Err(error) => {
+                        exit_with_error(format!("Couldn't open a socket for {server}: {error}"))
+                    }
+                }
+
+                Self::add_online_systems(app);
+                app.add_systems(
+                    FixedUpdate,
+                    (
+                        (predict_local_spaceship, predict_missiles)
+                            .chain()
+                            .in_set(InGameSet::UserInput)
+                            .run_if(resource_exists::<NetworkClient>)
+                            .run_if(resource_exists::<Prediction>),
+                        move_predicted_missiles.in_set(InGameSet::EntityUpdates),
+                    )
+                        .run_if(in_state(AppState::Online)),
+                );
+            }
+            NetworkRole::Spectator { server, conditions } => {
+                match NetworkClient::spectate(*server, *conditions) {
+                    Ok(client) => {
+                        app.insert_resource(client);
+                    }
+                    Err(error) => {
+                        exit_with_error(format!("Couldn't open a socket for {server}: {error}"))
+                    }
+                }
+
+                Self::add_online_systems(app);
+                app.add_plugins(SpectatorPlugin);
+            }
+            NetworkRole::Replay { path } => {
+                match ReplayPlayer::open(path) {
+                    Ok(replay) => {
+                        app.insert_resource(replay);
+                    }
+                    Err(error) => exit_with_error(format!(
+                        "Couldn't open the replay {}: {error}",
+                        path.display()
+                    )),
+                }
+
+                Self::add_online_systems(app);
+                app.add_plugins(SpectatorPlugin);
+            }
+        }
+    }
+}
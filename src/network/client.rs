@@ -0,0 +1,442 @@
+use bevy::{prelude::*, utils::HashMap};
+use std::{
+    collections::VecDeque,
+    io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    time::Duration,
+};
+
+use crate::{
+    application::AppState,
+    asset_loader::SceneAssets,
+    asteroid::Wave,
+    camera::CameraTarget,
+    difficulty::Difficulty,
+    end_game::DespawnAtEndgame,
+    movement::{Acceleration, Velocity},
+    player::PlayerId,
+    score::Score,
+    settings::Settings,
+    spaceship::{
+        self,
+        health::Health,
+        lives::{Invulnerable, Lives},
+    },
+    stats::RunStats,
+    ui::{BlipKind, RadarBlip},
+};
+
+use super::{
+    prediction::{claim_predicted_missile, reconcile, Predicted, PredictedMissile, Prediction},
+    protocol::{
+        ClientMessage, EntityKind, EntitySnapshot, Link, LinkConditions, NetworkId, ServerMessage,
+        Snapshot, SnapshotParts,
+    },
+    replay::ReplayPlayer,
+};
+
+const JOIN_RETRY_INTERVAL: u64 = 1000;
+/// How far behind the newest snapshot entities are drawn, so there's usually
+/// a snapshot on either side to interpolate between.
+const INTERPOLATION_DELAY: f64 = 0.1;
+const MAX_EXTRAPOLATION: f64 = 0.25;
+const SNAPSHOT_BUFFER_LENGTH: usize = 8;
+
+/// A connection to a server, from joining until leaving.
+#[derive(Resource, Debug)]
+pub struct NetworkClient {
+    link: Link,
+    server: SocketAddr,
+    pub player: Option<PlayerId>,
+    spectating: bool,
+    join_timer: Timer,
+    last_tick: u32,
+    snapshot_parts: SnapshotParts,
+    /// The tick of the last game over, so its repeats are ignored.
+    last_game_over: Option<u32>,
+}
+
+impl NetworkClient {
+    pub fn connect(server: SocketAddr, conditions: LinkConditions) -> io::Result<Self> {
+        let any: IpAddr = if server.is_ipv4() {
+            Ipv4Addr::UNSPECIFIED.into()
+        } else {
+            Ipv6Addr::UNSPECIFIED.into()
+        };
+
+        Ok(Self {
+            link: Link::bind(SocketAddr::new(any, 0), conditions)?,
+            server,
+            player: None,
+            spectating: false,
+            join_timer: Timer::new(
+                Duration::from_millis(JOIN_RETRY_INTERVAL),
+                TimerMode::Repeating,
+            ),
+            last_tick: 0,
+            snapshot_parts: SnapshotParts::default(),
+            last_game_over: None,
+        })
+    }
+
+    pub fn spectate(server: SocketAddr, conditions: LinkConditions) -> io::Result<Self> {
+        Ok(Self {
+            spectating: true,
+            ..Self::connect(server, conditions)?
+        })
+    }
+
+    fn hello(&self) -> ClientMessage {
+        if self.spectating {
+            ClientMessage::Spectate
+        } else {
+            ClientMessage::Join
+        }
+    }
+
+    pub fn send(&mut self, message: &ClientMessage) {
+        let server = self.server;
+        self.link.send(server, message);
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Sample {
+    time: f64,
+    transform: Transform,
+    velocity: Vec3,
+}
+
+/// Recent server positions of an entity, drawn [`INTERPOLATION_DELAY`] in the past.
+#[derive(Component, Debug, Default)]
+pub struct SnapshotBuffer {
+    samples: VecDeque<Sample>,
+}
+
+impl SnapshotBuffer {
+    fn push(&mut self, sample: Sample) {
+        self.samples.push_back(sample);
+
+        if self.samples.len() > SNAPSHOT_BUFFER_LENGTH {
+            self.samples.pop_front();
+        }
+    }
+
+    fn sample(&self, time: f64) -> Option<Transform> {
+        let first = self.samples.front()?;
+        let last = self.samples.back()?;
+
+        if time <= first.time {
+            return Some(first.transform);
+        }
+
+        if time >= last.time {
+            let ahead = (time - last.time).min(MAX_EXTRAPOLATION) as f32;
+            let mut transform = last.transform;
+            transform.translation += last.velocity * ahead;
+            return Some(transform);
+        }
+
+        let (from, to) = self
+            .samples
+            .iter()
+            .zip(self.samples.iter().skip(1))
+            .find(|(from, to)| from.time <= time && time <= to.time)?;
+
+        let t = ((time - from.time) / (to.time - from.time)) as f32;
+
+        Some(Transform {
+            translation: from.transform.translation.lerp(to.transform.translation, t),
+            rotation: from.transform.rotation.slerp(to.transform.rotation, t),
+            scale: from.transform.scale.lerp(to.transform.scale, t),
+        })
+    }
+}
+
+pub fn join_server(mut client: ResMut<NetworkClient>) {
+    info!("Joining {}", client.server);
+    let hello = client.hello();
+    client.send(&hello);
+}
+
+/// Joining is repeated until the server answers, in case either message was
+/// lost. Spectators never get a player, so for them it's a keepalive.
+pub fn retry_join(mut client: ResMut<NetworkClient>, time: Res<Time>) {
+    if client.player.is_none() && client.join_timer.tick(time.delta()).just_finished() {
+        let hello = client.hello();
+        client.send(&hello);
+    }
+}
+
+pub fn receive_server_messages(
+    mut commands: Commands,
+    mut client: ResMut<NetworkClient>,
+    mut app_state: ResMut<NextState<AppState>>,
+    mut score: ResMut<Score>,
+    mut wave: ResMut<Wave>,
+    real_time: Res<Time<Real>>,
+) {
+    let mut latest = None;
+
+    client.link.flush(&real_time);
+
+    for (address, message) in client.link.receive::<ServerMessage>() {
+        if address != client.server {
+            continue;
+        }
+
+        match message {
+            ServerMessage::Welcome {
+                player,
+                control_scheme,
+                arena_mode,
+            } => {
+                if client.player.is_none() {
+                    info!("Joined {} as {player}", client.server);
+                    commands.insert_resource(Prediction::new(control_scheme, arena_mode));
+                }
+                client.player = Some(player);
+            }
+            ServerMessage::Full => {
+                error!("{} is full", client.server);
+                commands.remove_resource::<NetworkClient>();
+                app_state.set(AppState::MainMenu);
+                return;
+            }
+            ServerMessage::Snapshot {
+                part,
+                parts,
+                snapshot,
+            } => {
+                let Some(snapshot) = client.snapshot_parts.receive(part, parts, snapshot) else {
+                    continue;
+                };
+
+                // Datagrams can arrive out of order, only the newest matters.
+                if snapshot.tick > client.last_tick {
+                    client.last_tick = snapshot.tick;
+                    latest = Some(snapshot);
+                }
+            }
+            ServerMessage::GameOver {
+                tick,
+                score: points,
+                difficulty,
+                wave: reached,
+                stats,
+            } => {
+                if client.last_game_over == Some(tick) {
+                    continue;
+                }
+                client.last_game_over = Some(tick);
+
+                info!("The game on {} is over", client.server);
+                *score = Score::new(difficulty);
+                score.set_points(points);
+                wave.number = reached;
+                commands.insert_resource(stats);
+                app_state.set(AppState::EndGame);
+                return;
+            }
+        }
+    }
+
+    if let Some(snapshot) = latest {
+        commands.add(move |world: &mut World| apply_snapshot(world, snapshot));
+    }
+}
+
+pub fn apply_snapshot(world: &mut World, snapshot: Snapshot) {
+    let now = world.resource::<Time>().elapsed_seconds_f64();
+
+    world.resource_mut::<Score>().set_points(snapshot.score);
+    world.resource_mut::<Wave>().number = snapshot.wave;
+
+    let local_player = world
+        .get_resource::<NetworkClient>()
+        .and_then(|client| client.player);
+
+    let mut known = world.query::<(Entity, &NetworkId)>();
+    let mut entities: HashMap<NetworkId, Entity> = known
+        .iter(world)
+        .map(|(entity, &id)| (id, entity))
+        .collect();
+
+    for entity_snapshot in snapshot.entities {
+        let sample = Sample {
+            time: now,
+            transform: entity_snapshot.transform,
+            velocity: entity_snapshot.velocity,
+        };
+
+        let local = match entity_snapshot.kind {
+            EntityKind::Spaceship(player) | EntityKind::Missile(player) => {
+                Some(player) == local_player
+            }
+            EntityKind::Asteroid(_) => false,
+        };
+
+        let entity = match entities.remove(&entity_snapshot.id) {
+            Some(entity) => entity,
+            None if local && matches!(entity_snapshot.kind, EntityKind::Missile(_)) => {
+                claim_predicted_missile(world, &entity_snapshot)
+                    .unwrap_or_else(|| spawn_networked_entity(world, &entity_snapshot, false))
+            }
+            None => spawn_networked_entity(world, &entity_snapshot, local),
+        };
+
+        if world.entity(entity).contains::<Predicted>() {
+            reconcile(world, entity, &entity_snapshot);
+        }
+
+        let mut entity = world.entity_mut(entity);
+
+        if let Some(mut buffer) = entity.get_mut::<SnapshotBuffer>() {
+            buffer.push(sample);
+        }
+
+        if !entity.contains::<Predicted>() {
+            if let Some(mut velocity) = entity.get_mut::<Velocity>() {
+                velocity.value = entity_snapshot.velocity;
+            }
+        }
+
+        if let Some(mut visibility) = entity.get_mut::<Visibility>() {
+            *visibility = if entity_snapshot.hidden {
+                Visibility::Hidden
+            } else {
+                Visibility::Inherited
+            };
+        }
+
+        if let (Some(health), Some(mut current)) =
+            (entity_snapshot.health, entity.get_mut::<Health>())
+        {
+            *current = health;
+        }
+
+        if let (Some(points), Some(mut score)) = (entity_snapshot.score, entity.get_mut::<Score>())
+        {
+            score.set_points(points);
+        }
+
+        if let (Some(remaining), Some(mut lives)) =
+            (entity_snapshot.lives, entity.get_mut::<Lives>())
+        {
+            lives.set_remaining(remaining);
+        }
+
+        // Only marks the spaceship, the server times it.
+        match (
+            entity_snapshot.invulnerable,
+            entity.contains::<Invulnerable>(),
+        ) {
+            (true, false) => {
+                entity.insert(Invulnerable::after_hit());
+            }
+            (false, true) => {
+                entity.remove::<Invulnerable>();
+            }
+            _ => {}
+        }
+    }
+
+    // Anything the server didn't mention has been destroyed.
+    for entity in entities.into_values() {
+        world.entity_mut(entity).despawn_recursive();
+    }
+}
+
+/// The local player's spaceship is predicted instead of interpolated.
+fn spawn_networked_entity(world: &mut World, snapshot: &EntitySnapshot, local: bool) -> Entity {
+    let assets = world.resource::<SceneAssets>();
+
+    let scene = match snapshot.kind {
+        EntityKind::Spaceship(_) => assets.spaceship.clone(),
+        EntityKind::Asteroid(_) => assets.asteroids.clone(),
+        EntityKind::Missile(_) => assets.missiles.clone(),
+    };
+
+    let mut entity = world.spawn((
+        SceneBundle {
+            scene,
+            transform: snapshot.transform,
+            ..default()
+        },
+        Velocity::new(snapshot.velocity),
+        snapshot.id,
+        DespawnAtEndgame,
+    ));
+
+    if local {
+        entity.insert((
+            Predicted::default(),
+            Acceleration::new(Vec3::ZERO),
+            CameraTarget,
+        ));
+    } else {
+        entity.insert(SnapshotBuffer::default());
+    }
+
+    match snapshot.kind {
+        EntityKind::Spaceship(player) => {
+            entity.insert((
+                player,
+                spaceship::health_bar(),
+                snapshot.health.unwrap_or_default(),
+                Lives::default(),
+                Score::new(Difficulty::default()),
+                RadarBlip(BlipKind::Player),
+            ));
+        }
+        EntityKind::Asteroid(_) => {
+            entity.insert(RadarBlip(BlipKind::Asteroid));
+        }
+        EntityKind::Missile(_) => {}
+    }
+
+    entity.id()
+}
+
+pub fn interpolate_entities(mut query: Query<(&SnapshotBuffer, &mut Transform)>, time: Res<Time>) {
+    let render_time = time.elapsed_seconds_f64() - INTERPOLATION_DELAY;
+
+    for (buffer, mut transform) in query.iter_mut() {
+        if let Some(sampled) = buffer.sample(render_time) {
+            *transform = sampled;
+        }
+    }
+}
+
+/// Leaves the server, or stops watching a replay.
+pub fn leave_server(
+    mut commands: Commands,
+    client: Option<ResMut<NetworkClient>>,
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    settings: Res<Settings>,
+    mut app_state: ResMut<NextState<AppState>>,
+) {
+    if !keyboard_input.just_pressed(settings.controls.pause) {
+        return;
+    }
+
+    if let Some(mut client) = client {
+        info!("Leaving {}", client.server);
+        client.send(&ClientMessage::Leave);
+    }
+
+    commands.remove_resource::<NetworkClient>();
+    commands.remove_resource::<ReplayPlayer>();
+    app_state.set(AppState::MainMenu);
+}
+
+pub fn despawn_networked_entities(
+    mut commands: Commands,
+    query: Query<Entity, Or<(With<NetworkId>, With<PredictedMissile>)>>,
+) {
+    commands.remove_resource::<Prediction>();
+
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
@@ -0,0 +1,252 @@
+use bevy::prelude::*;
+use std::{collections::VecDeque, time::Duration};
+
+use crate::{
+    asset_loader::SceneAssets,
+    end_game::DespawnAtEndgame,
+    movement::{self, Acceleration, Velocity},
+    player::{PlayerId, StepInput, StepInputs},
+    settings::{Action, ArenaMode, ControlScheme},
+    spaceship::{self, WEAPON_TIMER},
+};
+
+use super::{
+    client::NetworkClient,
+    protocol::{ClientMessage, EntitySnapshot, InputFrame},
+};
+
+/// About two seconds of frames, more than any round trip worth playing on.
+const INPUT_HISTORY_LENGTH: usize = 128;
+/// How many of the newest frames each input message carries.
+const REDUNDANT_INPUTS: usize = 6;
+/// A predicted missile the server hasn't confirmed by then was never fired.
+const MISSILE_CLAIM_TIMEOUT: u64 = 1000;
+
+/// Input the server may not have applied yet, and the rules to replay it by.
+#[derive(Resource, Debug)]
+pub struct Prediction {
+    next_sequence: u32,
+    history: VecDeque<InputFrame>,
+    control_scheme: ControlScheme,
+    arena_mode: ArenaMode,
+    weapon_timer: Timer,
+}
+
+impl Prediction {
+    pub fn new(control_scheme: ControlScheme, arena_mode: ArenaMode) -> Self {
+        let weapon_cooldown = Duration::from_millis(WEAPON_TIMER);
+        let mut weapon_timer = Timer::new(weapon_cooldown, TimerMode::Once);
+        weapon_timer.tick(weapon_cooldown);
+
+        Self {
+            next_sequence: 0,
+            history: VecDeque::new(),
+            control_scheme,
+            arena_mode,
+            weapon_timer,
+        }
+    }
+
+    /// The same step the server's fixed update runs for a spaceship.
+    fn step(
+        &self,
+        transform: &mut Transform,
+        velocity: &mut Vec3,
+        acceleration: &mut Vec3,
+        input: StepInput,
+        delta: f32,
+    ) {
+        spaceship::steer(
+            transform,
+            velocity,
+            acceleration,
+            input,
+            self.control_scheme,
+            delta,
+        );
+        movement::integrate(&mut transform.translation, velocity, *acceleration, delta);
+        spaceship::wrap_translation(&mut transform.translation, self.arena_mode);
+    }
+}
+
+/// The local player's spaceship, moved by their input straight away instead
+/// of waiting to hear back from the server.
+#[derive(Component, Debug, Default)]
+pub struct Predicted {
+    respawning: bool,
+}
+
+/// A missile the local player fired, flown locally from the moment it left
+/// the spaceship.
+#[derive(Component, Debug)]
+pub struct PredictedMissile;
+
+/// A predicted missile the server hasn't mentioned yet.
+#[derive(Component, Debug)]
+pub struct Unclaimed {
+    timer: Timer,
+}
+
+pub fn predict_local_spaceship(
+    mut client: ResMut<NetworkClient>,
+    mut prediction: ResMut<Prediction>,
+    inputs: Res<StepInputs>,
+    mut query: Query<(&mut Transform, &mut Velocity, &mut Acceleration, &Predicted)>,
+    time: Res<Time>,
+) {
+    // Whoever is at this machine plays with the first player's controls,
+    // whichever player the server gave them.
+    let input = inputs.get(PlayerId(0));
+    let frame = InputFrame {
+        sequence: prediction.next_sequence,
+        input,
+    };
+
+    prediction.next_sequence += 1;
+    prediction.history.push_back(frame);
+
+    if prediction.history.len() > INPUT_HISTORY_LENGTH {
+        prediction.history.pop_front();
+    }
+
+    let recent = prediction.history.len().saturating_sub(REDUNDANT_INPUTS);
+    client.send(&ClientMessage::Input {
+        frames: prediction.history.range(recent..).copied().collect(),
+    });
+
+    for (mut transform, mut velocity, mut acceleration, predicted) in query.iter_mut() {
+        if !predicted.respawning {
+            prediction.step(
+                &mut transform,
+                &mut velocity.value,
+                &mut acceleration.value,
+                input,
+                time.delta_seconds(),
+            );
+        }
+    }
+}
+
+/// Rolls the local spaceship back to the server's state, then re-simulates
+/// the input the server hadn't applied yet.
+pub fn reconcile(world: &mut World, entity: Entity, snapshot: &EntitySnapshot) {
+    let delta = world.resource::<Time<Fixed>>().delta_seconds();
+    let Some(mut prediction) = world.get_resource_mut::<Prediction>() else {
+        return;
+    };
+
+    if let Some(last_input) = snapshot.last_input {
+        prediction
+            .history
+            .retain(|frame| frame.sequence > last_input);
+    }
+
+    let mut transform = snapshot.transform;
+    let mut velocity = snapshot.velocity;
+    let mut acceleration = Vec3::ZERO;
+
+    if !snapshot.respawning {
+        for frame in prediction.history.iter() {
+            prediction.step(
+                &mut transform,
+                &mut velocity,
+                &mut acceleration,
+                frame.input,
+                delta,
+            );
+        }
+    }
+
+    let mut entity = world.entity_mut(entity);
+    entity.insert((
+        transform,
+        Velocity::new(velocity),
+        Acceleration::new(acceleration),
+        Predicted {
+            respawning: snapshot.respawning,
+        },
+    ));
+}
+
+pub fn predict_missiles(
+    mut commands: Commands,
+    mut prediction: ResMut<Prediction>,
+    inputs: Res<StepInputs>,
+    spaceships: Query<(&Transform, &Predicted)>,
+    missiles: Query<(), With<PredictedMissile>>,
+    assets: Res<SceneAssets>,
+    time: Res<Time>,
+) {
+    prediction.weapon_timer.tick(time.delta());
+
+    // The server lets a spaceship fire again once its missile is gone or its
+    // weapon timer has run out.
+    if !inputs.get(PlayerId(0)).just_pressed(Action::Fire)
+        || !(missiles.is_empty() || prediction.weapon_timer.finished())
+    {
+        return;
+    }
+
+    for (spaceship_transform, predicted) in spaceships.iter() {
+        if predicted.respawning {
+            continue;
+        }
+
+        let (transform, velocity) = spaceship::missile_launch(spaceship_transform);
+
+        commands.spawn((
+            PredictedMissile,
+            Unclaimed {
+                timer: Timer::new(
+                    Duration::from_millis(MISSILE_CLAIM_TIMEOUT),
+                    TimerMode::Once,
+                ),
+            },
+            SceneBundle {
+                scene: assets.missiles.clone(),
+                transform,
+                ..default()
+            },
+            velocity,
+            DespawnAtEndgame,
+        ));
+        prediction.weapon_timer.reset();
+    }
+}
+
+pub fn move_predicted_missiles(
+    mut query: Query<(&Velocity, &mut Transform), With<PredictedMissile>>,
+    time: Res<Time>,
+) {
+    for (velocity, mut transform) in query.iter_mut() {
+        transform.translation += velocity.value * time.delta_seconds();
+    }
+}
+
+/// Hands a new missile of the local player to the oldest predicted one, which
+/// is already flying where the server's is.
+pub fn claim_predicted_missile(world: &mut World, snapshot: &EntitySnapshot) -> Option<Entity> {
+    let mut unclaimed = world.query::<(Entity, &Unclaimed)>();
+    let (entity, _) = unclaimed
+        .iter(world)
+        .max_by_key(|(_, unclaimed)| unclaimed.timer.elapsed())?;
+
+    world
+        .entity_mut(entity)
+        .remove::<Unclaimed>()
+        .insert(snapshot.id);
+
+    Some(entity)
+}
+
+pub fn expire_unclaimed_missiles(
+    mut commands: Commands,
+    mut query: Query<(Entity, &mut Unclaimed)>,
+    time: Res<Time>,
+) {
+    for (entity, mut unclaimed) in query.iter_mut() {
+        if unclaimed.timer.tick(time.delta()).finished() {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
@@ -0,0 +1,381 @@
+use bevy::{
+    prelude::*,
+    utils::{Duration, Instant},
+};
+use bincode::Options;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{
+    io,
+    net::{SocketAddr, UdpSocket},
+};
+
+use crate::{
+    asteroid::AsteroidSize,
+    difficulty::Difficulty,
+    player::{PlayerId, StepInput},
+    settings::{ArenaMode, ControlScheme},
+    spaceship::health::Health,
+    stats::RunStats,
+};
+
+/// Small enough that a datagram crosses a 1500 byte MTU without being
+/// fragmented, since losing any fragment would lose all of it.
+pub const MAX_MESSAGE_SIZE: usize = 1200;
+/// Leaves room for the rest of a snapshot message around its entities.
+const MAX_SNAPSHOT_PART_SIZE: usize = MAX_MESSAGE_SIZE - 64;
+
+/// Messages go over the wire as compact binary. Anything that claims to be
+/// bigger than a datagram is rejected before it's read.
+fn wire_format() -> impl Options {
+    bincode::DefaultOptions::new().with_limit(MAX_MESSAGE_SIZE as u64)
+}
+
+pub fn encode<T: Serialize>(message: &T) -> bincode::Result<Vec<u8>> {
+    wire_format().serialize(message)
+}
+
+fn decode<T: DeserializeOwned>(bytes: &[u8]) -> bincode::Result<T> {
+    wire_format().deserialize(bytes)
+}
+
+/// A client's input for one step of the simulation, numbered so the server
+/// can say which steps it has applied.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct InputFrame {
+    pub sequence: u32,
+    pub input: StepInput,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub enum ClientMessage {
+    Join,
+    /// The newest input frames, oldest first. Recent frames are repeated in
+    /// every message so a lost datagram doesn't lose input.
+    Input {
+        frames: Vec<InputFrame>,
+    },
+    /// Watch without a spaceship. Spectators send nothing else, so they
+    /// repeat this to stay connected.
+    Spectate,
+    Leave,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub enum ServerMessage {
+    /// Along with the rules a client needs to predict its own spaceship.
+    Welcome {
+        player: PlayerId,
+        control_scheme: ControlScheme,
+        arena_mode: ArenaMode,
+    },
+    /// Every spaceship already has a player.
+    Full,
+    /// Snapshots too big for one datagram are split into several parts,
+    /// each with some of the entities.
+    Snapshot {
+        part: u16,
+        parts: u16,
+        snapshot: Snapshot,
+    },
+    /// The run is over and the server is starting another. Sent a few times
+    /// in case some are lost, all with the same tick.
+    GameOver {
+        tick: u32,
+        score: usize,
+        difficulty: Difficulty,
+        wave: u32,
+        stats: RunStats,
+    },
+}
+
+/// Identifies an entity across the server and its clients, since their
+/// [`Entity`] ids don't line up.
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct NetworkId(pub u32);
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum EntityKind {
+    Spaceship(PlayerId),
+    Asteroid(AsteroidSize),
+    Missile(PlayerId),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EntitySnapshot {
+    pub id: NetworkId,
+    pub kind: EntityKind,
+    pub transform: Transform,
+    pub velocity: Vec3,
+    /// Respawning and blinking spaceships.
+    pub hidden: bool,
+    /// Respawning spaceships don't respond to input.
+    pub respawning: bool,
+    pub invulnerable: bool,
+    pub health: Option<Health>,
+    pub lives: Option<u32>,
+    pub score: Option<usize>,
+    /// The last input frame applied to a client's spaceship, which its
+    /// prediction re-simulates from.
+    pub last_input: Option<u32>,
+}
+
+/// The state of every networked entity on one server tick.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub tick: u32,
+    pub score: usize,
+    pub wave: u32,
+    pub entities: Vec<EntitySnapshot>,
+}
+
+impl Snapshot {
+    /// The messages to send this snapshot in, as few as will each fit in a
+    /// datagram.
+    pub fn into_messages(self) -> Vec<ServerMessage> {
+        let mut groups: Vec<(Vec<EntitySnapshot>, usize)> = Vec::new();
+
+        for entity in self.entities {
+            let size = wire_format()
+                .serialized_size(&entity)
+                .map_or(0, |size| size as usize);
+
+            match groups.last_mut() {
+                Some((group, group_size)) if *group_size + size <= MAX_SNAPSHOT_PART_SIZE => {
+                    group.push(entity);
+                    *group_size += size;
+                }
+                _ => groups.push((vec![entity], size)),
+            }
+        }
+
+        if groups.is_empty() {
+            groups.push((Vec::new(), 0));
+        }
+
+        let parts = groups.len() as u16;
+
+        groups
+            .into_iter()
+            .enumerate()
+            .map(|(part, (entities, _))| ServerMessage::Snapshot {
+                part: part as u16,
+                parts,
+                snapshot: Snapshot {
+                    tick: self.tick,
+                    score: self.score,
+                    wave: self.wave,
+                    entities,
+                },
+            })
+            .collect()
+    }
+}
+
+/// Puts split snapshots back together. Parts of a tick are dropped once a
+/// part of a newer one arrives, so losing one loses that whole tick.
+#[derive(Debug, Default)]
+pub struct SnapshotParts {
+    tick: u32,
+    received: Vec<(u16, Snapshot)>,
+}
+
+impl SnapshotParts {
+    /// The whole snapshot, once this was its last missing part.
+    pub fn receive(&mut self, part: u16, parts: u16, snapshot: Snapshot) -> Option<Snapshot> {
+        if parts <= 1 {
+            return Some(snapshot);
+        }
+
+        if snapshot.tick < self.tick {
+            return None;
+        }
+
+        if snapshot.tick > self.tick {
+            self.tick = snapshot.tick;
+            self.received.clear();
+        }
+
+        if self.received.iter().any(|&(received, _)| received == part) {
+            return None;
+        }
+
+        self.received.push((part, snapshot));
+
+        if self.received.len() < parts as usize {
+            return None;
+        }
+
+        let mut pieces = std::mem::take(&mut self.received)
+            .into_iter()
+            .map(|(_, piece)| piece);
+        let mut whole = pieces.next()?;
+
+        for piece in pieces {
+            whole.entities.extend(piece.entities);
+        }
+
+        Some(whole)
+    }
+}
+
+/// Simulated network trouble for trying out prediction, set with
+/// `--latency MS`, `--jitter MS` and `--packet-loss PERCENT`. It applies to
+/// what this instance sends, so give the flags to both ends for a slow round
+/// trip.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct LinkConditions {
+    pub latency: Duration,
+    pub jitter: Duration,
+    /// Chance from 0 to 1 that a datagram is dropped.
+    pub packet_loss: f32,
+    /// Makes which datagrams are dropped, and how late the rest are, the same
+    /// every time, for tests.
+    pub seed: Option<u64>,
+}
+
+impl LinkConditions {
+    pub fn from_args(args: &[String]) -> Self {
+        let value = |flag: &str| {
+            args.iter()
+                .position(|arg| arg == flag)
+                .and_then(|index| args.get(index + 1))
+                .and_then(|value| value.parse::<f32>().ok())
+                .map(|value| value.max(0.))
+        };
+
+        Self {
+            latency: Duration::from_secs_f32(value("--latency").unwrap_or_default() / 1000.),
+            jitter: Duration::from_secs_f32(value("--jitter").unwrap_or_default() / 1000.),
+            packet_loss: (value("--packet-loss").unwrap_or_default() / 100.).min(1.),
+            seed: None,
+        }
+    }
+
+    fn is_perfect(&self) -> bool {
+        self.latency.is_zero() && self.jitter.is_zero() && self.packet_loss == 0.
+    }
+}
+
+#[derive(Debug)]
+struct DelayedDatagram {
+    due: Instant,
+    address: SocketAddr,
+    bytes: Vec<u8>,
+}
+
+/// A non-blocking socket that sends through the [`LinkConditions`]. Its
+/// clock only moves on with [`Link::flush_at`], so delays are measured in
+/// whatever time the caller steps through.
+#[derive(Debug)]
+pub struct Link {
+    socket: UdpSocket,
+    conditions: LinkConditions,
+    rng: StdRng,
+    now: Instant,
+    delayed: Vec<DelayedDatagram>,
+}
+
+impl Link {
+    pub fn bind(address: SocketAddr, conditions: LinkConditions) -> io::Result<Self> {
+        let socket = UdpSocket::bind(address)?;
+        socket.set_nonblocking(true)?;
+
+        if !conditions.is_perfect() {
+            info!("Simulating {conditions:?} on {address}");
+        }
+
+        Ok(Self {
+            socket,
+            conditions,
+            rng: conditions
+                .seed
+                .map_or_else(StdRng::from_entropy, StdRng::seed_from_u64),
+            now: Instant::now(),
+            delayed: Vec::new(),
+        })
+    }
+
+    pub fn send<T: Serialize>(&mut self, address: SocketAddr, message: &T) {
+        // Messages too big for a datagram fail to encode.
+        let bytes = match encode(message) {
+            Ok(bytes) => bytes,
+            Err(error) => {
+                error!("Couldn't send a message to {address}: {error}");
+                return;
+            }
+        };
+
+        if self.conditions.is_perfect() {
+            self.send_bytes(address, &bytes);
+            return;
+        }
+
+        if self.rng.gen::<f32>() < self.conditions.packet_loss {
+            return;
+        }
+
+        let jitter = self.conditions.jitter.mul_f32(self.rng.gen());
+
+        self.delayed.push(DelayedDatagram {
+            due: self.now + self.conditions.latency + jitter,
+            address,
+            bytes,
+        });
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    /// Moves the clock on to `now` and sends any delayed datagrams that are
+    /// due by then. Whatever is sent next is delayed from `now`.
+    pub fn flush_at(&mut self, now: Instant) {
+        self.now = now;
+
+        let (due, waiting): (Vec<_>, Vec<_>) = std::mem::take(&mut self.delayed)
+            .into_iter()
+            .partition(|datagram| datagram.due <= now);
+        self.delayed = waiting;
+
+        for datagram in due {
+            self.send_bytes(datagram.address, &datagram.bytes);
+        }
+    }
+
+    fn send_bytes(&self, address: SocketAddr, bytes: &[u8]) {
+        if let Err(error) = self.socket.send_to(bytes, address) {
+            warn!("Couldn't send to {address}: {error}");
+        }
+    }
+
+    /// Steps the clock with the app's real time, once a frame.
+    pub fn flush(&mut self, time: &Time<Real>) {
+        self.flush_at(time.last_update().unwrap_or(time.startup()));
+    }
+
+    /// Everything waiting on the socket. Malformed datagrams are dropped with
+    /// a warning.
+    pub fn receive<T: DeserializeOwned>(&mut self) -> Vec<(SocketAddr, T)> {
+        let mut buffer = [0; MAX_MESSAGE_SIZE];
+        let mut messages = Vec::new();
+
+        loop {
+            let (length, address) = match self.socket.recv_from(&mut buffer) {
+                Ok(received) => received,
+                Err(error) if error.kind() == io::ErrorKind::WouldBlock => break,
+                Err(error) => {
+                    warn!("Couldn't receive: {error}");
+                    break;
+                }
+            };
+
+            match decode(&buffer[..length]) {
+                Ok(message) => messages.push((address, message)),
+                Err(error) => warn!("Ignoring a malformed message from {address}: {error}"),
+            }
+        }
+
+        messages
+    }
+}
@@ -0,0 +1,111 @@
+use bevy::{asset::ron, prelude::*};
+use std::{
+    collections::VecDeque,
+    fs::File,
+    io::{self, BufRead, BufReader, LineWriter, Write},
+    path::Path,
+};
+
+use super::{client::apply_snapshot, protocol::Snapshot};
+
+/// A snapshot and when, in seconds since recording started, the server took
+/// it. Replay files have one per line.
+#[derive(Debug)]
+struct ReplayFrame {
+    time: f64,
+    snapshot: Snapshot,
+}
+
+/// Every snapshot a server sent, written with `--record FILE`.
+#[derive(Resource, Debug)]
+pub struct ReplayRecorder {
+    writer: LineWriter<File>,
+    started: Option<f64>,
+}
+
+impl ReplayRecorder {
+    pub fn create(path: &Path) -> io::Result<Self> {
+        Ok(Self {
+            writer: LineWriter::new(File::create(path)?),
+            started: None,
+        })
+    }
+
+    pub fn record(&mut self, now: f64, snapshot: &Snapshot) {
+        let started = *self.started.get_or_insert(now);
+        let frame = ron::to_string(&(now - started, snapshot));
+
+        let written = match frame {
+            Ok(frame) => writeln!(self.writer, "{frame}"),
+            Err(error) => {
+                error!("Couldn't serialize a replay frame: {error}");
+                return;
+            }
+        };
+
+        if let Err(error) = written {
+            error!("Couldn't record a replay frame: {error}");
+        }
+    }
+}
+
+/// A replay file played back with `--replay FILE`.
+#[derive(Resource, Debug)]
+pub struct ReplayPlayer {
+    frames: VecDeque<ReplayFrame>,
+    length: usize,
+    started: Option<f64>,
+}
+
+impl ReplayPlayer {
+    /// Lines that don't parse, e.g. the last one of a server that was killed
+    /// mid-write, are skipped.
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let mut frames = VecDeque::new();
+
+        for line in BufReader::new(File::open(path)?).lines() {
+            match ron::from_str::<(f64, Snapshot)>(&line?) {
+                Ok((time, snapshot)) => frames.push_back(ReplayFrame { time, snapshot }),
+                Err(error) => warn!("Skipping a broken replay frame: {error}"),
+            }
+        }
+
+        Ok(Self {
+            length: frames.len(),
+            frames,
+            started: None,
+        })
+    }
+
+    pub fn progress(&self) -> f32 {
+        if self.length == 0 {
+            return 1.;
+        }
+
+        1. - self.frames.len() as f32 / self.length as f32
+    }
+
+    pub fn finished(&self) -> bool {
+        self.frames.is_empty()
+    }
+}
+
+pub fn play_replay(mut commands: Commands, mut replay: ResMut<ReplayPlayer>, time: Res<Time>) {
+    let now = time.elapsed_seconds_f64();
+    let started = *replay.started.get_or_insert(now);
+
+    let mut latest = None;
+
+    // Like over the network, only the newest snapshot matters.
+    while replay
+        .frames
+        .front()
+        .is_some_and(|frame| frame.time <= now - started)
+    {
+        latest = replay.frames.pop_front();
+    }
+
+    if let Some(frame) = latest {
+        commands.add(move |world: &mut World| apply_snapshot(world, frame.snapshot));
+    }
+}
@@ -0,0 +1,410 @@
+use bevy::{ecs::query::QueryData, prelude::*, utils::HashMap};
+use std::{collections::VecDeque, io, net::SocketAddr, time::Duration};
+
+use crate::{
+    application::AppState,
+    asteroid::{Asteroid, AsteroidSize, Wave},
+    movement::Velocity,
+    player::{PlayerId, PlayerSetup, RemoteInputs, StepInput, StepInputs},
+    score::Score,
+    settings::Settings,
+    spaceship::{
+        health::Health,
+        lives::{Invulnerable, Lives, Respawning},
+        Missile, Spaceship,
+    },
+    stats::RunStats,
+};
+
+use super::{
+    protocol::{
+        ClientMessage, EntityKind, EntitySnapshot, InputFrame, Link, LinkConditions, NetworkId,
+        ServerMessage, Snapshot,
+    },
+    replay::ReplayRecorder,
+};
+
+const SNAPSHOT_INTERVAL: u64 = 50;
+/// How many copies of the end of a game go out, since nothing is resent.
+const GAME_OVER_REPEATS: usize = 3;
+const CLIENT_TIMEOUT: f32 = 10.0;
+/// More queued input than this means the client got ahead, e.g. after a
+/// stall, and the oldest frames are skipped to catch up.
+const MAX_QUEUED_INPUTS: usize = 8;
+
+#[derive(Debug)]
+struct ConnectedClient {
+    player: PlayerId,
+    last_heard: f32,
+    /// Input frames waiting for their simulation step.
+    queued: VecDeque<InputFrame>,
+    last_received: Option<u32>,
+    last_applied: Option<u32>,
+    held: StepInput,
+}
+
+impl ConnectedClient {
+    fn new(player: PlayerId, now: f32) -> Self {
+        Self {
+            player,
+            last_heard: now,
+            queued: VecDeque::new(),
+            last_received: None,
+            last_applied: None,
+            held: StepInput::default(),
+        }
+    }
+
+    fn receive(&mut self, frame: InputFrame) -> bool {
+        if self
+            .last_received
+            .is_some_and(|last| frame.sequence <= last)
+        {
+            return false;
+        }
+
+        self.last_received = Some(frame.sequence);
+        self.queued.push_back(frame);
+        true
+    }
+
+    fn next_input(&mut self) -> StepInput {
+        while self.queued.len() > MAX_QUEUED_INPUTS {
+            self.queued.pop_front();
+        }
+
+        self.held = match self.queued.pop_front() {
+            Some(frame) => {
+                self.last_applied = Some(frame.sequence);
+                frame.input
+            }
+            // The frame is late or lost, keep holding the same buttons.
+            None => StepInput {
+                pressed: self.held.pressed,
+                ..default()
+            },
+        };
+
+        self.held
+    }
+}
+
+/// The authoritative side: simulates the game and tells clients about it.
+#[derive(Resource, Debug)]
+pub struct NetworkServer {
+    link: Link,
+    clients: HashMap<SocketAddr, ConnectedClient>,
+    /// When each spectator was last heard from.
+    spectators: HashMap<SocketAddr, f32>,
+    snapshot_timer: Timer,
+    tick: u32,
+    next_id: u32,
+}
+
+impl NetworkServer {
+    pub fn bind(address: SocketAddr, conditions: LinkConditions) -> io::Result<Self> {
+        Ok(Self {
+            link: Link::bind(address, conditions)?,
+            clients: HashMap::default(),
+            spectators: HashMap::default(),
+            snapshot_timer: Timer::new(
+                Duration::from_millis(SNAPSHOT_INTERVAL),
+                TimerMode::Repeating,
+            ),
+            tick: 0,
+            next_id: 0,
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.link.local_addr()
+    }
+
+    fn free_player(&self, players: &PlayerSetup) -> Option<PlayerId> {
+        players
+            .ids()
+            .find(|&player| !self.clients.values().any(|client| client.player == player))
+    }
+}
+
+pub fn receive_client_messages(
+    mut server: ResMut<NetworkServer>,
+    mut inputs: ResMut<RemoteInputs>,
+    players: Res<PlayerSetup>,
+    settings: Res<Settings>,
+    time: Res<Time>,
+    real_time: Res<Time<Real>>,
+) {
+    let server = &mut *server;
+    let now = time.elapsed_seconds();
+
+    server.link.flush(&real_time);
+
+    for (address, message) in server.link.receive::<ClientMessage>() {
+        match message {
+            ClientMessage::Join => {
+                let player = match server.clients.get(&address) {
+                    Some(client) => Some(client.player),
+                    None => server.free_player(&players),
+                };
+
+                let Some(player) = player else {
+                    info!("Turning {address} away, the game is full");
+                    server.link.send(address, &ServerMessage::Full);
+                    continue;
+                };
+
+                server
+                    .clients
+                    .entry(address)
+                    .or_insert_with(|| {
+                        info!("{address} joined as {player}");
+                        ConnectedClient::new(player, now)
+                    })
+                    .last_heard = now;
+
+                server.link.send(
+                    address,
+                    &ServerMessage::Welcome {
+                        player,
+                        control_scheme: settings.gameplay.control_scheme,
+                        arena_mode: settings.gameplay.arena_mode,
+                    },
+                );
+            }
+            ClientMessage::Input { frames } => {
+                if let Some(client) = server.clients.get_mut(&address) {
+                    client.last_heard = now;
+
+                    // Steering waits for its step, firing and shields are
+                    // read every frame.
+                    for frame in frames {
+                        if client.receive(frame) {
+                            inputs.receive(
+                                client.player,
+                                frame.input.pressed,
+                                frame.input.just_pressed,
+                            );
+                        }
+                    }
+                }
+            }
+            ClientMessage::Spectate => {
+                if server.spectators.insert(address, now).is_none() {
+                    info!("{address} is spectating");
+                }
+            }
+            ClientMessage::Leave => {
+                if let Some(client) = server.clients.remove(&address) {
+                    info!("{} left", client.player);
+                    inputs.forget(client.player);
+                }
+
+                if server.spectators.remove(&address).is_some() {
+                    info!("{address} stopped spectating");
+                }
+            }
+        }
+    }
+
+    server.clients.retain(|address, client| {
+        let connected = now - client.last_heard < CLIENT_TIMEOUT;
+
+        if !connected {
+            info!("{address} timed out");
+            inputs.forget(client.player);
+        }
+
+        connected
+    });
+
+    server
+        .spectators
+        .retain(|_, last_heard| now - *last_heard < CLIENT_TIMEOUT);
+}
+
+/// Feeds each client's input frames to the simulation, one per step.
+pub fn apply_client_inputs(mut server: ResMut<NetworkServer>, mut inputs: ResMut<StepInputs>) {
+    for client in server.clients.values_mut() {
+        inputs.set(client.player, client.next_input());
+    }
+}
+
+pub fn assign_network_ids(
+    mut commands: Commands,
+    mut server: ResMut<NetworkServer>,
+    query: Query<
+        Entity,
+        (
+            Without<NetworkId>,
+            Or<(With<Spaceship>, With<Asteroid>, With<Missile>)>,
+        ),
+    >,
+) {
+    for entity in query.iter() {
+        commands.entity(entity).insert(NetworkId(server.next_id));
+        server.next_id += 1;
+    }
+}
+
+#[derive(QueryData)]
+pub struct NetworkedEntity {
+    entity: Entity,
+    id: &'static NetworkId,
+    transform: &'static Transform,
+    velocity: &'static Velocity,
+    visibility: &'static Visibility,
+    size: Option<&'static AsteroidSize>,
+    player: Option<&'static PlayerId>,
+}
+
+#[derive(QueryData)]
+pub struct SpaceshipState {
+    health: &'static Health,
+    score: &'static Score,
+    lives: Option<&'static Lives>,
+    respawning: Has<Respawning>,
+    invulnerable: Has<Invulnerable>,
+}
+
+/// Sent every [`SNAPSHOT_INTERVAL`], for clients, spectators and recording.
+#[derive(Event, Debug, Clone)]
+pub struct SnapshotTaken(pub Snapshot);
+
+pub fn take_snapshot(
+    mut server: ResMut<NetworkServer>,
+    entities: Query<NetworkedEntity>,
+    spaceships: Query<SpaceshipState, With<Spaceship>>,
+    score: Res<Score>,
+    wave: Res<Wave>,
+    time: Res<Time>,
+    mut taken: EventWriter<SnapshotTaken>,
+) {
+    if !server.snapshot_timer.tick(time.delta()).just_finished() {
+        return;
+    }
+
+    server.tick += 1;
+
+    let last_inputs: HashMap<PlayerId, u32> = server
+        .clients
+        .values()
+        .filter_map(|client| Some((client.player, client.last_applied?)))
+        .collect();
+
+    let entities = entities
+        .iter()
+        .filter_map(|networked| {
+            let spaceship = spaceships.get(networked.entity).ok();
+
+            let kind = match (networked.player, networked.size) {
+                (Some(&player), _) if spaceship.is_some() => EntityKind::Spaceship(player),
+                (Some(&player), _) => EntityKind::Missile(player),
+                (None, Some(&size)) => EntityKind::Asteroid(size),
+                (None, None) => return None,
+            };
+
+            Some(EntitySnapshot {
+                id: *networked.id,
+                kind,
+                transform: *networked.transform,
+                velocity: networked.velocity.value,
+                hidden: *networked.visibility == Visibility::Hidden,
+                respawning: spaceship
+                    .as_ref()
+                    .is_some_and(|spaceship| spaceship.respawning),
+                invulnerable: spaceship
+                    .as_ref()
+                    .is_some_and(|spaceship| spaceship.invulnerable),
+                health: spaceship.as_ref().map(|spaceship| *spaceship.health),
+                lives: spaceship
+                    .as_ref()
+                    .and_then(|spaceship| spaceship.lives)
+                    .map(Lives::remaining),
+                score: spaceship.as_ref().map(|spaceship| spaceship.score.points()),
+                last_input: networked
+                    .player
+                    .filter(|_| spaceship.is_some())
+                    .and_then(|player| last_inputs.get(player).copied()),
+            })
+        })
+        .collect();
+
+    taken.send(SnapshotTaken(Snapshot {
+        tick: server.tick,
+        score: score.points(),
+        wave: wave.number,
+        entities,
+    }));
+}
+
+fn addresses(server: &NetworkServer) -> Vec<SocketAddr> {
+    server
+        .clients
+        .keys()
+        .chain(server.spectators.keys())
+        .copied()
+        .collect()
+}
+
+pub fn send_snapshots(mut server: ResMut<NetworkServer>, mut taken: EventReader<SnapshotTaken>) {
+    let addresses = addresses(&server);
+
+    for SnapshotTaken(snapshot) in taken.read() {
+        for message in snapshot.clone().into_messages() {
+            for &address in &addresses {
+                server.link.send(address, &message);
+            }
+        }
+    }
+}
+
+pub fn record_replay(
+    mut recorder: ResMut<ReplayRecorder>,
+    mut taken: EventReader<SnapshotTaken>,
+    time: Res<Time>,
+) {
+    for SnapshotTaken(snapshot) in taken.read() {
+        recorder.record(time.elapsed_seconds_f64(), snapshot);
+    }
+}
+
+/// The server has nobody at the menus, so it goes straight into a game with
+/// the gameplay settings it was started with.
+pub fn start_server_game(
+    mut commands: Commands,
+    mut app_state: ResMut<NextState<AppState>>,
+    settings: Res<Settings>,
+) {
+    info!("Starting a new game for the network");
+    commands.insert_resource(settings.gameplay.difficulty);
+    app_state.set(AppState::InGame);
+}
+
+/// Tells everyone how the run went before the next one starts.
+pub fn send_game_over(
+    mut server: ResMut<NetworkServer>,
+    score: Res<Score>,
+    wave: Res<Wave>,
+    stats: Res<RunStats>,
+) {
+    info!("Game over with {} points", score.points());
+
+    let message = ServerMessage::GameOver {
+        tick: server.tick,
+        score: score.points(),
+        difficulty: score.difficulty(),
+        wave: wave.number,
+        stats: stats.clone(),
+    };
+
+    for address in addresses(&server) {
+        for _ in 0..GAME_OVER_REPEATS {
+            server.link.send(address, &message);
+        }
+    }
+}
+
+pub fn restart_server_game(mut app_state: ResMut<NextState<AppState>>) {
+    app_state.set(AppState::MainMenu);
+}
@@ -0,0 +1,203 @@
+use bevy::prelude::*;
+use bevy_inspector_egui::{
+    bevy_egui::EguiContexts,
+    egui::{self, Color32, RichText},
+};
+
+use crate::{
+    application::AppState,
+    asteroid::Wave,
+    camera,
+    player::PlayerId,
+    score::Score,
+    settings::{key_name, Settings},
+    spaceship::health::Health,
+};
+
+use super::replay::ReplayPlayer;
+
+const PAN_SPEED: f32 = 60.;
+const ZOOM_SPEED: f32 = 80.;
+const MIN_CAMERA_HEIGHT: f32 = 30.;
+const MAX_CAMERA_HEIGHT: f32 = 300.;
+/// How quickly the camera catches up with the followed spaceship.
+const FOLLOW_SMOOTHING: f32 = 5.;
+
+const SWITCH_VIEW: KeyCode = KeyCode::Tab;
+const ZOOM_IN: KeyCode = KeyCode::KeyE;
+const ZOOM_OUT: KeyCode = KeyCode::KeyQ;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+enum SpectatorView {
+    #[default]
+    Free,
+    Follow(PlayerId),
+}
+
+/// Watching a game, live or from a replay, without playing in it.
+#[derive(Resource, Debug, Default)]
+pub struct Spectator {
+    view: SpectatorView,
+}
+
+/// Cycles from the free camera through every player and back.
+fn switch_view(
+    mut spectator: ResMut<Spectator>,
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    spaceships: Query<&PlayerId, With<Health>>,
+) {
+    if !keyboard_input.just_pressed(SWITCH_VIEW) {
+        return;
+    }
+
+    let mut players: Vec<PlayerId> = spaceships.iter().copied().collect();
+    players.sort();
+
+    let next = match spectator.view {
+        SpectatorView::Free => players.first(),
+        SpectatorView::Follow(current) => players.iter().find(|&&player| player > current),
+    };
+
+    spectator.view = next.map_or(SpectatorView::Free, |&player| SpectatorView::Follow(player));
+}
+
+fn move_camera(
+    spectator: Res<Spectator>,
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    mut camera: Query<&mut Transform, With<Camera3d>>,
+    spaceships: Query<(&PlayerId, &Transform), (With<Health>, Without<Camera3d>)>,
+    time: Res<Time>,
+) {
+    let Ok(mut camera) = camera.get_single_mut() else {
+        return;
+    };
+
+    let held = |keys: [KeyCode; 2]| -> f32 {
+        if keyboard_input.any_pressed(keys) {
+            1.
+        } else {
+            0.
+        }
+    };
+    let delta = time.delta_seconds();
+
+    let zoom = held([ZOOM_OUT, KeyCode::PageUp]) - held([ZOOM_IN, KeyCode::PageDown]);
+    camera.translation.y = (camera.translation.y + zoom * ZOOM_SPEED * delta)
+        .clamp(MIN_CAMERA_HEIGHT, MAX_CAMERA_HEIGHT);
+
+    match spectator.view {
+        SpectatorView::Free => {
+            let right = held([KeyCode::KeyD, KeyCode::ArrowRight])
+                - held([KeyCode::KeyA, KeyCode::ArrowLeft]);
+            let up =
+                held([KeyCode::KeyW, KeyCode::ArrowUp]) - held([KeyCode::KeyS, KeyCode::ArrowDown]);
+
+            let pan = camera.right() * right + camera.up() * up;
+            camera.translation += pan * PAN_SPEED * delta;
+        }
+        SpectatorView::Follow(player) => {
+            let Some((_, spaceship)) = spaceships.iter().find(|(&other, _)| other == player) else {
+                return;
+            };
+
+            let target = Vec3::new(
+                spaceship.translation.x,
+                camera.translation.y,
+                spaceship.translation.z,
+            );
+            camera.translation = camera
+                .translation
+                .lerp(target, (FOLLOW_SMOOTHING * delta).min(1.));
+        }
+    }
+}
+
+fn draw_overlay(
+    mut contexts: EguiContexts,
+    spectator: Res<Spectator>,
+    spaceships: Query<(&PlayerId, &Health, &Score)>,
+    score: Res<Score>,
+    wave: Res<Wave>,
+    replay: Option<Res<ReplayPlayer>>,
+    settings: Res<Settings>,
+) {
+    let Some(ctx) = contexts.try_ctx_mut() else {
+        return;
+    };
+
+    let mut spaceships: Vec<_> = spaceships.iter().collect();
+    spaceships.sort_by_key(|(player, ..)| **player);
+
+    egui::SidePanel::right("Spectator")
+        .default_width(200.0)
+        .show(ctx, |ui| {
+            match replay {
+                Some(replay) if replay.finished() => {
+                    ui.heading("Replay finished");
+                }
+                Some(replay) => {
+                    ui.heading("Replay");
+                    ui.add(egui::ProgressBar::new(replay.progress()));
+                }
+                None => {
+                    ui.heading("Spectating");
+                }
+            }
+
+            ui.label(match spectator.view {
+                SpectatorView::Free => "Free camera".to_string(),
+                SpectatorView::Follow(player) => format!("Following {player}"),
+            });
+
+            ui.separator();
+            ui.label(
+                RichText::new(format!("Score: {}", score.into_inner())).color(Color32::YELLOW),
+            );
+            ui.label(RichText::new(format!("Wave: {}", *wave)).color(Color32::WHITE));
+
+            egui::Grid::new("Players").striped(true).show(ui, |ui| {
+                for (player, health, player_score) in spaceships {
+                    ui.label(player.to_string());
+                    ui.label(RichText::new(player_score.to_string()).color(Color32::YELLOW));
+                    ui.label(RichText::new(format!("Health: {health}")).color(Color32::RED));
+                    ui.end_row();
+                }
+            });
+
+            ui.separator();
+            ui.label(format!("{}: switch view", key_name(SWITCH_VIEW)));
+            ui.label("WASD: move the free camera");
+            ui.label(format!(
+                "{}/{}: zoom",
+                key_name(ZOOM_IN),
+                key_name(ZOOM_OUT)
+            ));
+            ui.label(format!("{}: leave", key_name(settings.controls.pause)));
+        });
+}
+
+fn reset_camera(
+    mut spectator: ResMut<Spectator>,
+    mut cameras: Query<&mut Transform, With<Camera3d>>,
+) {
+    spectator.view = SpectatorView::Free;
+
+    for mut transform in cameras.iter_mut() {
+        *transform = camera::overhead_transform();
+    }
+}
+
+pub struct SpectatorPlugin;
+
+impl Plugin for SpectatorPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Spectator>()
+            .add_systems(
+                Update,
+                (switch_view, move_camera, draw_overlay)
+                    .chain()
+                    .run_if(in_state(AppState::Online)),
+            )
+            .add_systems(OnExit(AppState::Online), reset_camera);
+    }
+}
@@ -0,0 +1,438 @@
+use bevy::{
+    ecs::system::RunSystemOnce,
+    prelude::*,
+    utils::{Duration, Instant},
+};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::{net::SocketAddr, thread};
+
+use crate::{
+    application::AppState,
+    asteroid::{AsteroidSize, Wave},
+    difficulty::Difficulty,
+    movement::{self, Acceleration, Velocity},
+    player::{ActionSet, PlayerId, PlayerSetup, RemoteInputs, StepInput, StepInputs},
+    score::Score,
+    settings::{Action, Settings},
+    spaceship::{self, health::Health, Spaceship},
+    stats::RunStats,
+};
+
+use super::{
+    client::{join_server, receive_server_messages, NetworkClient},
+    prediction::{predict_local_spaceship, Predicted, Prediction},
+    protocol::{
+        encode, EntityKind, EntitySnapshot, Link, LinkConditions, NetworkId, ServerMessage,
+        Snapshot, SnapshotParts, MAX_MESSAGE_SIZE,
+    },
+    server::{
+        apply_client_inputs, receive_client_messages, send_game_over, send_snapshots,
+        take_snapshot, NetworkServer, SnapshotTaken,
+    },
+};
+
+/// How many times to poll both ends before giving up on a message.
+const ATTEMPTS: usize = 200;
+/// How long the operating system gets to deliver a datagram between polls.
+const POLL_INTERVAL: Duration = Duration::from_millis(5);
+/// How far both ends' clocks move on between polls.
+const CLOCK_STEP: Duration = Duration::from_millis(10);
+/// Joining is repeated this often, in attempts, in case it was lost.
+const JOIN_RETRY: usize = 20;
+/// One fixed simulation step.
+const STEP: Duration = Duration::from_micros(16_667);
+
+fn localhost() -> SocketAddr {
+    ([127, 0, 0, 1], 0).into()
+}
+
+fn bad_link(seed: u64) -> LinkConditions {
+    LinkConditions {
+        latency: Duration::from_millis(40),
+        jitter: Duration::from_millis(20),
+        packet_loss: 0.2,
+        seed: Some(seed),
+    }
+}
+
+/// Moves a world's real time on, which is the clock its link is flushed by.
+fn advance_clock(world: &mut World, by: Duration) {
+    let mut time = world.resource_mut::<Time<Real>>();
+    let now = time.last_update().unwrap_or(time.startup()) + by;
+    time.update_with_instant(now);
+}
+
+/// A server on a free port on localhost, for `players`.
+fn server(players: PlayerSetup, conditions: LinkConditions) -> (World, SocketAddr) {
+    let server = NetworkServer::bind(localhost(), conditions).expect("couldn't bind the server");
+    let address = server.local_addr().expect("the server has no address");
+
+    let mut world = World::new();
+    world.insert_resource(server);
+    world.insert_resource(players);
+    world.insert_resource(Settings::default());
+    world.insert_resource(Score::new(Difficulty::default()));
+    world.init_resource::<RemoteInputs>();
+    world.init_resource::<StepInputs>();
+    world.init_resource::<Wave>();
+    world.init_resource::<RunStats>();
+    world.init_resource::<Time>();
+    world.insert_resource(Time::<Real>::new(Instant::now()));
+    world.init_resource::<Events<SnapshotTaken>>();
+
+    (world, address)
+}
+
+/// A client of `server` that hasn't joined yet.
+fn client(server: SocketAddr, conditions: LinkConditions) -> World {
+    let client = NetworkClient::connect(server, conditions).expect("couldn't bind a client");
+
+    let mut world = World::new();
+    world.insert_resource(client);
+    world.insert_resource(Score::new(Difficulty::default()));
+    world.init_resource::<StepInputs>();
+    world.init_resource::<Wave>();
+    world.init_resource::<Time>();
+    world.insert_resource(Time::<Real>::new(Instant::now()));
+    world.insert_resource(Time::<Fixed>::from_duration(STEP));
+    world.resource_mut::<Time<Fixed>>().advance_by(STEP);
+    world.init_resource::<NextState<AppState>>();
+
+    world
+}
+
+/// Lets the server and a client handle each other's messages until `done`
+/// says the client has what it was waiting for. Until the client has joined
+/// it keeps asking to. Both clocks move on a step each time, for links
+/// that delay what they send.
+fn exchange(server: &mut World, client: &mut World, done: impl Fn(&World) -> bool) -> bool {
+    for attempt in 0..ATTEMPTS {
+        if attempt % JOIN_RETRY == 0
+            && client.contains_resource::<NetworkClient>()
+            && !joined(client)
+        {
+            client.run_system_once(join_server);
+        }
+
+        server.run_system_once(receive_client_messages);
+
+        if client.contains_resource::<NetworkClient>() {
+            client.run_system_once(receive_server_messages);
+        }
+
+        if done(client) {
+            return true;
+        }
+
+        advance_clock(server, CLOCK_STEP);
+        advance_clock(client, CLOCK_STEP);
+        thread::sleep(POLL_INTERVAL);
+    }
+
+    false
+}
+
+fn joined(client: &World) -> bool {
+    client
+        .get_resource::<NetworkClient>()
+        .is_some_and(|client| client.player.is_some())
+}
+
+#[test]
+fn joining_gets_a_welcome() {
+    let (mut server, address) = server(PlayerSetup::default(), LinkConditions::default());
+    let mut client = client(address, LinkConditions::default());
+
+    assert!(exchange(&mut server, &mut client, joined));
+    assert_eq!(client.resource::<NetworkClient>().player, Some(PlayerId(0)));
+    assert!(client.contains_resource::<Prediction>());
+}
+
+#[test]
+fn joining_a_full_game_is_turned_away() {
+    // Single player, so there's only one spaceship to go round.
+    let (mut server, address) = server(PlayerSetup::default(), LinkConditions::default());
+    let mut first = client(address, LinkConditions::default());
+    assert!(exchange(&mut server, &mut first, joined));
+
+    let mut second = client(address, LinkConditions::default());
+    assert!(exchange(&mut server, &mut second, |client| {
+        !client.contains_resource::<NetworkClient>()
+    }));
+    assert_eq!(
+        second.resource::<NextState<AppState>>().0,
+        Some(AppState::MainMenu)
+    );
+}
+
+#[test]
+fn snapshots_reach_clients() {
+    let (mut server, address) = server(PlayerSetup::default(), LinkConditions::default());
+    let mut client = client(address, LinkConditions::default());
+    assert!(exchange(&mut server, &mut client, joined));
+
+    server.resource_mut::<Score>().set_points(42);
+    server.resource_mut::<Wave>().number = 3;
+    server
+        .resource_mut::<Time>()
+        .advance_by(Duration::from_secs(1));
+    server.run_system_once(take_snapshot);
+    server.run_system_once(send_snapshots);
+
+    assert!(exchange(&mut server, &mut client, |client| {
+        client.resource::<Score>().points() == 42
+    }));
+    assert_eq!(client.resource::<Wave>().number, 3);
+}
+
+#[test]
+fn game_over_ends_the_game_for_clients() {
+    let (mut server, address) = server(PlayerSetup::default(), LinkConditions::default());
+    let mut client = client(address, LinkConditions::default());
+    assert!(exchange(&mut server, &mut client, joined));
+
+    server.insert_resource(Score::new(Difficulty::Hard));
+    server.resource_mut::<Score>().set_points(1234);
+    server.resource_mut::<Wave>().number = 5;
+    server.resource_mut::<RunStats>().shots_fired = 40;
+    server.run_system_once(send_game_over);
+
+    assert!(exchange(&mut server, &mut client, |client| {
+        client.resource::<NextState<AppState>>().0 == Some(AppState::EndGame)
+    }));
+    assert_eq!(client.resource::<Score>().points(), 1234);
+    assert_eq!(client.resource::<Score>().difficulty(), Difficulty::Hard);
+    assert_eq!(client.resource::<Wave>().number, 5);
+    assert_eq!(client.resource::<RunStats>().shots_fired, 40);
+
+    // The repeats of the same game over are ignored.
+    client.insert_resource(NextState::<AppState>(None));
+    assert!(!exchange(&mut server, &mut client, |client| {
+        client.resource::<NextState<AppState>>().0.is_some()
+    }));
+}
+
+#[test]
+fn big_snapshots_are_split_to_fit_datagrams() {
+    let entities: Vec<EntitySnapshot> = (0..2000)
+        .map(|id| EntitySnapshot {
+            id: NetworkId(id),
+            kind: EntityKind::Asteroid(AsteroidSize::Large),
+            transform: Transform::from_xyz(id as f32, 0., -(id as f32)),
+            velocity: Vec3::ONE,
+            hidden: false,
+            respawning: false,
+            invulnerable: false,
+            health: None,
+            lives: None,
+            score: None,
+            last_input: None,
+        })
+        .collect();
+    let snapshot = Snapshot {
+        tick: 7,
+        score: 0,
+        wave: 1,
+        entities,
+    };
+
+    let messages = snapshot.into_messages();
+    assert!(messages.len() > 1);
+
+    let mut parts = SnapshotParts::default();
+    let mut whole = None;
+
+    // Last part first, since datagrams can turn up in any order.
+    for message in messages.into_iter().rev() {
+        let encoded = encode(&message).unwrap();
+        assert!(encoded.len() <= MAX_MESSAGE_SIZE);
+
+        let (part, count, snapshot) = match message {
+            ServerMessage::Snapshot {
+                part,
+                parts,
+                snapshot,
+            } => (part, parts, snapshot),
+            other => panic!("expected a snapshot, got {other:?}"),
+        };
+
+        assert!(whole.is_none());
+        whole = parts.receive(part, count, snapshot);
+    }
+
+    let whole = whole.expect("the snapshot wasn't put back together");
+    assert_eq!(whole.tick, 7);
+    assert_eq!(whole.entities.len(), 2000);
+}
+
+/// Everything `link` has been sent, polling until `count` have arrived in
+/// case the operating system is slow to deliver them.
+fn receive_numbers(link: &mut Link, count: usize) -> Vec<u32> {
+    let mut received = Vec::new();
+
+    for _ in 0..ATTEMPTS {
+        received.extend(link.receive::<u32>().into_iter().map(|(_, number)| number));
+        if received.len() >= count {
+            break;
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+
+    received
+}
+
+#[test]
+fn a_bad_link_delays_and_drops_datagrams() {
+    let conditions = LinkConditions {
+        latency: Duration::from_millis(50),
+        jitter: Duration::from_millis(10),
+        packet_loss: 0.5,
+        seed: Some(7),
+    };
+    let mut sender = Link::bind(localhost(), conditions).unwrap();
+    let mut receiver = Link::bind(localhost(), LinkConditions::default()).unwrap();
+    let address = receiver.local_addr().unwrap();
+
+    // The same seed makes the same choices the link does: drop or not, then
+    // how much jitter. Half the jitter splits what's sent into two batches.
+    let halfway = conditions.jitter / 2;
+    let mut rng = StdRng::seed_from_u64(7);
+    let mut expected_early = Vec::new();
+    let mut expected_late = Vec::new();
+    for number in 0..200_u32 {
+        if rng.gen::<f32>() < conditions.packet_loss {
+            continue;
+        }
+        if conditions.jitter.mul_f32(rng.gen()) <= halfway {
+            expected_early.push(number);
+        } else {
+            expected_late.push(number);
+        }
+    }
+    assert!((40..160).contains(&(expected_early.len() + expected_late.len())));
+
+    let start = Instant::now();
+    sender.flush_at(start);
+    for number in 0..200_u32 {
+        sender.send(address, &number);
+    }
+
+    sender.flush_at(start + conditions.latency - Duration::from_millis(1));
+    assert!(receiver.receive::<u32>().is_empty());
+
+    sender.flush_at(start + conditions.latency + halfway);
+    assert_eq!(
+        receive_numbers(&mut receiver, expected_early.len()),
+        expected_early
+    );
+
+    sender.flush_at(start + conditions.latency + conditions.jitter);
+    assert_eq!(
+        receive_numbers(&mut receiver, expected_late.len()),
+        expected_late
+    );
+    assert!(receiver.receive::<u32>().is_empty());
+}
+
+/// The server's half of one fixed step: apply the client's input to its
+/// spaceship and maybe send a snapshot.
+fn server_step(server: &mut World) {
+    server.run_system_once(receive_client_messages);
+    server.run_system_once(apply_client_inputs);
+
+    let input = server.resource::<StepInputs>().get(PlayerId(0));
+    let control_scheme = server.resource::<Settings>().gameplay.control_scheme;
+    let mut spaceships =
+        server.query_filtered::<(&mut Transform, &mut Velocity), With<Spaceship>>();
+    for (mut transform, mut velocity) in spaceships.iter_mut(server) {
+        let mut acceleration = Vec3::ZERO;
+        spaceship::steer(
+            &mut transform,
+            &mut velocity.value,
+            &mut acceleration,
+            input,
+            control_scheme,
+            STEP.as_secs_f32(),
+        );
+        movement::integrate(
+            &mut transform.translation,
+            &mut velocity.value,
+            acceleration,
+            STEP.as_secs_f32(),
+        );
+    }
+
+    server.resource_mut::<Time>().advance_by(STEP);
+    advance_clock(server, STEP);
+    server.run_system_once(take_snapshot);
+    server.run_system_once(send_snapshots);
+    server.resource_mut::<Events<SnapshotTaken>>().clear();
+}
+
+#[test]
+fn prediction_converges_over_a_bad_link() {
+    let (mut server, address) = server(PlayerSetup::default(), bad_link(1));
+    let mut client = client(address, bad_link(2));
+    assert!(exchange(&mut server, &mut client, joined));
+
+    server.spawn((
+        Spaceship,
+        PlayerId(0),
+        NetworkId(0),
+        TransformBundle::default(),
+        VisibilityBundle::default(),
+        Velocity::new(Vec3::ZERO),
+        Health::new(1),
+        Score::new(Difficulty::default()),
+    ));
+    client.spawn((
+        Predicted::default(),
+        NetworkId(0),
+        Transform::default(),
+        Velocity::new(Vec3::ZERO),
+        Acceleration::new(Vec3::ZERO),
+    ));
+
+    // Turn for a second, then wait long enough for every input to reach the
+    // server and its snapshots to come back. Late and lost input makes the
+    // server hold the turn for longer than the client did, which the client
+    // has to take on.
+    let mut turning = ActionSet::default();
+    turning.insert(Action::RotateLeft);
+
+    for step in 0..150 {
+        let pressed = if step < 60 {
+            turning
+        } else {
+            ActionSet::default()
+        };
+        client.resource_mut::<StepInputs>().set(
+            PlayerId(0),
+            StepInput {
+                pressed,
+                ..default()
+            },
+        );
+        client.resource_mut::<Time>().advance_by(STEP);
+        advance_clock(&mut client, STEP);
+        client.run_system_once(predict_local_spaceship);
+
+        server_step(&mut server);
+        client.run_system_once(receive_server_messages);
+    }
+
+    let server_rotation = server
+        .query_filtered::<&Transform, With<Spaceship>>()
+        .single(&server)
+        .rotation;
+    let client_rotation = client
+        .query_filtered::<&Transform, With<Predicted>>()
+        .single(&client)
+        .rotation;
+
+    assert!(server_rotation.angle_between(Quat::IDENTITY) > 1.);
+    assert!(
+        server_rotation.angle_between(client_rotation) < 1e-4,
+        "the server is at {server_rotation}, the client at {client_rotation}"
+    );
+}
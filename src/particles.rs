@@ -0,0 +1,516 @@
+use bevy::{ecs::query::QueryData, prelude::*, utils::HashMap};
+use rand::Rng;
+use std::f32::consts::TAU;
+
+use crate::{
+    application::AppState,
+    collision_detection::HazardDestroyed,
+    hazards::{MineDetonated, MINE_BLAST_RADIUS},
+    movement::{Acceleration, Velocity},
+    schedule::InGameSet,
+    settings::Settings,
+    spaceship::{lives::SpaceshipDestroyed, Missile, Spaceship},
+};
+
+const DEBRIS_PER_RADIUS: f32 = 6.;
+const DEBRIS_SPEED: f32 = 8.;
+const DEBRIS_SPIN: f32 = 6.;
+const DEBRIS_LIFETIME: f32 = 1.5;
+const SPARKS_PER_EXPLOSION: usize = 16;
+const SPARK_SPEED: f32 = 25.;
+const SPARK_LIFETIME: f32 = 0.4;
+const SPACESHIP_DEBRIS: usize = 24;
+
+const TRAIL_INTERVAL: f32 = 0.02;
+const TRAIL_LIFETIME: f32 = 0.3;
+const EXHAUST_INTERVAL: f32 = 0.03;
+const EXHAUST_SPEED: f32 = 12.;
+const EXHAUST_LIFETIME: f32 = 0.25;
+/// How far from the spaceship's centre its engines are.
+const EXHAUST_OFFSET: f32 = 2.;
+/// Velocity changes smaller than this between frames don't count as firing
+/// the engines, so the impulse controls get exhaust too.
+const THRUST_THRESHOLD: f32 = 0.1;
+
+const MAX_FLASHES: usize = 4;
+const FLASH_DURATION: f32 = 0.2;
+const FLASH_INTENSITY: f32 = 400_000.;
+const FLASH_INTENSITY_PER_RADIUS: f32 = 200_000.;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+enum ParticleKind {
+    #[default]
+    Debris,
+    Spark,
+    Trail,
+    Exhaust,
+}
+
+impl ParticleKind {
+    /// Velocity lost per second, as a fraction.
+    fn drag(self) -> f32 {
+        match self {
+            ParticleKind::Debris => 1.,
+            ParticleKind::Spark => 3.,
+            ParticleKind::Trail => 0.,
+            ParticleKind::Exhaust => 4.,
+        }
+    }
+}
+
+/// One pooled particle. Finished ones stay around hidden until reused.
+#[derive(Component, Debug, Default)]
+struct Particle {
+    kind: ParticleKind,
+    velocity: Vec3,
+    spin: Vec3,
+    size: f32,
+    age: f32,
+    lifetime: f32,
+    active: bool,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct ParticleRequest {
+    kind: ParticleKind,
+    position: Vec3,
+    velocity: Vec3,
+    spin: Vec3,
+    size: f32,
+    lifetime: f32,
+}
+
+/// Particles to spawn this frame, and the ones free to spawn them with.
+#[derive(Resource, Debug, Default)]
+struct ParticlePool {
+    requests: Vec<ParticleRequest>,
+    free: Vec<Entity>,
+    live: usize,
+}
+
+impl ParticlePool {
+    fn emit(&mut self, request: ParticleRequest) {
+        self.requests.push(request);
+    }
+}
+
+/// Every particle of a kind shares its mesh and material.
+#[derive(Resource, Debug, Default)]
+struct ParticleAssets {
+    meshes: HashMap<ParticleKind, Handle<Mesh>>,
+    materials: HashMap<ParticleKind, Handle<StandardMaterial>>,
+}
+
+#[derive(QueryData)]
+#[query_data(mutable)]
+struct PooledParticle {
+    particle: &'static mut Particle,
+    transform: &'static mut Transform,
+    visibility: &'static mut Visibility,
+    mesh: &'static mut Handle<Mesh>,
+    material: &'static mut Handle<StandardMaterial>,
+}
+
+/// A light that flares up where something blew up.
+#[derive(Component, Debug, Default)]
+struct Flash {
+    remaining: f32,
+    intensity: f32,
+}
+
+fn load_particle_assets(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    let glowing = |color: Color| StandardMaterial {
+        base_color: color,
+        emissive: color * 4.,
+        unlit: true,
+        ..default()
+    };
+
+    let mut assets = ParticleAssets::default();
+    for (kind, mesh, material) in [
+        (
+            ParticleKind::Debris,
+            meshes.add(Cuboid::new(1., 1., 1.)),
+            materials.add(StandardMaterial {
+                base_color: Color::rgb(0.4, 0.35, 0.3),
+                perceptual_roughness: 1.,
+                ..default()
+            }),
+        ),
+        (
+            ParticleKind::Spark,
+            meshes.add(Sphere::new(0.5).mesh().ico(0).unwrap()),
+            materials.add(glowing(Color::rgb(1., 0.7, 0.2))),
+        ),
+        (
+            ParticleKind::Trail,
+            meshes.add(Sphere::new(0.5).mesh().ico(1).unwrap()),
+            materials.add(glowing(Color::rgb(0.6, 0.8, 1.))),
+        ),
+        (
+            ParticleKind::Exhaust,
+            meshes.add(Sphere::new(0.5).mesh().ico(1).unwrap()),
+            materials.add(glowing(Color::rgb(1., 0.45, 0.1))),
+        ),
+    ] {
+        assets.meshes.insert(kind, mesh);
+        assets.materials.insert(kind, material);
+    }
+    commands.insert_resource(assets);
+}
+
+fn spawn_flashes(mut commands: Commands) {
+    for _ in 0..MAX_FLASHES {
+        commands.spawn((
+            Flash::default(),
+            PointLightBundle {
+                point_light: PointLight {
+                    intensity: 0.,
+                    color: Color::rgb(1., 0.8, 0.5),
+                    ..default()
+                },
+                visibility: Visibility::Hidden,
+                ..default()
+            },
+        ));
+    }
+}
+
+/// Flashes only fade while the game is running, so they go with it rather
+/// than staying lit behind the menus.
+fn despawn_flashes(mut commands: Commands, flashes: Query<Entity, With<Flash>>) {
+    for entity in flashes.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+fn random_direction(rng: &mut impl Rng) -> Vec3 {
+    let angle = rng.gen_range(0.0..TAU);
+    Vec3::new(angle.cos(), rng.gen_range(-0.3..0.3), angle.sin()).normalize()
+}
+
+fn explode(pool: &mut ParticlePool, position: Vec3, radius: f32, debris: usize) {
+    let mut rng = rand::thread_rng();
+
+    for _ in 0..debris {
+        pool.emit(ParticleRequest {
+            kind: ParticleKind::Debris,
+            position: position + random_direction(&mut rng) * radius * rng.gen_range(0.0..0.5),
+            velocity: random_direction(&mut rng) * DEBRIS_SPEED * rng.gen_range(0.3..1.),
+            spin: random_direction(&mut rng) * DEBRIS_SPIN,
+            size: radius * rng.gen_range(0.1..0.3),
+            lifetime: DEBRIS_LIFETIME * rng.gen_range(0.6..1.),
+        });
+    }
+
+    for _ in 0..SPARKS_PER_EXPLOSION {
+        pool.emit(ParticleRequest {
+            kind: ParticleKind::Spark,
+            position,
+            velocity: random_direction(&mut rng) * SPARK_SPEED * rng.gen_range(0.5..1.),
+            spin: Vec3::ZERO,
+            size: rng.gen_range(0.15..0.3),
+            lifetime: SPARK_LIFETIME * rng.gen_range(0.5..1.),
+        });
+    }
+}
+
+fn flash(flashes: &mut Query<(&mut Flash, &mut Transform)>, position: Vec3, intensity: f32) {
+    // Take over whichever light is closest to going out.
+    let Some((mut flash, mut transform)) = flashes
+        .iter_mut()
+        .min_by(|(a, _), (b, _)| a.remaining.total_cmp(&b.remaining))
+    else {
+        return;
+    };
+
+    flash.remaining = FLASH_DURATION;
+    flash.intensity = intensity;
+    transform.translation = position;
+}
+
+fn explode_hazards(
+    mut pool: ResMut<ParticlePool>,
+    mut flashes: Query<(&mut Flash, &mut Transform)>,
+    mut destroyed: EventReader<HazardDestroyed>,
+) {
+    for hazard in destroyed.read() {
+        let debris = (hazard.radius * DEBRIS_PER_RADIUS).ceil() as usize;
+        explode(&mut pool, hazard.position, hazard.radius, debris);
+        flash(
+            &mut flashes,
+            hazard.position,
+            FLASH_INTENSITY + hazard.radius * FLASH_INTENSITY_PER_RADIUS,
+        );
+    }
+}
+
+fn explode_mines(
+    mut pool: ResMut<ParticlePool>,
+    mut flashes: Query<(&mut Flash, &mut Transform)>,
+    mut detonated: EventReader<MineDetonated>,
+) {
+    for &MineDetonated { position, .. } in detonated.read() {
+        explode(
+            &mut pool,
+            position,
+            MINE_BLAST_RADIUS / 2.,
+            SPARKS_PER_EXPLOSION,
+        );
+        flash(&mut flashes, position, FLASH_INTENSITY * 3.);
+    }
+}
+
+fn explode_spaceships(
+    mut pool: ResMut<ParticlePool>,
+    mut flashes: Query<(&mut Flash, &mut Transform)>,
+    spaceships: Query<&GlobalTransform, With<Spaceship>>,
+    mut destroyed: EventReader<SpaceshipDestroyed>,
+) {
+    for &SpaceshipDestroyed { spaceship } in destroyed.read() {
+        let Ok(transform) = spaceships.get(spaceship) else {
+            continue;
+        };
+
+        let position = transform.translation();
+        explode(&mut pool, position, 2., SPACESHIP_DEBRIS);
+        flash(&mut flashes, position, FLASH_INTENSITY * 2.);
+    }
+}
+
+fn emit_missile_trails(
+    mut pool: ResMut<ParticlePool>,
+    missiles: Query<&GlobalTransform, With<Missile>>,
+    mut timer: Local<f32>,
+    time: Res<Time>,
+) {
+    *timer += time.delta_seconds();
+    if *timer < TRAIL_INTERVAL {
+        return;
+    }
+    *timer = 0.;
+
+    for transform in missiles.iter() {
+        pool.emit(ParticleRequest {
+            kind: ParticleKind::Trail,
+            position: transform.translation(),
+            velocity: Vec3::ZERO,
+            spin: Vec3::ZERO,
+            size: 0.3,
+            lifetime: TRAIL_LIFETIME,
+        });
+    }
+}
+
+fn emit_thruster_exhaust(
+    mut pool: ResMut<ParticlePool>,
+    spaceships: Query<
+        (
+            Entity,
+            &Transform,
+            &Velocity,
+            &Acceleration,
+            &ViewVisibility,
+        ),
+        With<Spaceship>,
+    >,
+    mut last_velocities: Local<HashMap<Entity, Vec3>>,
+    mut timer: Local<f32>,
+    time: Res<Time>,
+) {
+    *timer += time.delta_seconds();
+    let emitting = *timer >= EXHAUST_INTERVAL;
+    if emitting {
+        *timer = 0.;
+    }
+
+    let mut rng = rand::thread_rng();
+    last_velocities.retain(|&entity, _| spaceships.contains(entity));
+
+    for (entity, transform, velocity, acceleration, visibility) in spaceships.iter() {
+        let last_velocity = last_velocities.insert(entity, velocity.value);
+        let thrusting = acceleration.value != Vec3::ZERO
+            || last_velocity.is_some_and(|last| last.distance(velocity.value) > THRUST_THRESHOLD);
+
+        if !emitting || !thrusting || !visibility.get() {
+            continue;
+        }
+
+        // Spaceships fly backwards along their transforms, so the engines
+        // point forward.
+        let engines = *transform.forward();
+        let spread = random_direction(&mut rng) * 0.2;
+
+        pool.emit(ParticleRequest {
+            kind: ParticleKind::Exhaust,
+            position: transform.translation + engines * EXHAUST_OFFSET,
+            velocity: velocity.value + (engines + spread) * EXHAUST_SPEED,
+            spin: Vec3::ZERO,
+            size: rng.gen_range(0.3..0.5),
+            lifetime: EXHAUST_LIFETIME,
+        });
+    }
+}
+
+/// Hands requests out to free particles, making new ones only when the pool
+/// runs dry. Anything over the budget is dropped.
+fn spawn_particles(
+    mut commands: Commands,
+    mut pool: ResMut<ParticlePool>,
+    mut particles: Query<PooledParticle>,
+    assets: Res<ParticleAssets>,
+    settings: Res<Settings>,
+) {
+    let budget = settings.video.max_particles;
+    let mut requests = std::mem::take(&mut pool.requests);
+
+    for request in requests.drain(..) {
+        if pool.live >= budget {
+            break;
+        }
+
+        let particle = Particle {
+            kind: request.kind,
+            velocity: request.velocity,
+            spin: request.spin,
+            size: request.size,
+            age: 0.,
+            lifetime: request.lifetime,
+            active: true,
+        };
+        let transform =
+            Transform::from_translation(request.position).with_scale(Vec3::splat(request.size));
+        let mesh = assets.meshes[&request.kind].clone();
+        let material = assets.materials[&request.kind].clone();
+
+        pool.live += 1;
+
+        match pool.free.pop().map(|entity| particles.get_mut(entity)) {
+            Some(Ok(mut pooled)) => {
+                *pooled.particle = particle;
+                *pooled.transform = transform;
+                *pooled.visibility = Visibility::Visible;
+                *pooled.mesh = mesh;
+                *pooled.material = material;
+            }
+            _ => {
+                commands.spawn((
+                    particle,
+                    PbrBundle {
+                        mesh,
+                        material,
+                        transform,
+                        ..default()
+                    },
+                ));
+            }
+        }
+    }
+
+    // Keep the allocation for next frame.
+    requests.clear();
+    pool.requests = requests;
+}
+
+fn update_particles(
+    mut pool: ResMut<ParticlePool>,
+    mut particles: Query<(Entity, &mut Particle, &mut Transform, &mut Visibility)>,
+    time: Res<Time>,
+) {
+    let delta = time.delta_seconds();
+
+    for (entity, mut particle, mut transform, mut visibility) in particles.iter_mut() {
+        if !particle.active {
+            continue;
+        }
+
+        particle.age += delta;
+        if particle.age >= particle.lifetime {
+            particle.active = false;
+            *visibility = Visibility::Hidden;
+            pool.free.push(entity);
+            pool.live -= 1;
+            continue;
+        }
+
+        let drag = (1. - particle.kind.drag() * delta).max(0.);
+        particle.velocity *= drag;
+
+        transform.translation += particle.velocity * delta;
+        transform.rotate(Quat::from_scaled_axis(particle.spin * delta));
+
+        // Shrink away rather than fade, so every particle can share a material.
+        let remaining = 1. - particle.age / particle.lifetime;
+        transform.scale = Vec3::splat(particle.size * remaining);
+    }
+}
+
+fn fade_flashes(
+    mut flashes: Query<(&mut Flash, &mut PointLight, &mut Visibility)>,
+    time: Res<Time>,
+) {
+    for (mut flash, mut light, mut visibility) in flashes.iter_mut() {
+        if flash.remaining <= 0. {
+            *visibility = Visibility::Hidden;
+            continue;
+        }
+
+        flash.remaining -= time.delta_seconds();
+        light.intensity = flash.intensity * (flash.remaining / FLASH_DURATION).max(0.);
+        light.shadows_enabled = false;
+        *visibility = Visibility::Visible;
+    }
+}
+
+/// Returns every particle to the pool when the game ends.
+fn clear_particles(
+    mut pool: ResMut<ParticlePool>,
+    mut particles: Query<(Entity, &mut Particle, &mut Visibility)>,
+) {
+    pool.requests.clear();
+
+    for (entity, mut particle, mut visibility) in particles.iter_mut() {
+        if particle.active {
+            particle.active = false;
+            *visibility = Visibility::Hidden;
+            pool.free.push(entity);
+        }
+    }
+    pool.live = 0;
+}
+
+pub struct ParticlesPlugin;
+
+impl Plugin for ParticlesPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ParticlePool>()
+            .add_systems(Startup, load_particle_assets)
+            .add_systems(
+                Update,
+                (explode_hazards, explode_mines, explode_spaceships)
+                    .after(InGameSet::CollisionDetection)
+                    .before(spawn_particles)
+                    .run_if(in_state(AppState::InGame)),
+            )
+            .add_systems(
+                Update,
+                (
+                    (emit_missile_trails, emit_thruster_exhaust),
+                    spawn_particles,
+                    update_particles,
+                    fade_flashes,
+                )
+                    .chain()
+                    .after(InGameSet::EntityUpdates)
+                    .run_if(in_state(AppState::InGame).or_else(in_state(AppState::Online))),
+            )
+            .add_systems(OnEnter(AppState::InGame), spawn_flashes)
+            .add_systems(OnExit(AppState::InGame), despawn_flashes)
+            .add_systems(OnEnter(AppState::EndGame), clear_particles)
+            .add_systems(OnEnter(AppState::MainMenu), clear_particles);
+    }
+}
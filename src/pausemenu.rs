@@ -1,13 +1,19 @@
 use bevy::prelude::*;
 use bevy_inspector_egui::{bevy_egui::EguiContexts, egui};
 
-use crate::{application::AppState, schedule::InGameSet};
+use crate::{
+    application::AppState, console::console_closed, schedule::InGameSet, settings::Settings,
+};
+
+#[cfg(not(target_arch = "wasm32"))]
+use crate::save_game::SaveAndQuit;
 
 fn pause_game(
     mut app_state: ResMut<NextState<AppState>>,
     keyboad_input: Res<ButtonInput<KeyCode>>,
+    settings: Res<Settings>,
 ) {
-    if keyboad_input.just_pressed(KeyCode::Escape) {
+    if keyboad_input.just_pressed(settings.controls.pause) {
         app_state.set(AppState::Paused);
     }
 }
@@ -15,17 +21,27 @@ fn pause_game(
 fn pause_menu(
     mut contexts: EguiContexts,
     mut app_state: ResMut<NextState<AppState>>,
+    #[cfg(not(target_arch = "wasm32"))] mut save_and_quit: EventWriter<SaveAndQuit>,
     #[cfg(not(target_arch = "wasm32"))] mut exit: EventWriter<bevy::app::AppExit>,
 ) {
+    let Some(ctx) = contexts.try_ctx_mut() else {
+        return;
+    };
+
     egui::SidePanel::left("Paused")
         .default_width(200.0)
-        .show(contexts.ctx_mut(), |ui| {
+        .show(ctx, |ui| {
             ui.allocate_space(egui::Vec2::new(1.0, 300.0));
             ui.label("Currently paused");
             if ui.button("[U]npause").clicked() {
                 app_state.set(AppState::InGame);
             };
 
+            #[cfg(not(target_arch = "wasm32"))]
+            if ui.button("[S]ave & Quit").clicked() {
+                save_and_quit.send(SaveAndQuit);
+            }
+
             #[cfg(not(target_arch = "wasm32"))]
             if ui.button("[Q]uit").clicked() {
                 exit.send(bevy::app::AppExit);
@@ -36,12 +52,21 @@ fn pause_menu(
 fn unpause_game(
     mut app_state: ResMut<NextState<AppState>>,
     keyboad_input: Res<ButtonInput<KeyCode>>,
+    settings: Res<Settings>,
+    #[cfg(not(target_arch = "wasm32"))] mut save_and_quit: EventWriter<SaveAndQuit>,
     #[cfg(not(target_arch = "wasm32"))] mut exit: EventWriter<bevy::app::AppExit>,
 ) {
-    if keyboad_input.just_pressed(KeyCode::Escape) || keyboad_input.just_pressed(KeyCode::KeyU) {
+    if keyboad_input.just_pressed(settings.controls.pause)
+        || keyboad_input.just_pressed(KeyCode::KeyU)
+    {
         app_state.set(AppState::InGame);
     }
 
+    #[cfg(not(target_arch = "wasm32"))]
+    if keyboad_input.just_pressed(KeyCode::KeyS) {
+        save_and_quit.send(SaveAndQuit);
+    }
+
     #[cfg(not(target_arch = "wasm32"))]
     if keyboad_input.just_pressed(KeyCode::KeyQ) {
         exit.send(bevy::app::AppExit);
@@ -56,7 +81,8 @@ impl Plugin for PauseMenuPlugin {
             Update,
             pause_game
                 .in_set(InGameSet::UserInput)
-                .run_if(in_state(AppState::InGame)),
+                .run_if(in_state(AppState::InGame))
+                .run_if(console_closed),
         )
         .add_systems(
             Update,
@@ -0,0 +1,52 @@
+use bevy::prelude::*;
+
+/// Reads a file relative to the working directory, returning `None` if it
+/// doesn't exist. There is no filesystem on the web, so this always returns
+/// `None` there.
+pub fn read(path: &str) -> Option<String> {
+    #[cfg(not(target_arch = "wasm32"))]
+    match std::fs::read_to_string(path) {
+        Ok(contents) => return Some(contents),
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => {}
+        Err(error) => error!("Couldn't read {path}: {error}"),
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    let _ = path;
+
+    None
+}
+
+pub fn write(path: &str, contents: &str) {
+    #[cfg(not(target_arch = "wasm32"))]
+    if let Err(error) = std::fs::write(path, contents) {
+        error!("Couldn't write {path}: {error}");
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    warn!(
+        "Not writing {path} ({} bytes), no filesystem",
+        contents.len()
+    );
+}
+
+pub fn remove(path: &str) {
+    #[cfg(not(target_arch = "wasm32"))]
+    if let Err(error) = std::fs::remove_file(path) {
+        error!("Couldn't remove {path}: {error}");
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    warn!("Not removing {path}, no filesystem");
+}
+
+pub fn exists(path: &str) -> bool {
+    #[cfg(not(target_arch = "wasm32"))]
+    return std::path::Path::new(path).exists();
+
+    #[cfg(target_arch = "wasm32")]
+    {
+        let _ = path;
+        false
+    }
+}
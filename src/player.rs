@@ -0,0 +1,363 @@
+use bevy::{
+    ecs::system::SystemParam,
+    input::{gamepad::GamepadConnection, InputSystem},
+    prelude::*,
+    utils::HashMap,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    console::console_closed,
+    schedule::InGameSet,
+    settings::{Action, Settings},
+};
+
+pub const MAX_PLAYERS: usize = 4;
+
+const PLAYER_SPACING: f32 = 15.0;
+
+/// Which player a spaceship, or the missile it fired, belongs to.
+#[derive(
+    Component,
+    Debug,
+    Reflect,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    Hash,
+    PartialOrd,
+    Ord,
+    Serialize,
+    Deserialize,
+)]
+#[reflect(Component)]
+pub struct PlayerId(pub usize);
+
+impl PlayerId {
+    /// Where this player's spaceship starts and respawns, spread out along x.
+    pub fn starting_translation(&self, players: usize) -> Vec3 {
+        let offset = self.0 as f32 - (players - 1) as f32 / 2.;
+        Vec3::new(offset * PLAYER_SPACING, 0., 0.)
+    }
+}
+
+impl std::fmt::Display for PlayerId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Player {}", self.0 + 1)
+    }
+}
+
+#[derive(Reflect, Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum PlayerMode {
+    #[default]
+    Single,
+    /// Everyone shoots asteroids together.
+    Coop,
+    /// Missiles hurt other players too, last one flying wins.
+    Versus,
+}
+
+impl PlayerMode {
+    pub const ALL: [PlayerMode; 3] = [PlayerMode::Single, PlayerMode::Coop, PlayerMode::Versus];
+}
+
+/// Who is playing the current run. Like the difficulty, new games take it
+/// from the main menu and continued games restore it from the save.
+#[derive(Resource, Reflect, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[reflect(Resource)]
+#[serde(default)]
+pub struct PlayerSetup {
+    pub mode: PlayerMode,
+    /// Only used outside of single player, always at least two.
+    pub count: usize,
+}
+
+impl Default for PlayerSetup {
+    fn default() -> Self {
+        Self {
+            mode: PlayerMode::Single,
+            count: 2,
+        }
+    }
+}
+
+impl PlayerSetup {
+    pub fn players(&self) -> usize {
+        match self.mode {
+            PlayerMode::Single => 1,
+            PlayerMode::Coop | PlayerMode::Versus => self.count.clamp(2, MAX_PLAYERS),
+        }
+    }
+
+    /// The run ends once fewer spaceships than this are left.
+    pub fn survivors_needed(&self) -> usize {
+        match self.mode {
+            PlayerMode::Single | PlayerMode::Coop => 1,
+            PlayerMode::Versus => 2,
+        }
+    }
+
+    pub fn ids(&self) -> impl Iterator<Item = PlayerId> {
+        (0..self.players()).map(PlayerId)
+    }
+}
+
+/// A set of [`Action`]s packed into bits, small enough to send every frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct ActionSet(u8);
+
+impl ActionSet {
+    fn bit(action: Action) -> u8 {
+        let index = Action::ALL
+            .iter()
+            .position(|&other| other == action)
+            .unwrap_or_default();
+
+        1 << index
+    }
+
+    pub fn insert(&mut self, action: Action) {
+        self.0 |= Self::bit(action);
+    }
+
+    pub fn contains(&self, action: Action) -> bool {
+        self.0 & Self::bit(action) != 0
+    }
+
+    pub fn union(self, other: ActionSet) -> ActionSet {
+        ActionSet(self.0 | other.0)
+    }
+}
+
+/// Everything one player is doing on one simulation step.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct StepInput {
+    pub pressed: ActionSet,
+    pub just_pressed: ActionSet,
+}
+
+impl StepInput {
+    pub fn pressed(&self, action: Action) -> bool {
+        self.pressed.contains(action)
+    }
+
+    pub fn just_pressed(&self, action: Action) -> bool {
+        self.just_pressed.contains(action)
+    }
+}
+
+/// Input for the fixed-step simulation. A frame can run any number of steps,
+/// including none, so presses are latched until a step has used them.
+#[derive(Resource, Debug, Default)]
+pub struct StepInputs {
+    inputs: HashMap<PlayerId, StepInput>,
+}
+
+impl StepInputs {
+    pub fn get(&self, player: PlayerId) -> StepInput {
+        self.inputs.get(&player).copied().unwrap_or_default()
+    }
+
+    /// Replaces a player's input for the next step, e.g. with one sent over
+    /// the network for exactly that step.
+    pub fn set(&mut self, player: PlayerId, input: StepInput) {
+        self.inputs.insert(player, input);
+    }
+
+    fn latch(&mut self, player: PlayerId, input: StepInput) {
+        let latched = self.inputs.entry(player).or_default();
+        latched.pressed = input.pressed;
+        latched.just_pressed = latched.just_pressed.union(input.just_pressed);
+    }
+}
+
+fn latch_local_inputs(
+    mut step_inputs: ResMut<StepInputs>,
+    input: PlayerInput,
+    players: Res<PlayerSetup>,
+) {
+    for player in players.ids().filter(|&player| !input.is_remote(player)) {
+        step_inputs.latch(player, input.step_input(player));
+    }
+}
+
+/// Lets go of everything local players were holding, while their keys are
+/// going to the console instead.
+fn release_local_inputs(
+    mut step_inputs: ResMut<StepInputs>,
+    input: PlayerInput,
+    players: Res<PlayerSetup>,
+) {
+    for player in players.ids().filter(|&player| !input.is_remote(player)) {
+        step_inputs.latch(player, StepInput::default());
+    }
+}
+
+fn consume_step_presses(mut step_inputs: ResMut<StepInputs>) {
+    for input in step_inputs.inputs.values_mut() {
+        input.just_pressed = ActionSet::default();
+    }
+}
+
+/// Input for players who aren't at this machine, e.g. clients of a server.
+/// Once a player has remote input, their local bindings are ignored.
+#[derive(Resource, Debug, Default)]
+pub struct RemoteInputs {
+    pressed: HashMap<PlayerId, ActionSet>,
+    just_pressed: HashMap<PlayerId, ActionSet>,
+}
+
+impl RemoteInputs {
+    /// Presses accumulate until the next frame's input systems have seen them,
+    /// so a tap isn't lost if two messages arrive in one frame.
+    pub fn receive(&mut self, player: PlayerId, pressed: ActionSet, just_pressed: ActionSet) {
+        self.pressed.insert(player, pressed);
+
+        let accumulated = self.just_pressed.entry(player).or_default();
+        *accumulated = accumulated.union(just_pressed);
+    }
+
+    pub fn forget(&mut self, player: PlayerId) {
+        self.pressed.remove(&player);
+        self.just_pressed.remove(&player);
+    }
+}
+
+fn clear_remote_presses(mut remote: ResMut<RemoteInputs>) {
+    remote.just_pressed.clear();
+}
+
+/// Connected gamepads in the order they were connected. The first is player
+/// 1's, and everyone after a gamepad that goes away moves up a slot.
+#[derive(Resource, Debug, Default)]
+pub struct GamepadOrder(Vec<Gamepad>);
+
+fn track_gamepads(
+    mut connections: EventReader<GamepadConnectionEvent>,
+    mut order: ResMut<GamepadOrder>,
+) {
+    for event in connections.read() {
+        match event.connection {
+            GamepadConnection::Connected(_) if !order.0.contains(&event.gamepad) => {
+                order.0.push(event.gamepad);
+            }
+            GamepadConnection::Connected(_) => {}
+            GamepadConnection::Disconnected => {
+                order.0.retain(|&gamepad| gamepad != event.gamepad);
+            }
+        }
+    }
+}
+
+fn gamepad_button(action: Action) -> GamepadButtonType {
+    match action {
+        Action::Thrust => GamepadButtonType::RightTrigger2,
+        Action::Reverse => GamepadButtonType::LeftTrigger2,
+        Action::RotateLeft => GamepadButtonType::DPadLeft,
+        Action::RotateRight => GamepadButtonType::DPadRight,
+        Action::Fire => GamepadButtonType::South,
+        Action::Shield => GamepadButtonType::East,
+        Action::Pause => GamepadButtonType::Start,
+    }
+}
+
+/// Reads an action for one player, from their key bindings if they have any
+/// and from the gamepad connected in their slot.
+#[derive(SystemParam)]
+pub struct PlayerInput<'w> {
+    keyboard: Res<'w, ButtonInput<KeyCode>>,
+    gamepad_buttons: Res<'w, ButtonInput<GamepadButton>>,
+    gamepads: Res<'w, GamepadOrder>,
+    settings: Res<'w, Settings>,
+    remote: Res<'w, RemoteInputs>,
+}
+
+impl PlayerInput<'_> {
+    fn gamepad(&self, player: PlayerId, action: Action) -> Option<GamepadButton> {
+        let gamepad = *self.gamepads.0.get(player.0)?;
+        Some(GamepadButton::new(gamepad, gamepad_button(action)))
+    }
+
+    pub fn is_remote(&self, player: PlayerId) -> bool {
+        self.remote.pressed.contains_key(&player)
+    }
+
+    /// Every action at once, as sent to a server or fed to a simulation step.
+    pub fn step_input(&self, player: PlayerId) -> StepInput {
+        let mut input = StepInput::default();
+
+        for action in Action::ALL {
+            if self.pressed(player, action) {
+                input.pressed.insert(action);
+            }
+
+            if self.just_pressed(player, action) {
+                input.just_pressed.insert(action);
+            }
+        }
+
+        input
+    }
+
+    pub fn pressed(&self, player: PlayerId, action: Action) -> bool {
+        if let Some(pressed) = self.remote.pressed.get(&player) {
+            return pressed.contains(action);
+        }
+
+        self.settings
+            .bindings(player)
+            .is_some_and(|bindings| self.keyboard.pressed(bindings.key(action)))
+            || self
+                .gamepad(player, action)
+                .is_some_and(|button| self.gamepad_buttons.pressed(button))
+    }
+
+    pub fn just_pressed(&self, player: PlayerId, action: Action) -> bool {
+        if self.remote.pressed.contains_key(&player) {
+            return self
+                .remote
+                .just_pressed
+                .get(&player)
+                .is_some_and(|just_pressed| just_pressed.contains(action));
+        }
+
+        self.settings
+            .bindings(player)
+            .is_some_and(|bindings| self.keyboard.just_pressed(bindings.key(action)))
+            || self
+                .gamepad(player, action)
+                .is_some_and(|button| self.gamepad_buttons.just_pressed(button))
+    }
+}
+
+pub struct PlayerPlugin;
+
+impl Plugin for PlayerPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<PlayerSetup>()
+            .init_resource::<RemoteInputs>()
+            .init_resource::<StepInputs>()
+            .init_resource::<GamepadOrder>()
+            .register_type::<PlayerId>()
+            .register_type::<PlayerMode>()
+            .register_type::<PlayerSetup>()
+            .add_systems(
+                PreUpdate,
+                (
+                    track_gamepads,
+                    (
+                        latch_local_inputs.run_if(console_closed),
+                        release_local_inputs.run_if(not(console_closed)),
+                    ),
+                )
+                    .chain()
+                    .after(InputSystem),
+            )
+            .add_systems(
+                FixedUpdate,
+                consume_step_presses.after(InGameSet::UserInput),
+            )
+            .add_systems(Update, clear_remote_presses.after(InGameSet::UserInput));
+    }
+}
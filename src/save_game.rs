@@ -0,0 +1,246 @@
+use bevy::{
+    ecs::entity::EntityHashMap,
+    prelude::*,
+    scene::{ron, serde::SceneDeserializer},
+};
+use serde::de::DeserializeSeed;
+
+use crate::{
+    application::AppState,
+    asset_loader::SceneAssets,
+    asteroid::{Asteroid, AsteroidSize, SpawnTimer, Wave, ASTEROID_COLLIDER_FIT},
+    camera::CameraTarget,
+    collision_detection::{Collider, ColliderFit, FastMover},
+    difficulty::Difficulty,
+    end_game::DespawnAtEndgame,
+    movement::{Acceleration, Velocity},
+    persistence,
+    player::{PlayerId, PlayerSetup},
+    score::Score,
+    scoring::{Combo, PointValue, WaveStats},
+    spaceship::{
+        self,
+        health::Health,
+        lives::{Invulnerable, Lives, Respawning},
+        shield::{attach_shield_display, SpaceshipShield},
+        AlreadyFired, Missile, Spaceship, MISSILE_COLLIDER_FIT, MISSILE_RADIUS,
+        SPACESHIP_COLLIDER_FIT,
+    },
+    stats::RunStats,
+    ui::{BlipKind, RadarBlip},
+};
+
+const SAVE_PATH: &str = "savegame.scn.ron";
+
+/// Sent from the pause menu to write the current run to disk and exit.
+#[derive(Event, Debug)]
+pub struct SaveAndQuit;
+
+/// Present while a saved game is being restored instead of starting a new one.
+#[derive(Resource, Debug)]
+pub struct ContinueGame;
+
+#[derive(Resource, Debug, Default)]
+pub struct SavedGameAvailable(pub bool);
+
+/// Marks entities that came out of a save file and still need their models
+/// and colliders, which aren't serialized.
+#[derive(Component, Debug)]
+struct Restored;
+
+fn save_game(world: &mut World) {
+    let mut saveable =
+        world.query_filtered::<Entity, Or<(With<Spaceship>, With<Asteroid>, With<Missile>)>>();
+    let entities: Vec<Entity> = saveable.iter(world).collect();
+
+    let scene = DynamicSceneBuilder::from_world(world)
+        .deny_all()
+        .allow::<Transform>()
+        .allow::<Velocity>()
+        .allow::<Acceleration>()
+        .allow::<Health>()
+        .allow::<Lives>()
+        .allow::<Respawning>()
+        .allow::<Invulnerable>()
+        .allow::<Spaceship>()
+        .allow::<AlreadyFired>()
+        .allow::<SpaceshipShield>()
+        .allow::<Asteroid>()
+        .allow::<AsteroidSize>()
+        .allow::<PointValue>()
+        .allow::<Missile>()
+        .allow::<PlayerId>()
+        .allow::<Score>()
+        .deny_all_resources()
+        .allow_resource::<Difficulty>()
+        .allow_resource::<PlayerSetup>()
+        .allow_resource::<Score>()
+        .allow_resource::<Wave>()
+        .allow_resource::<SpawnTimer>()
+        .allow_resource::<Combo>()
+        .allow_resource::<WaveStats>()
+        .allow_resource::<RunStats>()
+        .extract_entities(entities.into_iter())
+        .extract_resources()
+        .build();
+
+    match scene.serialize_ron(world.resource::<AppTypeRegistry>()) {
+        Ok(serialized) => {
+            info!("Saving game to {SAVE_PATH}");
+            persistence::write(SAVE_PATH, &serialized);
+        }
+        Err(error) => error!("Couldn't serialize the game: {error}"),
+    }
+
+    world.send_event(bevy::app::AppExit);
+}
+
+fn load_saved_game(world: &mut World) {
+    let Some(contents) = persistence::read(SAVE_PATH) else {
+        error!("No saved game at {SAVE_PATH}");
+        return;
+    };
+
+    let type_registry = world.resource::<AppTypeRegistry>().clone();
+    let scene = {
+        let type_registry = type_registry.read();
+        let mut deserializer = match ron::de::Deserializer::from_str(&contents) {
+            Ok(deserializer) => deserializer,
+            Err(error) => {
+                error!("Couldn't parse {SAVE_PATH}: {error}");
+                return;
+            }
+        };
+
+        SceneDeserializer {
+            type_registry: &type_registry,
+        }
+        .deserialize(&mut deserializer)
+    };
+
+    let scene = match scene {
+        Ok(scene) => scene,
+        Err(error) => {
+            error!("Couldn't deserialize {SAVE_PATH}: {error}");
+            return;
+        }
+    };
+
+    let mut entity_map = EntityHashMap::default();
+    if let Err(error) = scene.write_to_world(world, &mut entity_map) {
+        error!("Couldn't restore the saved game: {error}");
+        return;
+    }
+
+    for &entity in entity_map.values() {
+        world.entity_mut(entity).insert(Restored);
+    }
+
+    info!("Restored {} entities from {SAVE_PATH}", entity_map.len());
+    persistence::remove(SAVE_PATH);
+}
+
+fn rebuild_restored_entities(
+    mut commands: Commands,
+    assets: Res<SceneAssets>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    restored: Query<
+        (
+            Entity,
+            &Transform,
+            Has<Spaceship>,
+            Option<&AsteroidSize>,
+            Has<SpaceshipShield>,
+        ),
+        With<Restored>,
+    >,
+) {
+    for (entity, transform, is_spaceship, asteroid_size, has_shield) in restored.iter() {
+        let (scene, collider, fit) = if is_spaceship {
+            (
+                assets.spaceship.clone(),
+                spaceship::spaceship_collider(),
+                SPACESHIP_COLLIDER_FIT,
+            )
+        } else if let Some(size) = asteroid_size {
+            (
+                assets.asteroids.clone(),
+                size.collider(),
+                ASTEROID_COLLIDER_FIT,
+            )
+        } else {
+            (
+                assets.missiles.clone(),
+                Collider::new(MISSILE_RADIUS),
+                MISSILE_COLLIDER_FIT,
+            )
+        };
+
+        commands.entity(entity).remove::<Restored>().insert((
+            SceneBundle {
+                scene,
+                transform: *transform,
+                ..default()
+            },
+            collider,
+            ColliderFit::new(fit),
+            DespawnAtEndgame,
+        ));
+
+        if is_spaceship {
+            commands.entity(entity).insert((
+                spaceship::health_bar(),
+                CameraTarget,
+                RadarBlip(BlipKind::Player),
+            ));
+        }
+
+        if asteroid_size.is_some() {
+            commands
+                .entity(entity)
+                .insert(RadarBlip(BlipKind::Asteroid));
+        } else if !is_spaceship {
+            commands.entity(entity).insert(FastMover::default());
+        }
+
+        if has_shield {
+            attach_shield_display(&mut commands, entity, &mut meshes, &mut materials);
+        }
+    }
+}
+
+fn forget_continue(mut commands: Commands) {
+    commands.remove_resource::<ContinueGame>();
+}
+
+fn check_for_saved_game(mut available: ResMut<SavedGameAvailable>) {
+    available.0 = persistence::exists(SAVE_PATH);
+}
+
+pub struct SaveGamePlugin;
+
+impl Plugin for SaveGamePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<SaveAndQuit>()
+            .init_resource::<SavedGameAvailable>()
+            .register_type::<TimerMode>()
+            .add_systems(
+                Update,
+                save_game
+                    .run_if(in_state(AppState::Paused))
+                    .run_if(on_event::<SaveAndQuit>()),
+            )
+            .add_systems(OnEnter(AppState::MainMenu), check_for_saved_game)
+            .add_systems(
+                OnTransition {
+                    from: AppState::MainMenu,
+                    to: AppState::InGame,
+                },
+                (load_saved_game, rebuild_restored_entities)
+                    .chain()
+                    .run_if(resource_exists::<ContinueGame>),
+            )
+            .add_systems(OnEnter(AppState::InGame), forget_continue);
+    }
+}
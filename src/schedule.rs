@@ -1,5 +1,9 @@
 use bevy::prelude::*;
 
+/// Steps per second of the fixed-step simulation. Movement runs at this rate
+/// on every machine so a networked spaceship can be re-simulated exactly.
+pub const SIMULATION_RATE: f64 = 60.0;
+
 #[derive(Debug, Hash, PartialEq, Eq, Clone, SystemSet)]
 pub enum InGameSet {
     UserInput,
@@ -12,21 +16,26 @@ pub struct SchedulePlugin;
 
 impl Plugin for SchedulePlugin {
     fn build(&self, app: &mut App) {
-        app.configure_sets(
-            Update,
-            (
-                InGameSet::DespawnEntities,
-                InGameSet::UserInput,
-                InGameSet::EntityUpdates,
-                InGameSet::CollisionDetection,
+        app.insert_resource(Time::<Fixed>::from_hz(SIMULATION_RATE))
+            .configure_sets(
+                Update,
+                (
+                    InGameSet::DespawnEntities,
+                    InGameSet::UserInput,
+                    InGameSet::EntityUpdates,
+                    InGameSet::CollisionDetection,
+                )
+                    .chain(),
+            )
+            .configure_sets(
+                FixedUpdate,
+                (InGameSet::UserInput, InGameSet::EntityUpdates).chain(),
             )
-                .chain(),
-        )
-        .add_systems(
-            Update,
-            apply_deferred
-                .after(InGameSet::DespawnEntities)
-                .before(InGameSet::UserInput),
-        );
+            .add_systems(
+                Update,
+                apply_deferred
+                    .after(InGameSet::DespawnEntities)
+                    .before(InGameSet::UserInput),
+            );
     }
 }
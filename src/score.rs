@@ -1,25 +1,173 @@
 use bevy::prelude::*;
-use bevy::{app::Plugin, ecs::component::Component};
+use bevy::{app::Plugin, asset::ron, ecs::component::Component};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    application::AppState,
+    console::{parse_argument, ConsoleAppExt, ConsoleCommand},
+    difficulty::Difficulty,
+    persistence,
+    save_game::ContinueGame,
+    stats::RunStats,
+};
+
+const HIGH_SCORES_PATH: &str = "highscores.ron";
+const MAX_HIGH_SCORES: usize = 100;
 
 pub struct ScorePlugin;
 
+/// Points for the current run, scaled by the run's difficulty. The resource
+/// is the whole run's total, each spaceship also keeps its own player's score.
 #[derive(Component, Resource, Debug, Reflect)]
-pub struct Score(usize);
+#[reflect(Component, Resource)]
+pub struct Score {
+    points: usize,
+    /// What's left over from scaling, so easy kills still add up.
+    fraction: f32,
+    difficulty: Difficulty,
+}
 
 impl Score {
-    pub fn score(&mut self, by: usize) {
-        self.0 += by;
+    pub fn new(difficulty: Difficulty) -> Self {
+        Self {
+            points: 0,
+            fraction: 0.,
+            difficulty,
+        }
+    }
+
+    /// Adds `by` points scaled by the difficulty and returns what was awarded.
+    pub fn score(&mut self, by: usize) -> usize {
+        let multiplier = self.difficulty.preset().score_multiplier;
+        let earned = by as f32 * multiplier + self.fraction;
+        let awarded = earned.floor() as usize;
+        self.fraction = earned.fract();
+        self.points += awarded;
+        awarded
+    }
+
+    /// Overwrites the points with a score kept somewhere else, e.g. by the server.
+    pub fn set_points(&mut self, points: usize) {
+        self.points = points;
+    }
+
+    pub fn points(&self) -> usize {
+        self.points
+    }
+
+    pub fn difficulty(&self) -> Difficulty {
+        self.difficulty
     }
 }
 
 impl std::fmt::Display for Score {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.0)
+        write!(f, "{}", self.points)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HighScore {
+    pub points: usize,
+    pub difficulty: Difficulty,
+}
+
+/// Every finished run, best first, so the leaderboard can be filtered by difficulty.
+#[derive(Resource, Debug, Default, Serialize, Deserialize)]
+pub struct HighScores(Vec<HighScore>);
+
+impl HighScores {
+    fn load() -> Self {
+        let Some(contents) = persistence::read(HIGH_SCORES_PATH) else {
+            return Self::default();
+        };
+
+        ron::from_str(&contents).unwrap_or_else(|error| {
+            warn!("Ignoring {HIGH_SCORES_PATH}: {error}");
+            Self::default()
+        })
+    }
+
+    fn save(&self) {
+        match ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default()) {
+            Ok(serialized) => persistence::write(HIGH_SCORES_PATH, &serialized),
+            Err(error) => error!("Couldn't serialize high scores: {error}"),
+        }
+    }
+
+    fn record(&mut self, score: &Score) {
+        let position = self.0.partition_point(|entry| entry.points >= score.points);
+        self.0.insert(
+            position,
+            HighScore {
+                points: score.points,
+                difficulty: score.difficulty,
+            },
+        );
+        self.0.truncate(MAX_HIGH_SCORES);
+    }
+
+    pub fn for_difficulty(&self, difficulty: Difficulty) -> impl Iterator<Item = &HighScore> {
+        self.0
+            .iter()
+            .filter(move |entry| entry.difficulty == difficulty)
     }
+
+    pub fn best(&self, difficulty: Difficulty) -> Option<usize> {
+        self.for_difficulty(difficulty)
+            .next()
+            .map(|entry| entry.points)
+    }
+}
+
+fn reset_score(mut commands: Commands, difficulty: Res<Difficulty>) {
+    commands.insert_resource(Score::new(*difficulty));
+}
+
+fn record_high_score(
+    score: Res<Score>,
+    mut high_scores: ResMut<HighScores>,
+    mut stats: ResMut<RunStats>,
+) {
+    stats.previous_best = high_scores.best(score.difficulty);
+    info!(
+        "Recording score of {} on {}",
+        score.points, score.difficulty
+    );
+    high_scores.record(&score);
+    high_scores.save();
+}
+
+fn set_score(world: &mut World, arguments: &[&str]) -> Result<String, String> {
+    let points: usize = parse_argument(arguments)?;
+    world.resource_mut::<Score>().set_points(points);
+    Ok(format!("Score set to {points}"))
 }
 
 impl Plugin for ScorePlugin {
     fn build(&self, app: &mut bevy::prelude::App) {
-        app.insert_resource(Score(0));
+        app.insert_resource(Score::new(Difficulty::default()))
+            .insert_resource(HighScores::load())
+            .register_type::<Score>()
+            .add_console_command(ConsoleCommand::new(
+                "set score",
+                "set score <points>",
+                "Set the run's score",
+                set_score,
+            ))
+            .add_systems(
+                OnTransition {
+                    from: AppState::MainMenu,
+                    to: AppState::InGame,
+                },
+                reset_score.run_if(not(resource_exists::<ContinueGame>)),
+            )
+            .add_systems(
+                OnTransition {
+                    from: AppState::InGame,
+                    to: AppState::EndGame,
+                },
+                record_high_score,
+            );
     }
 }
@@ -0,0 +1,293 @@
+use bevy::prelude::*;
+use std::time::Duration;
+
+use crate::{
+    application::AppState,
+    asteroid::{Asteroid, WaveCompleted},
+    collision_detection::{Collider, HazardDestroyed, SpaceshipDamaged},
+    end_game::DespawnAtEndgame,
+    hazards::MineDetonated,
+    player::PlayerId,
+    save_game::ContinueGame,
+    schedule::InGameSet,
+    score::Score,
+    spaceship::{lives::Invulnerable, MissileFired, Spaceship},
+};
+
+const COMBO_STEP: f32 = 0.25;
+const MAX_COMBO: f32 = 4.0;
+const COMBO_DECAY_TIME: u64 = 1500;
+
+const ACCURACY_BONUS: usize = 10;
+const ACCURACY_MIN_SHOTS: u32 = 5;
+const NO_DAMAGE_BONUS: usize = 15;
+
+const CLOSE_CALL_MARGIN: f32 = 2.0;
+const CLOSE_CALL_BONUS: usize = 2;
+
+const POPUP_TIME: u64 = 1000;
+const POPUP_RISE_SPEED: f32 = 6.0;
+
+/// How many points destroying this is worth, before any multipliers.
+#[derive(Component, Debug, Reflect)]
+#[reflect(Component)]
+pub struct PointValue(pub usize);
+
+/// Grows with each kill and drops back a step every [`COMBO_DECAY_TIME`]
+/// milliseconds without one. Taking damage breaks the combo entirely.
+#[derive(Resource, Debug, Reflect)]
+#[reflect(Resource)]
+pub struct Combo {
+    chain: u32,
+    decay: Timer,
+}
+
+impl Default for Combo {
+    fn default() -> Self {
+        Self {
+            chain: 0,
+            decay: Timer::new(
+                Duration::from_millis(COMBO_DECAY_TIME),
+                TimerMode::Repeating,
+            ),
+        }
+    }
+}
+
+impl Combo {
+    pub fn multiplier(&self) -> f32 {
+        (1. + COMBO_STEP * self.chain as f32).min(MAX_COMBO)
+    }
+
+    fn kill(&mut self) {
+        self.chain += 1;
+        self.decay.reset();
+    }
+}
+
+/// What has happened during the current wave, for the end of wave bonuses.
+#[derive(Resource, Debug, Default, Reflect)]
+#[reflect(Resource)]
+pub struct WaveStats {
+    shots: u32,
+    hits: u32,
+    damage_taken: u32,
+}
+
+/// Floating "+N" text, drawn by the ui at this entity's position.
+#[derive(Component, Debug)]
+pub struct ScorePopup {
+    pub text: String,
+    pub timer: Timer,
+}
+
+/// The asteroid is inside the close call zone around the spaceship. If it
+/// leaves again without hitting anything, that's a dodge.
+#[derive(Component, Debug)]
+struct NearMiss;
+
+#[derive(Component, Debug)]
+struct Dodged;
+
+fn spawn_popup(commands: &mut Commands, position: Vec3, text: String) {
+    commands.spawn((
+        ScorePopup {
+            text,
+            timer: Timer::new(Duration::from_millis(POPUP_TIME), TimerMode::Once),
+        },
+        TransformBundle::from_transform(Transform::from_translation(position)),
+        DespawnAtEndgame,
+    ));
+}
+
+/// Everything a missile destroyed, including mines it set off.
+fn score_destroyed_hazards(
+    mut commands: Commands,
+    mut destroyed: EventReader<HazardDestroyed>,
+    mut detonated: EventReader<MineDetonated>,
+    mut score: ResMut<Score>,
+    mut player_scores: Query<(&PlayerId, &mut Score)>,
+    mut combo: ResMut<Combo>,
+    mut wave_stats: ResMut<WaveStats>,
+) {
+    let shot_mines = detonated
+        .read()
+        .filter_map(|mine| Some((mine.position, mine.shot?.points, mine.shot?.player)));
+    let hazards = destroyed
+        .read()
+        .map(|hazard| (hazard.position, hazard.points, hazard.player))
+        .chain(shot_mines);
+
+    for (position, points, shooter) in hazards {
+        let points = (points as f32 * combo.multiplier()).round() as usize;
+        let awarded = score.score(points);
+
+        if let Some((_, mut player_score)) = player_scores
+            .iter_mut()
+            .find(|(player, _)| Some(**player) == shooter)
+        {
+            player_score.score(points);
+        }
+
+        combo.kill();
+        wave_stats.hits += 1;
+
+        spawn_popup(&mut commands, position, format!("+{awarded}"));
+    }
+}
+
+fn track_shots(mut fired: EventReader<MissileFired>, mut wave_stats: ResMut<WaveStats>) {
+    wave_stats.shots += fired.read().count() as u32;
+}
+
+fn track_damage(
+    mut damaged: EventReader<SpaceshipDamaged>,
+    mut wave_stats: ResMut<WaveStats>,
+    mut combo: ResMut<Combo>,
+) {
+    for event in damaged.read() {
+        wave_stats.damage_taken += event.damage;
+        combo.chain = 0;
+    }
+}
+
+fn decay_combo(mut combo: ResMut<Combo>, time: Res<Time>) {
+    if combo.chain == 0 {
+        return;
+    }
+
+    if combo.decay.tick(time.delta()).just_finished() {
+        combo.chain -= 1;
+    }
+}
+
+fn award_wave_bonuses(
+    mut commands: Commands,
+    mut completed: EventReader<WaveCompleted>,
+    mut score: ResMut<Score>,
+    mut wave_stats: ResMut<WaveStats>,
+    spaceship: Query<&GlobalTransform, With<Spaceship>>,
+) {
+    for &WaveCompleted { wave } in completed.read() {
+        let position = spaceship
+            .iter()
+            .next()
+            .map_or(Vec3::ZERO, |transform| transform.translation());
+
+        if wave_stats.shots >= ACCURACY_MIN_SHOTS {
+            let accuracy = wave_stats.hits as f32 / wave_stats.shots as f32;
+            let bonus = (ACCURACY_BONUS as f32 * accuracy.min(1.)).round() as usize;
+
+            if bonus > 0 {
+                let awarded = score.score(bonus);
+                info!("Wave {wave} accuracy {:.0}%", accuracy * 100.);
+                spawn_popup(&mut commands, position, format!("Accuracy +{awarded}"));
+            }
+        }
+
+        if wave_stats.damage_taken == 0 {
+            let awarded = score.score(NO_DAMAGE_BONUS);
+            info!("Wave {wave} without taking damage");
+            spawn_popup(&mut commands, position, format!("Untouched +{awarded}"));
+        }
+
+        *wave_stats = WaveStats::default();
+    }
+}
+
+fn detect_close_calls(
+    mut commands: Commands,
+    spaceships: Query<(&GlobalTransform, &Collider), (With<Spaceship>, Without<Invulnerable>)>,
+    asteroids: Query<
+        (Entity, &GlobalTransform, &Collider, Has<NearMiss>),
+        (With<Asteroid>, Without<Dodged>),
+    >,
+    mut score: ResMut<Score>,
+) {
+    for (asteroid, asteroid_transform, asteroid_collider, near_miss) in asteroids.iter() {
+        let close = spaceships
+            .iter()
+            .any(|(spaceship_transform, spaceship_collider)| {
+                let distance = spaceship_transform
+                    .translation()
+                    .distance(asteroid_transform.translation());
+
+                distance < spaceship_collider.radius + asteroid_collider.radius + CLOSE_CALL_MARGIN
+            });
+
+        match (near_miss, close) {
+            (false, true) => {
+                commands.entity(asteroid).insert(NearMiss);
+            }
+            (true, false) => {
+                let awarded = score.score(CLOSE_CALL_BONUS);
+                spawn_popup(
+                    &mut commands,
+                    asteroid_transform.translation(),
+                    format!("Close call +{awarded}"),
+                );
+                commands
+                    .entity(asteroid)
+                    .remove::<NearMiss>()
+                    .insert(Dodged);
+            }
+            _ => {}
+        }
+    }
+}
+
+fn update_popups(
+    mut commands: Commands,
+    mut popups: Query<(Entity, &mut ScorePopup, &mut Transform)>,
+    time: Res<Time>,
+) {
+    for (entity, mut popup, mut transform) in popups.iter_mut() {
+        if popup.timer.tick(time.delta()).finished() {
+            commands.entity(entity).despawn_recursive();
+            continue;
+        }
+
+        transform.translation.z += POPUP_RISE_SPEED * time.delta_seconds();
+    }
+}
+
+fn reset_scoring(mut commands: Commands) {
+    commands.insert_resource(Combo::default());
+    commands.insert_resource(WaveStats::default());
+}
+
+pub struct ScoringPlugin;
+
+impl Plugin for ScoringPlugin {
+    fn build(&self, app: &mut App) {
+        // Read here even where hazards are left out, e.g. on a server.
+        app.add_event::<MineDetonated>()
+            .init_resource::<Combo>()
+            .init_resource::<WaveStats>()
+            .register_type::<PointValue>()
+            .register_type::<Combo>()
+            .register_type::<WaveStats>()
+            .add_systems(
+                OnTransition {
+                    from: AppState::MainMenu,
+                    to: AppState::InGame,
+                },
+                reset_scoring.run_if(not(resource_exists::<ContinueGame>)),
+            )
+            .add_systems(
+                Update,
+                (
+                    track_shots,
+                    track_damage,
+                    score_destroyed_hazards,
+                    decay_combo,
+                    award_wave_bonuses,
+                    detect_close_calls,
+                    update_popups,
+                )
+                    .chain()
+                    .in_set(InGameSet::EntityUpdates)
+                    .run_if(in_state(AppState::InGame)),
+            );
+    }
+}
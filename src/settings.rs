@@ -0,0 +1,622 @@
+use bevy::{
+    asset::ron,
+    prelude::*,
+    window::{PresentMode, PrimaryWindow, WindowMode},
+};
+use bevy_inspector_egui::{bevy_egui::EguiContexts, egui};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    application::AppState,
+    difficulty::Difficulty,
+    persistence,
+    player::{PlayerId, PlayerMode, PlayerSetup, MAX_PLAYERS},
+};
+
+const SETTINGS_PATH: &str = "settings.ron";
+
+pub const RESOLUTIONS: [(u32, u32); 4] = [(1280, 720), (1600, 900), (1920, 1080), (2560, 1440)];
+
+#[derive(Resource, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub video: VideoSettings,
+    pub audio: AudioSettings,
+    pub gameplay: GameplaySettings,
+    pub camera: CameraSettings,
+    pub controls: KeyBindings,
+    /// The second player's half of the keyboard. Anyone after that needs a gamepad.
+    pub second_player_controls: KeyBindings,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            video: VideoSettings::default(),
+            audio: AudioSettings::default(),
+            gameplay: GameplaySettings::default(),
+            camera: CameraSettings::default(),
+            controls: KeyBindings::default(),
+            second_player_controls: KeyBindings::second_player(),
+        }
+    }
+}
+
+impl Settings {
+    pub fn bindings(&self, player: PlayerId) -> Option<&KeyBindings> {
+        match player.0 {
+            0 => Some(&self.controls),
+            1 => Some(&self.second_player_controls),
+            _ => None,
+        }
+    }
+
+    fn bindings_mut(&mut self, player: PlayerId) -> Option<&mut KeyBindings> {
+        match player.0 {
+            0 => Some(&mut self.controls),
+            1 => Some(&mut self.second_player_controls),
+            _ => None,
+        }
+    }
+
+    /// Whichever other binding already uses `key`. Players may share a pause
+    /// key, but no key does two things.
+    fn key_taken_by(
+        &self,
+        key: KeyCode,
+        player: PlayerId,
+        action: Action,
+    ) -> Option<(PlayerId, Action)> {
+        [PlayerId(0), PlayerId(1)]
+            .into_iter()
+            .flat_map(|other| Action::ALL.map(|other_action| (other, other_action)))
+            .filter(|&binding| binding != (player, action))
+            .filter(|&(_, other_action)| action != Action::Pause || other_action != Action::Pause)
+            .find(|&(other, other_action)| {
+                self.bindings(other)
+                    .is_some_and(|bindings| bindings.key(other_action) == key)
+            })
+    }
+
+    fn load() -> Self {
+        let Some(contents) = persistence::read(SETTINGS_PATH) else {
+            return Self::default();
+        };
+
+        ron::from_str(&contents).unwrap_or_else(|error| {
+            warn!("Ignoring {SETTINGS_PATH}: {error}");
+            Self::default()
+        })
+    }
+
+    fn save(&self) {
+        match ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default()) {
+            Ok(serialized) => persistence::write(SETTINGS_PATH, &serialized),
+            Err(error) => error!("Couldn't serialize settings: {error}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum DisplayMode {
+    #[default]
+    Windowed,
+    Borderless,
+    Fullscreen,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct VideoSettings {
+    pub display_mode: DisplayMode,
+    pub vsync: bool,
+    pub resolution: (u32, u32),
+    pub point_light_shadows: bool,
+    pub directional_light_shadows: bool,
+    /// Particles on screen at once; explosions and trails are cut short past it.
+    pub max_particles: usize,
+}
+
+impl Default for VideoSettings {
+    fn default() -> Self {
+        Self {
+            display_mode: DisplayMode::Windowed,
+            vsync: true,
+            resolution: RESOLUTIONS[0],
+            point_light_shadows: true,
+            directional_light_shadows: true,
+            max_particles: 1000,
+        }
+    }
+}
+
+/// Volumes are all in `0.0..=1.0`; sound effects and music are scaled by `master`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AudioSettings {
+    pub master: f32,
+    pub sfx: f32,
+    pub music: f32,
+}
+
+impl AudioSettings {
+    pub fn sfx_volume(&self) -> f32 {
+        self.master * self.sfx
+    }
+
+    pub fn music_volume(&self) -> f32 {
+        self.master * self.music
+    }
+}
+
+impl Default for AudioSettings {
+    fn default() -> Self {
+        Self {
+            master: 1.0,
+            sfx: 1.0,
+            music: 0.6,
+        }
+    }
+}
+
+/// How the thrust keys move the spaceship.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum ControlScheme {
+    /// Each press of thrust or reverse gives the ship a fixed kick.
+    #[default]
+    Impulse,
+    /// Holding thrust or reverse accelerates the ship.
+    Thrust,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum ArenaMode {
+    /// The spaceship can fly off into the void.
+    #[default]
+    Open,
+    /// Leaving one edge of the arena brings the spaceship back on the other.
+    Wraparound,
+}
+
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GameplaySettings {
+    /// The difficulty preselected in the main menu.
+    pub difficulty: Difficulty,
+    pub control_scheme: ControlScheme,
+    pub arena_mode: ArenaMode,
+    /// The players preselected in the main menu.
+    pub players: PlayerSetup,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum CameraMode {
+    /// The whole arena from straight above.
+    Fixed,
+    /// Keeps the spaceships in view, zooming out when things get busy.
+    #[default]
+    Follow,
+    /// Dragged with the right mouse button and zoomed with the wheel.
+    Free,
+}
+
+impl CameraMode {
+    pub const ALL: [CameraMode; 3] = [CameraMode::Fixed, CameraMode::Follow, CameraMode::Free];
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CameraSettings {
+    pub mode: CameraMode,
+    /// Scales screen shake, from `0.0` (none) to `2.0`.
+    pub shake_intensity: f32,
+    /// Scales how long big hits slow the game down for, from `0.0` to `2.0`.
+    pub hit_stop_intensity: f32,
+    /// No screen shake or hit-stop at all, whatever the intensities.
+    pub reduce_motion: bool,
+}
+
+impl Default for CameraSettings {
+    fn default() -> Self {
+        Self {
+            mode: CameraMode::default(),
+            shake_intensity: 1.0,
+            hit_stop_intensity: 1.0,
+            reduce_motion: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Action {
+    Thrust,
+    Reverse,
+    RotateLeft,
+    RotateRight,
+    Fire,
+    Shield,
+    Pause,
+}
+
+impl Action {
+    pub const ALL: [Action; 7] = [
+        Action::Thrust,
+        Action::Reverse,
+        Action::RotateLeft,
+        Action::RotateRight,
+        Action::Fire,
+        Action::Shield,
+        Action::Pause,
+    ];
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct KeyBindings {
+    pub thrust: KeyCode,
+    pub reverse: KeyCode,
+    pub rotate_left: KeyCode,
+    pub rotate_right: KeyCode,
+    pub fire: KeyCode,
+    pub shield: KeyCode,
+    pub pause: KeyCode,
+}
+
+impl KeyBindings {
+    pub fn key(&self, action: Action) -> KeyCode {
+        match action {
+            Action::Thrust => self.thrust,
+            Action::Reverse => self.reverse,
+            Action::RotateLeft => self.rotate_left,
+            Action::RotateRight => self.rotate_right,
+            Action::Fire => self.fire,
+            Action::Shield => self.shield,
+            Action::Pause => self.pause,
+        }
+    }
+
+    fn key_mut(&mut self, action: Action) -> &mut KeyCode {
+        match action {
+            Action::Thrust => &mut self.thrust,
+            Action::Reverse => &mut self.reverse,
+            Action::RotateLeft => &mut self.rotate_left,
+            Action::RotateRight => &mut self.rotate_right,
+            Action::Fire => &mut self.fire,
+            Action::Shield => &mut self.shield,
+            Action::Pause => &mut self.pause,
+        }
+    }
+}
+
+impl KeyBindings {
+    fn second_player() -> Self {
+        Self {
+            thrust: KeyCode::ArrowUp,
+            reverse: KeyCode::ArrowDown,
+            rotate_left: KeyCode::ArrowLeft,
+            rotate_right: KeyCode::ArrowRight,
+            fire: KeyCode::ControlRight,
+            shield: KeyCode::ShiftRight,
+            pause: KeyCode::Escape,
+        }
+    }
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        Self {
+            thrust: KeyCode::KeyW,
+            reverse: KeyCode::KeyS,
+            rotate_left: KeyCode::KeyA,
+            rotate_right: KeyCode::KeyD,
+            fire: KeyCode::Space,
+            shield: KeyCode::KeyF,
+            pause: KeyCode::Escape,
+        }
+    }
+}
+
+/// A short name for a key, e.g. `F` rather than `KeyF`.
+pub fn key_name(key: KeyCode) -> String {
+    let name = format!("{key:?}");
+
+    name.strip_prefix("Key")
+        .or_else(|| name.strip_prefix("Digit"))
+        .unwrap_or(&name)
+        .to_string()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum SettingsTab {
+    #[default]
+    Video,
+    Audio,
+    Gameplay,
+    Camera,
+    Controls,
+}
+
+fn settings_menu(
+    mut contexts: EguiContexts,
+    mut settings: ResMut<Settings>,
+    mut app_state: ResMut<NextState<AppState>>,
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    mut tab: Local<SettingsTab>,
+    mut rebinding: Local<Option<(PlayerId, Action)>>,
+    mut rejected: Local<Option<String>>,
+) {
+    let Some(ctx) = contexts.try_ctx_mut() else {
+        return;
+    };
+
+    let mut edited = settings.clone();
+
+    if let Some((player, action)) = *rebinding {
+        if let Some(&key) = keyboard_input.get_just_pressed().next() {
+            let taken = edited.key_taken_by(key, player, action);
+
+            // Escape backs out, unless it's going back to being a pause key.
+            if key == KeyCode::Escape && (action != Action::Pause || taken.is_some()) {
+                *rebinding = None;
+                *rejected = None;
+            } else if let Some((other, other_action)) = taken {
+                *rejected = Some(format!(
+                    "{} is already {other}'s {other_action:?}",
+                    key_name(key)
+                ));
+            } else {
+                if let Some(bindings) = edited.bindings_mut(player) {
+                    *bindings.key_mut(action) = key;
+                }
+                *rebinding = None;
+                *rejected = None;
+            }
+        }
+    } else if keyboard_input.just_pressed(settings.controls.pause) {
+        app_state.set(AppState::MainMenu);
+    }
+
+    egui::CentralPanel::default().show(ctx, |ui| {
+        ui.heading("Settings");
+
+        ui.horizontal(|ui| {
+            ui.selectable_value(&mut *tab, SettingsTab::Video, "Video");
+            ui.selectable_value(&mut *tab, SettingsTab::Audio, "Audio");
+            ui.selectable_value(&mut *tab, SettingsTab::Gameplay, "Gameplay");
+            ui.selectable_value(&mut *tab, SettingsTab::Camera, "Camera");
+            ui.selectable_value(&mut *tab, SettingsTab::Controls, "Controls");
+        });
+
+        ui.separator();
+
+        match *tab {
+            SettingsTab::Video => {
+                let video = &mut edited.video;
+
+                egui::ComboBox::from_label("Window mode")
+                    .selected_text(format!("{:?}", video.display_mode))
+                    .show_ui(ui, |ui| {
+                        for mode in [
+                            DisplayMode::Windowed,
+                            DisplayMode::Borderless,
+                            DisplayMode::Fullscreen,
+                        ] {
+                            ui.selectable_value(&mut video.display_mode, mode, format!("{mode:?}"));
+                        }
+                    });
+
+                let (width, height) = video.resolution;
+                egui::ComboBox::from_label("Resolution")
+                    .selected_text(format!("{width}x{height}"))
+                    .show_ui(ui, |ui| {
+                        for (width, height) in RESOLUTIONS {
+                            ui.selectable_value(
+                                &mut video.resolution,
+                                (width, height),
+                                format!("{width}x{height}"),
+                            );
+                        }
+                    });
+
+                ui.checkbox(&mut video.vsync, "VSync");
+                ui.checkbox(&mut video.point_light_shadows, "Point light shadows");
+                ui.checkbox(
+                    &mut video.directional_light_shadows,
+                    "Directional light shadows",
+                );
+                ui.add(egui::Slider::new(&mut video.max_particles, 0..=4000).text("Particles"));
+            }
+            SettingsTab::Audio => {
+                let audio = &mut edited.audio;
+
+                ui.add(egui::Slider::new(&mut audio.master, 0.0..=1.0).text("Master"));
+                ui.add(egui::Slider::new(&mut audio.sfx, 0.0..=1.0).text("Sound effects"));
+                ui.add(egui::Slider::new(&mut audio.music, 0.0..=1.0).text("Music"));
+            }
+            SettingsTab::Gameplay => {
+                let gameplay = &mut edited.gameplay;
+
+                ui.horizontal(|ui| {
+                    ui.label("Difficulty");
+                    for difficulty in Difficulty::ALL {
+                        ui.selectable_value(
+                            &mut gameplay.difficulty,
+                            difficulty,
+                            format!("{difficulty:?}"),
+                        );
+                    }
+                });
+
+                ui.horizontal(|ui| {
+                    ui.label("Controls");
+                    for scheme in [ControlScheme::Impulse, ControlScheme::Thrust] {
+                        ui.selectable_value(
+                            &mut gameplay.control_scheme,
+                            scheme,
+                            format!("{scheme:?}"),
+                        );
+                    }
+                });
+
+                ui.horizontal(|ui| {
+                    ui.label("Arena");
+                    for mode in [ArenaMode::Open, ArenaMode::Wraparound] {
+                        ui.selectable_value(&mut gameplay.arena_mode, mode, format!("{mode:?}"));
+                    }
+                });
+
+                ui.horizontal(|ui| {
+                    ui.label("Players");
+                    for mode in PlayerMode::ALL {
+                        ui.selectable_value(&mut gameplay.players.mode, mode, format!("{mode:?}"));
+                    }
+                });
+
+                if gameplay.players.mode != PlayerMode::Single {
+                    ui.add(
+                        egui::Slider::new(&mut gameplay.players.count, 2..=MAX_PLAYERS)
+                            .text("Player count"),
+                    );
+                }
+            }
+            SettingsTab::Camera => {
+                let camera = &mut edited.camera;
+
+                ui.horizontal(|ui| {
+                    ui.label("Camera");
+                    for mode in CameraMode::ALL {
+                        ui.selectable_value(&mut camera.mode, mode, format!("{mode:?}"));
+                    }
+                });
+
+                ui.checkbox(&mut camera.reduce_motion, "Reduce motion");
+                ui.add_enabled_ui(!camera.reduce_motion, |ui| {
+                    ui.add(
+                        egui::Slider::new(&mut camera.shake_intensity, 0.0..=2.0)
+                            .text("Screen shake"),
+                    );
+                    ui.add(
+                        egui::Slider::new(&mut camera.hit_stop_intensity, 0.0..=2.0)
+                            .text("Hit-stop"),
+                    );
+                });
+            }
+            SettingsTab::Controls => {
+                let players = [PlayerId(0), PlayerId(1)];
+
+                egui::Grid::new("Key bindings").show(ui, |ui| {
+                    ui.label("");
+                    for player in players {
+                        ui.label(player.to_string());
+                    }
+                    ui.end_row();
+
+                    for action in Action::ALL {
+                        ui.label(format!("{action:?}"));
+
+                        for player in players {
+                            let Some(bindings) = edited.bindings(player) else {
+                                continue;
+                            };
+
+                            let text = if *rebinding == Some((player, action)) {
+                                "Press a key...".to_string()
+                            } else {
+                                key_name(bindings.key(action))
+                            };
+
+                            if ui.button(text).clicked() {
+                                *rebinding = Some((player, action));
+                                *rejected = None;
+                            }
+                        }
+
+                        ui.end_row();
+                    }
+                });
+
+                if rebinding.is_some() {
+                    ui.label("Press Escape to cancel.");
+                }
+                if let Some(rejected) = rejected.as_ref() {
+                    ui.colored_label(egui::Color32::RED, rejected);
+                }
+
+                ui.label("Gamepads are assigned to players in the order they were connected.");
+
+                if ui.button("Reset to defaults").clicked() {
+                    edited.controls = KeyBindings::default();
+                    edited.second_player_controls = KeyBindings::second_player();
+                    *rebinding = None;
+                    *rejected = None;
+                }
+            }
+        }
+
+        ui.separator();
+
+        if ui.button("Back").clicked() {
+            app_state.set(AppState::MainMenu);
+        }
+    });
+
+    settings.set_if_neq(edited);
+}
+
+fn apply_video_settings(
+    settings: Res<Settings>,
+    mut windows: Query<&mut Window, With<PrimaryWindow>>,
+) {
+    let Ok(mut window) = windows.get_single_mut() else {
+        return;
+    };
+
+    let video = &settings.video;
+
+    window.mode = match video.display_mode {
+        DisplayMode::Windowed => WindowMode::Windowed,
+        DisplayMode::Borderless => WindowMode::BorderlessFullscreen,
+        DisplayMode::Fullscreen => WindowMode::Fullscreen,
+    };
+
+    window.present_mode = if video.vsync {
+        PresentMode::AutoVsync
+    } else {
+        PresentMode::AutoNoVsync
+    };
+
+    let (width, height) = video.resolution;
+    window.resolution.set(width as f32, height as f32);
+}
+
+/// What's in the settings file, so it's only written when something changed.
+#[derive(Resource, Debug)]
+struct SavedSettings(Settings);
+
+fn save_settings(settings: Res<Settings>, mut saved: ResMut<SavedSettings>) {
+    if saved.0 == *settings {
+        return;
+    }
+
+    info!("Saving settings to {SETTINGS_PATH}");
+    settings.save();
+    saved.0 = settings.clone();
+}
+
+pub struct SettingsPlugin;
+
+impl Plugin for SettingsPlugin {
+    fn build(&self, app: &mut App) {
+        let settings = Settings::load();
+
+        app.insert_resource(SavedSettings(settings.clone()))
+            .insert_resource(settings)
+            .add_systems(Update, settings_menu.run_if(in_state(AppState::Settings)))
+            .add_systems(
+                Update,
+                apply_video_settings.run_if(resource_changed::<Settings>),
+            )
+            .add_systems(OnExit(AppState::Settings), save_settings)
+            .add_systems(OnExit(AppState::MainMenu), save_settings);
+    }
+}
@@ -1,19 +1,34 @@
 pub mod health;
+pub mod lives;
 pub mod shield;
 
 use std::time::Duration;
 
 use self::{
     health::Health,
+    lives::{
+        award_extra_lives, check_destroyed, invulnerability_timer, lose_life, respawn_timer,
+        GodMode, Invulnerable, Lives, Respawning, SpaceshipDestroyed,
+    },
     shield::{enable_shields, shield_timer, ShieldDisplay, SpaceshipShield},
 };
 use crate::{
     application::AppState,
     asset_loader::SceneAssets,
-    collision_detection::Collider,
+    asteroid::{SPAWN_RANGE_X, SPAWN_RANGE_Y},
+    camera::CameraTarget,
+    collision_detection::{Collider, ColliderFit, ColliderShape, FastMover},
+    console::{console_closed, parse_argument, ConsoleAppExt, ConsoleCommand},
+    difficulty::{Difficulty, DifficultyPreset},
     end_game::DespawnAtEndgame,
     movement::{Acceleration, MovingObjectBundle, Velocity},
+    player::{PlayerId, PlayerInput, PlayerSetup, StepInput, StepInputs},
+    save_game::ContinueGame,
     schedule::InGameSet,
+    score::Score,
+    settings::{Action, ArenaMode, ControlScheme, Settings},
+    stats::RunStats,
+    ui::{BlipKind, RadarBlip},
 };
 use bevy::prelude::*;
 use bevy_health_bar3d::{
@@ -21,100 +36,202 @@ use bevy_health_bar3d::{
     plugin::HealthBarPlugin,
 };
 
-const STARTING_TRANSLATION: Vec3 = Vec3::new(0., 0., 0.);
-
 const SPACESHIP_SPEED: f32 = 25.0;
+const SPACESHIP_THRUST: f32 = 20.0;
 const SPACESHIP_ROTATION: f32 = 2.5;
-const SPACESHIP_RADIUS: f32 = 5.0;
+/// Reach from the middle of the ship to its nose.
+pub const SPACESHIP_RADIUS: f32 = 5.0;
+/// Until the model loads and [`ColliderFit`] takes over, the ship is a
+/// capsule this wide either side, reaching [`SPACESHIP_RADIUS`] fore and aft.
+const SPACESHIP_HALF_WIDTH: f32 = 2.0;
+/// The wings taper, so a capsule around them is still a little generous.
+pub const SPACESHIP_COLLIDER_FIT: f32 = 0.9;
 
-const MISSILE_RADIUS: f32 = 1.0;
+pub const MISSILE_RADIUS: f32 = 1.0;
+pub const MISSILE_COLLIDER_FIT: f32 = 1.0;
 const MISSILE_SPEED: f32 = 10.0;
 const MISSILE_FORWARD_SPAWN_SCALAR: f32 = 7.5;
 
-const WEAPON_TIMER: u64 = 800;
+pub const WEAPON_TIMER: u64 = 800;
+
+/// The edges of where asteroids spawn, where wraparound brings ships back in.
+const ARENA_HALF_EXTENT: Vec2 = Vec2::new(SPAWN_RANGE_X.end, SPAWN_RANGE_Y.end);
 
 pub const STARTING_HEALTH: u32 = 5;
 
 #[derive(Component, Debug, Reflect, Resource)]
+#[reflect(Component)]
 pub struct Spaceship;
 
 #[derive(Component, Debug, Reflect, Resource)]
+#[reflect(Component)]
 pub struct Missile;
 
 #[derive(Component, Debug, Reflect, Resource)]
+#[reflect(Component)]
 pub struct AlreadyFired {
     timer: Timer,
 }
 
-fn spaceship_movement_controls(
-    mut query: Query<(&mut Transform, &mut Velocity), With<Spaceship>>,
-    keyboard_input: Res<ButtonInput<KeyCode>>,
-    time: Res<Time>,
-) {
-    let Ok((mut transform, mut velocity)) = query.get_single_mut() else {
-        return;
-    };
+#[derive(Event, Debug)]
+pub struct MissileFired {
+    pub spaceship: Entity,
+}
 
+/// One simulation step of a player's steering. Network prediction replays
+/// these, so it must only depend on its arguments.
+pub fn steer(
+    transform: &mut Transform,
+    velocity: &mut Vec3,
+    acceleration: &mut Vec3,
+    input: StepInput,
+    control_scheme: ControlScheme,
+    delta: f32,
+) {
     let mut rotation = 0.0;
     let mut movement = 0.0;
+    let mut thrust = 0.0;
 
-    if keyboard_input.just_pressed(KeyCode::KeyS) {
-        movement = -SPACESHIP_SPEED;
-    } else if keyboard_input.just_pressed(KeyCode::KeyW) {
-        movement = SPACESHIP_SPEED;
+    match control_scheme {
+        ControlScheme::Impulse => {
+            if input.just_pressed(Action::Reverse) {
+                movement = -SPACESHIP_SPEED;
+            } else if input.just_pressed(Action::Thrust) {
+                movement = SPACESHIP_SPEED;
+            }
+        }
+        ControlScheme::Thrust => {
+            if input.pressed(Action::Reverse) {
+                thrust = -SPACESHIP_THRUST;
+            } else if input.pressed(Action::Thrust) {
+                thrust = SPACESHIP_THRUST;
+            }
+        }
     }
 
-    if keyboard_input.pressed(KeyCode::KeyD) {
-        rotation = -SPACESHIP_ROTATION * time.delta_seconds();
-    } else if keyboard_input.pressed(KeyCode::KeyA) {
-        rotation = SPACESHIP_ROTATION * time.delta_seconds();
+    if input.pressed(Action::RotateRight) {
+        rotation = -SPACESHIP_ROTATION * delta;
+    } else if input.pressed(Action::RotateLeft) {
+        rotation = SPACESHIP_ROTATION * delta;
     }
 
     transform.rotate_y(rotation);
 
-    velocity.value += -transform.forward() * movement;
+    *velocity += -transform.forward() * movement;
+    *acceleration = -transform.forward() * thrust;
+}
+
+fn spaceship_movement_controls(
+    mut query: Query<
+        (&PlayerId, &mut Transform, &mut Velocity, &mut Acceleration),
+        (With<Spaceship>, Without<Respawning>),
+    >,
+    inputs: Res<StepInputs>,
+    settings: Res<Settings>,
+    time: Res<Time>,
+) {
+    for (&player, mut transform, mut velocity, mut acceleration) in query.iter_mut() {
+        steer(
+            &mut transform,
+            &mut velocity.value,
+            &mut acceleration.value,
+            inputs.get(player),
+            settings.gameplay.control_scheme,
+            time.delta_seconds(),
+        );
+    }
+}
+
+pub fn wrap_translation(translation: &mut Vec3, arena_mode: ArenaMode) {
+    if arena_mode != ArenaMode::Wraparound {
+        return;
+    }
+
+    if translation.x.abs() > ARENA_HALF_EXTENT.x {
+        translation.x = -translation.x.signum() * ARENA_HALF_EXTENT.x;
+    }
+
+    if translation.z.abs() > ARENA_HALF_EXTENT.y {
+        translation.z = -translation.z.signum() * ARENA_HALF_EXTENT.y;
+    }
+}
+
+fn wrap_around_arena(mut query: Query<&mut Transform, With<Spaceship>>, settings: Res<Settings>) {
+    for mut transform in query.iter_mut() {
+        wrap_translation(&mut transform.translation, settings.gameplay.arena_mode);
+    }
 }
 
 fn spaceship_weapon_controls(
     mut commands: Commands,
-    query: Query<(Entity, &Transform), (With<Spaceship>, Without<AlreadyFired>)>,
-    keyboard_input: Res<ButtonInput<KeyCode>>,
+    query: Query<
+        (Entity, &PlayerId, &Transform),
+        (With<Spaceship>, Without<AlreadyFired>, Without<Respawning>),
+    >,
+    input: PlayerInput,
     assets: Res<SceneAssets>,
+    mut fired: EventWriter<MissileFired>,
+    mut stats: ResMut<RunStats>,
 ) {
-    if !keyboard_input.just_pressed(KeyCode::Space) {
-        return;
-    }
+    for (spaceship_entity, &player, spaceship_transform) in query.iter() {
+        if !input.just_pressed(player, Action::Fire) {
+            continue;
+        }
 
-    let Ok((spaceship_entity, spaceship_transform)) = query.get_single() else {
-        return;
-    };
+        fire_missile(
+            &mut commands,
+            spaceship_entity,
+            player,
+            spaceship_transform,
+            &assets,
+        );
 
-    commands
-        .get_entity(spaceship_entity)
-        .unwrap()
-        .insert(AlreadyFired {
-            timer: Timer::new(Duration::from_millis(WEAPON_TIMER), TimerMode::Once),
+        fired.send(MissileFired {
+            spaceship: spaceship_entity,
         });
+        stats.shots_fired += 1;
+    }
+}
+
+/// Where a missile fired by a spaceship starts, and how fast it flies.
+pub fn missile_launch(spaceship_transform: &Transform) -> (Transform, Velocity) {
+    (
+        Transform::from_translation(
+            spaceship_transform.translation
+                + -spaceship_transform.forward() * MISSILE_FORWARD_SPAWN_SCALAR,
+        ),
+        Velocity::new(-spaceship_transform.forward() * MISSILE_SPEED),
+    )
+}
+
+fn fire_missile(
+    commands: &mut Commands,
+    spaceship_entity: Entity,
+    player: PlayerId,
+    spaceship_transform: &Transform,
+    assets: &SceneAssets,
+) {
+    commands.entity(spaceship_entity).insert(AlreadyFired {
+        timer: Timer::new(Duration::from_millis(WEAPON_TIMER), TimerMode::Once),
+    });
+
+    let (transform, velocity) = missile_launch(spaceship_transform);
 
     commands.spawn((
         Missile,
+        player,
         MovingObjectBundle {
-            velocity: Velocity::new(-spaceship_transform.forward() * MISSILE_SPEED),
+            velocity,
             acceleration: Acceleration::new(Vec3::ZERO),
             model: SceneBundle {
                 scene: assets.missiles.clone(),
-                transform: Transform::from_translation(
-                    spaceship_transform.translation
-                        + -spaceship_transform.forward() * MISSILE_FORWARD_SPAWN_SCALAR,
-                ),
+                transform,
                 ..default()
             },
             collider: Collider::new(MISSILE_RADIUS),
         },
-        AudioBundle {
-            source: assets.explosion.clone(),
-            ..default()
-        },
+        ColliderFit::new(MISSILE_COLLIDER_FIT),
+        FastMover::default(),
         DespawnAtEndgame,
     ));
 }
@@ -124,37 +241,94 @@ fn weapon_timer(
     mut query: Query<(Entity, &mut AlreadyFired)>,
     time: Res<Time>,
 ) {
-    let Ok((entity, mut already_fired)) = query.get_single_mut() else {
-        return;
-    };
+    for (entity, mut already_fired) in query.iter_mut() {
+        if already_fired.timer.tick(time.delta()).finished() {
+            commands.entity(entity).remove::<AlreadyFired>();
+        }
+    }
+}
+
+/// A capsule along the ship, so hits match its long, narrow hull.
+pub fn spaceship_collider() -> Collider {
+    Collider::with_shape(ColliderShape::Capsule {
+        half_length: SPACESHIP_RADIUS - SPACESHIP_HALF_WIDTH,
+        radius: SPACESHIP_HALF_WIDTH,
+    })
+}
 
-    if already_fired.timer.tick(time.delta()).finished() {
-        commands.entity(entity).remove::<AlreadyFired>();
+pub fn health_bar() -> BarSettings<Health> {
+    BarSettings::<Health> {
+        offset: -5.,
+        height: BarHeight::Static(1.),
+        width: 10.,
+        ..default()
     }
 }
 
-fn spawn_spaceship(mut commands: Commands, assets: Res<SceneAssets>) {
-    commands.spawn((
-        Spaceship,
-        MovingObjectBundle {
-            velocity: Velocity::new(Vec3::ZERO),
-            acceleration: Acceleration::new(Vec3::ZERO),
-            model: SceneBundle {
-                scene: assets.spaceship.clone(),
-                transform: Transform::from_translation(STARTING_TRANSLATION),
-                ..default()
+pub fn spawn_spaceships(
+    mut commands: Commands,
+    assets: Res<SceneAssets>,
+    difficulty: Res<Difficulty>,
+    players: Res<PlayerSetup>,
+) {
+    let health = DifficultyPreset::scale_count(difficulty.preset().health, STARTING_HEALTH);
+
+    for player in players.ids() {
+        commands.spawn((
+            Spaceship,
+            player,
+            MovingObjectBundle {
+                velocity: Velocity::new(Vec3::ZERO),
+                acceleration: Acceleration::new(Vec3::ZERO),
+                model: SceneBundle {
+                    scene: assets.spaceship.clone(),
+                    transform: Transform::from_translation(
+                        player.starting_translation(players.players()),
+                    ),
+                    ..default()
+                },
+                collider: spaceship_collider(),
             },
-            collider: Collider::new(SPACESHIP_RADIUS),
-        },
-        BarSettings::<Health> {
-            offset: -5.,
-            height: BarHeight::Static(1.),
-            width: 10.,
-            ..default()
-        },
-        Health::default(),
-        DespawnAtEndgame,
-    ));
+            ColliderFit::new(SPACESHIP_COLLIDER_FIT),
+            health_bar(),
+            Health::new(health),
+            Lives::default(),
+            Score::new(*difficulty),
+            CameraTarget,
+            RadarBlip(BlipKind::Player),
+            DespawnAtEndgame,
+        ));
+    }
+}
+
+fn toggle_god_mode(world: &mut World, _arguments: &[&str]) -> Result<String, String> {
+    let mut query = world.query_filtered::<(Entity, Has<GodMode>), With<Spaceship>>();
+    let spaceships: Vec<(Entity, bool)> = query.iter(world).collect();
+    if spaceships.is_empty() {
+        return Err("There are no spaceships".to_string());
+    }
+
+    let enable = spaceships.iter().any(|&(_, god_mode)| !god_mode);
+    for (spaceship, _) in spaceships {
+        if enable {
+            world.entity_mut(spaceship).insert(GodMode);
+        } else {
+            world.entity_mut(spaceship).remove::<GodMode>();
+        }
+    }
+
+    Ok(format!("God mode {}", if enable { "on" } else { "off" }))
+}
+
+fn set_health(world: &mut World, arguments: &[&str]) -> Result<String, String> {
+    let current: u32 = parse_argument(arguments)?;
+
+    let mut query = world.query_filtered::<&mut Health, With<Spaceship>>();
+    for mut health in query.iter_mut(world) {
+        health.set(current);
+    }
+
+    Ok(format!("Health set to {current}"))
 }
 
 pub struct SpaceshipPlugin;
@@ -179,30 +353,60 @@ impl Plugin for SpaceshipPlugin {
                 from: AppState::MainMenu,
                 to: AppState::InGame,
             },
-            spawn_spaceship,
+            spawn_spaceships.run_if(not(resource_exists::<ContinueGame>)),
         )
         .add_systems(
             Update,
-            (
-                spaceship_weapon_controls,
-                spaceship_movement_controls,
-                enable_shields,
-            )
+            (spaceship_weapon_controls, enable_shields)
                 .chain()
                 .in_set(InGameSet::UserInput)
+                .run_if(in_state(AppState::InGame))
+                .run_if(console_closed),
+        )
+        .add_systems(
+            FixedUpdate,
+            (
+                spaceship_movement_controls.in_set(InGameSet::UserInput),
+                wrap_around_arena.after(InGameSet::EntityUpdates),
+            )
                 .run_if(in_state(AppState::InGame)),
         )
         .add_systems(
             Update,
-            (shield_timer, weapon_timer)
+            (
+                shield_timer,
+                weapon_timer,
+                check_destroyed,
+                lose_life,
+                respawn_timer,
+                invulnerability_timer,
+                award_extra_lives,
+            )
                 .chain()
                 .in_set(InGameSet::EntityUpdates)
                 .run_if(in_state(AppState::InGame)),
         )
+        .add_console_command(ConsoleCommand::new(
+            "god",
+            "god",
+            "Toggle invulnerability for every spaceship",
+            toggle_god_mode,
+        ))
+        .add_console_command(ConsoleCommand::new(
+            "set health",
+            "set health <health>",
+            "Set every spaceship's health",
+            set_health,
+        ))
+        .add_event::<SpaceshipDestroyed>()
+        .add_event::<MissileFired>()
         .register_type::<Missile>()
         .register_type::<Spaceship>()
         .register_type::<ShieldDisplay>()
         .register_type::<AlreadyFired>()
-        .register_type::<SpaceshipShield>();
+        .register_type::<SpaceshipShield>()
+        .register_type::<Lives>()
+        .register_type::<Respawning>()
+        .register_type::<Invulnerable>();
     }
 }
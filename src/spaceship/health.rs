@@ -1,25 +1,46 @@
 use bevy::prelude::*;
 use bevy_health_bar3d::prelude::*;
+use serde::{Deserialize, Serialize};
 use std::ops::{Add, AddAssign, Sub, SubAssign};
 
 use super::STARTING_HEALTH;
 
-#[derive(Component, Debug, Reflect, Resource)]
-pub struct Health(u32);
+#[derive(Component, Debug, Clone, Copy, Reflect, Resource, Serialize, Deserialize)]
+#[reflect(Component)]
+pub struct Health {
+    current: u32,
+    max: u32,
+}
+
+impl Health {
+    pub fn new(max: u32) -> Self {
+        Self { current: max, max }
+    }
+
+    pub fn heal_fully(&mut self) {
+        self.current = self.max;
+    }
+
+    /// Sets the current health, raising the maximum to match if needed.
+    pub fn set(&mut self, current: u32) {
+        self.current = current;
+        self.max = self.max.max(current);
+    }
+}
 
 impl std::fmt::Display for Health {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.write_str(&format!("{}", self.0))
+        f.write_str(&format!("{}", self.current))
     }
 }
 
 impl Percentage for Health {
     fn value(&self) -> f32 {
-        dbg!(if self.0 == 0 {
+        if self.current == 0 {
             0.
         } else {
-            1. / (STARTING_HEALTH as f32 / self.0 as f32)
-        })
+            1. / (self.max as f32 / self.current as f32)
+        }
     }
 }
 
@@ -27,13 +48,16 @@ impl Add<u32> for Health {
     type Output = Self;
 
     fn add(self, rhs: u32) -> Self::Output {
-        Self(self.0 + rhs)
+        Self {
+            current: self.current + rhs,
+            ..self
+        }
     }
 }
 
 impl AddAssign<u32> for Health {
     fn add_assign(&mut self, rhs: u32) {
-        self.0 += rhs;
+        self.current += rhs;
     }
 }
 
@@ -41,30 +65,33 @@ impl Sub<u32> for Health {
     type Output = Self;
 
     fn sub(self, rhs: u32) -> Self::Output {
-        Self(self.0 - rhs)
+        Self {
+            current: self.current.saturating_sub(rhs),
+            ..self
+        }
     }
 }
 
 impl SubAssign<u32> for Health {
     fn sub_assign(&mut self, rhs: u32) {
-        self.0 -= rhs;
+        self.current = self.current.saturating_sub(rhs);
     }
 }
 
 impl PartialEq<u32> for Health {
     fn eq(&self, other: &u32) -> bool {
-        self.0 == *other
+        self.current == *other
     }
 }
 
 impl PartialOrd<u32> for Health {
     fn partial_cmp(&self, other: &u32) -> Option<std::cmp::Ordering> {
-        Some(self.0.cmp(other))
+        Some(self.current.cmp(other))
     }
 }
 
 impl Default for Health {
     fn default() -> Self {
-        Self(STARTING_HEALTH)
+        Self::new(STARTING_HEALTH)
     }
 }
@@ -0,0 +1,226 @@
+use bevy::prelude::*;
+use std::time::Duration;
+
+use crate::{
+    application::AppState,
+    collision_detection::SpaceshipDamaged,
+    movement::{Acceleration, Velocity},
+    player::{PlayerId, PlayerSetup},
+    score::Score,
+};
+
+use super::{health::Health, Spaceship};
+
+const STARTING_LIVES: u32 = 3;
+const EXTRA_LIFE_SCORE: usize = 50;
+
+const RESPAWN_TIME: u64 = 2000;
+const INVULNERABLE_TIME: u64 = 3000;
+const BLINK_TIME: u64 = 150;
+
+/// Sent when a spaceship runs out of health.
+#[derive(Event, Debug)]
+pub struct SpaceshipDestroyed {
+    pub spaceship: Entity,
+}
+
+#[derive(Component, Debug, Reflect, Resource)]
+#[reflect(Component)]
+pub struct Lives {
+    remaining: u32,
+    next_extra_life: usize,
+}
+
+impl Default for Lives {
+    fn default() -> Self {
+        Self {
+            remaining: STARTING_LIVES,
+            next_extra_life: EXTRA_LIFE_SCORE,
+        }
+    }
+}
+
+impl Lives {
+    pub fn remaining(&self) -> u32 {
+        self.remaining
+    }
+
+    /// For spaceships whose lives are counted somewhere else, e.g. by a server.
+    pub fn set_remaining(&mut self, remaining: u32) {
+        self.remaining = remaining;
+    }
+}
+
+impl std::fmt::Display for Lives {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.remaining)
+    }
+}
+
+/// The spaceship has exploded and is waiting to come back.
+#[derive(Component, Debug, Reflect, Resource)]
+#[reflect(Component)]
+pub struct Respawning {
+    timer: Timer,
+}
+
+/// Asteroids pass straight through the spaceship while this is present.
+/// Respawning spaceships are invulnerable too.
+#[derive(Component, Debug, Reflect, Resource)]
+#[reflect(Component)]
+pub struct Invulnerable {
+    timer: Timer,
+    blink: Timer,
+}
+
+impl Invulnerable {
+    fn new(duration: Duration) -> Self {
+        Self {
+            timer: Timer::new(duration, TimerMode::Once),
+            blink: Timer::new(Duration::from_millis(BLINK_TIME), TimerMode::Repeating),
+        }
+    }
+
+    /// A moment to get clear after hitting something that's still there.
+    pub fn after_hit() -> Self {
+        Self::new(Duration::from_millis(INVULNERABLE_TIME))
+    }
+}
+
+/// Nothing hurts the spaceship while this is present, for testing from the
+/// console.
+#[derive(Component, Debug)]
+pub struct GodMode;
+
+pub fn check_destroyed(
+    mut damaged: EventReader<SpaceshipDamaged>,
+    query: Query<&Health>,
+    mut destroyed: EventWriter<SpaceshipDestroyed>,
+) {
+    for &SpaceshipDamaged { spaceship, .. } in damaged.read() {
+        if query.get(spaceship).is_ok_and(|health| *health < 1) {
+            destroyed.send(SpaceshipDestroyed { spaceship });
+        }
+    }
+}
+
+pub fn lose_life(
+    mut commands: Commands,
+    mut destroyed: EventReader<SpaceshipDestroyed>,
+    mut query: Query<(
+        &mut Lives,
+        &mut Visibility,
+        &mut Velocity,
+        &mut Acceleration,
+    )>,
+    mut app_state: ResMut<NextState<AppState>>,
+    players: Res<PlayerSetup>,
+) {
+    let mut eliminated = false;
+
+    for &SpaceshipDestroyed { spaceship } in destroyed.read() {
+        let Ok((mut lives, mut visibility, mut velocity, mut acceleration)) =
+            query.get_mut(spaceship)
+        else {
+            continue;
+        };
+
+        lives.remaining = lives.remaining.saturating_sub(1);
+
+        if lives.remaining == 0 {
+            warn!("Time to die!");
+            commands.entity(spaceship).despawn_recursive();
+            eliminated = true;
+            continue;
+        }
+
+        info!("Lost a life, {} remaining", lives.remaining);
+
+        *visibility = Visibility::Hidden;
+        velocity.value = Vec3::ZERO;
+        acceleration.value = Vec3::ZERO;
+
+        commands.entity(spaceship).insert((
+            Respawning {
+                timer: Timer::new(Duration::from_millis(RESPAWN_TIME), TimerMode::Once),
+            },
+            Invulnerable::new(Duration::from_millis(RESPAWN_TIME)),
+        ));
+    }
+
+    if !eliminated {
+        return;
+    }
+
+    let survivors = query
+        .iter()
+        .filter(|(lives, ..)| lives.remaining > 0)
+        .count();
+
+    if survivors < players.survivors_needed() {
+        app_state.set(AppState::EndGame);
+    }
+}
+
+pub fn respawn_timer(
+    mut commands: Commands,
+    mut query: Query<(
+        Entity,
+        &PlayerId,
+        &mut Respawning,
+        &mut Transform,
+        &mut Health,
+        &mut Visibility,
+    )>,
+    players: Res<PlayerSetup>,
+    time: Res<Time>,
+) {
+    for (spaceship, player, mut respawning, mut transform, mut health, mut visibility) in
+        query.iter_mut()
+    {
+        if !respawning.timer.tick(time.delta()).finished() {
+            *visibility = Visibility::Hidden;
+            continue;
+        }
+
+        *transform = Transform::from_translation(player.starting_translation(players.players()));
+        health.heal_fully();
+        *visibility = Visibility::Inherited;
+
+        commands
+            .entity(spaceship)
+            .remove::<Respawning>()
+            .insert(Invulnerable::new(Duration::from_millis(INVULNERABLE_TIME)));
+    }
+}
+
+pub fn invulnerability_timer(
+    mut commands: Commands,
+    mut query: Query<(Entity, &mut Invulnerable, &mut Visibility), Without<Respawning>>,
+    time: Res<Time>,
+) {
+    for (spaceship, mut invulnerable, mut visibility) in query.iter_mut() {
+        if invulnerable.timer.tick(time.delta()).finished() {
+            *visibility = Visibility::Inherited;
+            commands.entity(spaceship).remove::<Invulnerable>();
+            continue;
+        }
+
+        if invulnerable.blink.tick(time.delta()).just_finished() {
+            *visibility = match *visibility {
+                Visibility::Hidden => Visibility::Inherited,
+                _ => Visibility::Hidden,
+            };
+        }
+    }
+}
+
+pub fn award_extra_lives(mut query: Query<(&Score, &mut Lives), With<Spaceship>>) {
+    for (score, mut lives) in query.iter_mut() {
+        while score.points() >= lives.next_extra_life {
+            lives.remaining += 1;
+            lives.next_extra_life += EXTRA_LIFE_SCORE;
+            info!("Extra life! {} remaining", lives.remaining);
+        }
+    }
+}
@@ -2,13 +2,20 @@ use bevy::prelude::*;
 use bevy_health_bar3d::configuration::{BarHeight, BarSettings, Percentage};
 use std::time::Duration;
 
-use crate::end_game::DespawnAtEndgame;
+use crate::{
+    end_game::DespawnAtEndgame,
+    player::{PlayerId, PlayerInput},
+    settings::Action,
+    stats::RunStats,
+};
 
-use super::Spaceship;
+use super::{lives::Respawning, Spaceship};
 
 const SHIELD_TIME: u64 = 1200;
+pub const SHIELD_RADIUS: f32 = 6.1;
 
 #[derive(Component, Debug, Reflect, Resource)]
+#[reflect(Component)]
 pub struct SpaceshipShield {
     timer: Timer,
 }
@@ -24,23 +31,41 @@ pub struct ShieldDisplay;
 
 pub fn enable_shields(
     mut commands: Commands,
-    query: Query<Entity, (With<Spaceship>, Without<SpaceshipShield>)>,
-    keyboard_input: Res<ButtonInput<KeyCode>>,
+    query: Query<
+        (Entity, &PlayerId),
+        (
+            With<Spaceship>,
+            Without<SpaceshipShield>,
+            Without<Respawning>,
+        ),
+    >,
+    input: PlayerInput,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<StandardMaterial>>,
+    mut stats: ResMut<RunStats>,
 ) {
-    if !keyboard_input.just_pressed(KeyCode::KeyF) {
-        return;
-    }
+    for (spaceship, &player) in query.iter() {
+        if !input.just_pressed(player, Action::Shield) {
+            continue;
+        }
 
-    let Ok(spaceship) = query.get_single() else {
-        return;
-    };
+        commands.entity(spaceship).insert(SpaceshipShield {
+            timer: Timer::new(Duration::from_millis(SHIELD_TIME), TimerMode::Once),
+        });
 
-    let mesh = meshes.add(Sphere {
-        radius: 6.1,
-        ..default()
-    });
+        attach_shield_display(&mut commands, spaceship, &mut meshes, &mut materials);
+        stats.shields_used += 1;
+    }
+}
+
+/// Adds the bubble and timer bar for a spaceship that has a [`SpaceshipShield`].
+pub fn attach_shield_display(
+    commands: &mut Commands,
+    spaceship: Entity,
+    meshes: &mut Assets<Mesh>,
+    materials: &mut Assets<StandardMaterial>,
+) {
+    let mesh = meshes.add(Sphere::new(SHIELD_RADIUS));
 
     let material = materials.add(StandardMaterial {
         base_color: Color::BLUE.with_a(0.5),
@@ -50,9 +75,6 @@ pub fn enable_shields(
 
     commands
         .entity(spaceship)
-        .insert(SpaceshipShield {
-            timer: Timer::new(Duration::from_millis(SHIELD_TIME), TimerMode::Once),
-        })
         .insert(BarSettings::<SpaceshipShield> {
             offset: -10.,
             height: BarHeight::Static(1.),
@@ -72,29 +94,37 @@ pub fn enable_shields(
         });
 }
 
-pub fn shield_timer(
-    mut commands: Commands,
-    mut query: Query<(Entity, &mut SpaceshipShield)>,
-    spaceship_shield_query: Query<Entity, With<ShieldDisplay>>,
-    time: Res<Time>,
+/// Takes the shield, its bubble and its timer bar off a spaceship.
+pub fn remove_shield(
+    commands: &mut Commands,
+    spaceship: Entity,
+    shield_displays: &Query<(Entity, &Parent), With<ShieldDisplay>>,
 ) {
-    let Ok((spaceship, mut shield)) = query.get_single_mut() else {
-        return;
-    };
-
-    if !shield.timer.tick(time.delta()).finished() {
-        return;
-    }
-
-    let Ok(shield_display) = spaceship_shield_query.get_single() else {
-        error!("Couldn't find a shield display");
-        return;
-    };
-
     commands
         .entity(spaceship)
         .remove::<SpaceshipShield>()
         .remove::<BarSettings<SpaceshipShield>>();
 
+    let Some((shield_display, _)) = shield_displays
+        .iter()
+        .find(|(_, parent)| parent.get() == spaceship)
+    else {
+        error!("Couldn't find a shield display");
+        return;
+    };
+
     commands.entity(shield_display).despawn_recursive();
 }
+
+pub fn shield_timer(
+    mut commands: Commands,
+    mut query: Query<(Entity, &mut SpaceshipShield)>,
+    shield_displays: Query<(Entity, &Parent), With<ShieldDisplay>>,
+    time: Res<Time>,
+) {
+    for (spaceship, mut shield) in query.iter_mut() {
+        if shield.timer.tick(time.delta()).finished() {
+            remove_shield(&mut commands, spaceship, &shield_displays);
+        }
+    }
+}
@@ -0,0 +1,85 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    application::AppState, asteroid::AsteroidSize, collision_detection::SpaceshipDamaged,
+    save_game::ContinueGame, schedule::InGameSet,
+};
+
+/// Everything the end game summary reports about the current run.
+#[derive(Resource, Debug, Clone, Default, Reflect, Serialize, Deserialize)]
+#[reflect(Resource)]
+pub struct RunStats {
+    pub time_survived: f32,
+    pub small_destroyed: u32,
+    pub medium_destroyed: u32,
+    pub large_destroyed: u32,
+    pub shots_fired: u32,
+    pub shields_used: u32,
+    pub damage_taken: u32,
+    /// Best score on this difficulty before the run was recorded.
+    pub previous_best: Option<usize>,
+}
+
+impl RunStats {
+    pub fn asteroid_destroyed(&mut self, size: AsteroidSize) {
+        match size {
+            AsteroidSize::Small => self.small_destroyed += 1,
+            AsteroidSize::Medium => self.medium_destroyed += 1,
+            AsteroidSize::Large => self.large_destroyed += 1,
+        }
+    }
+
+    pub fn destroyed(&self, size: AsteroidSize) -> u32 {
+        match size {
+            AsteroidSize::Small => self.small_destroyed,
+            AsteroidSize::Medium => self.medium_destroyed,
+            AsteroidSize::Large => self.large_destroyed,
+        }
+    }
+
+    pub fn total_destroyed(&self) -> u32 {
+        self.small_destroyed + self.medium_destroyed + self.large_destroyed
+    }
+
+    /// Fraction of missiles that destroyed an asteroid, if any were fired.
+    pub fn accuracy(&self) -> Option<f32> {
+        (self.shots_fired > 0).then(|| self.total_destroyed() as f32 / self.shots_fired as f32)
+    }
+}
+
+fn reset_run_stats(mut commands: Commands) {
+    commands.insert_resource(RunStats::default());
+}
+
+fn track_time_survived(mut stats: ResMut<RunStats>, time: Res<Time>) {
+    stats.time_survived += time.delta_seconds();
+}
+
+fn track_damage_taken(mut damaged: EventReader<SpaceshipDamaged>, mut stats: ResMut<RunStats>) {
+    for event in damaged.read() {
+        stats.damage_taken += event.damage;
+    }
+}
+
+pub struct StatsPlugin;
+
+impl Plugin for StatsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<RunStats>()
+            .register_type::<RunStats>()
+            .add_systems(
+                OnTransition {
+                    from: AppState::MainMenu,
+                    to: AppState::InGame,
+                },
+                reset_run_stats.run_if(not(resource_exists::<ContinueGame>)),
+            )
+            .add_systems(
+                Update,
+                (track_time_survived, track_damage_taken)
+                    .in_set(InGameSet::EntityUpdates)
+                    .run_if(in_state(AppState::InGame)),
+            );
+    }
+}
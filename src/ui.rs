@@ -4,7 +4,58 @@ use bevy_inspector_egui::{
     egui::{self, Color32, RichText},
 };
 
-use crate::{application::AppState, score::Score, spaceship::health::Health};
+use crate::{
+    application::AppState,
+    asteroid::Wave,
+    camera::CameraTarget,
+    collision_detection::Collider,
+    movement::Velocity,
+    network::Spectator,
+    player::PlayerId,
+    score::Score,
+    scoring::{Combo, ScorePopup},
+    settings::{key_name, Settings},
+    spaceship::{health::Health, lives::Lives, SPACESHIP_RADIUS},
+};
+
+/// World units from the spaceship to the radar's outer ring.
+const RADAR_RANGE: f32 = 80.;
+const RADAR_SIZE: f32 = 180.;
+const RADAR_RINGS: usize = 3;
+/// How far ahead edge arrows look for hazards on a collision course.
+const INCOMING_WARNING_TIME: f32 = 4.;
+const INCOMING_MARGIN: f32 = 3.;
+const ARROW_SIZE: f32 = 14.;
+const ARROW_EDGE_INSET: f32 = 24.;
+
+/// What a radar blip stands for, which sets its colour.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlipKind {
+    Asteroid,
+    Enemy,
+    Pickup,
+    Player,
+}
+
+impl BlipKind {
+    fn color(&self) -> Color32 {
+        match self {
+            BlipKind::Asteroid => Color32::from_rgb(230, 150, 60),
+            BlipKind::Enemy => Color32::RED,
+            BlipKind::Pickup => Color32::GREEN,
+            BlipKind::Player => Color32::LIGHT_BLUE,
+        }
+    }
+
+    /// Whether it gets an edge arrow when it's heading for the spaceship.
+    pub fn is_hazard(&self) -> bool {
+        matches!(self, BlipKind::Asteroid | BlipKind::Enemy)
+    }
+}
+
+/// Shows up on the radar.
+#[derive(Component, Debug, Clone, Copy)]
+pub struct RadarBlip(pub BlipKind);
 
 pub struct UiPlugin;
 
@@ -12,25 +63,264 @@ impl Plugin for UiPlugin {
     fn build(&self, app: &mut bevy::prelude::App) {
         app.add_systems(
             Update,
-            update_ui.run_if(in_state(AppState::InGame).or_else(in_state(AppState::Paused))),
+            (
+                update_ui,
+                draw_score_popups,
+                draw_radar,
+                draw_incoming_arrows,
+            )
+                .run_if(
+                    in_state(AppState::InGame)
+                        .or_else(in_state(AppState::Paused))
+                        .or_else(in_state(AppState::Online)),
+                )
+                .run_if(not(resource_exists::<Spectator>)),
         );
     }
 }
 
-fn update_ui(mut contexts: EguiContexts, health: Query<&Health>, score: Res<Score>) {
-    let Ok(health) = health.get_single() else {
+fn update_ui(
+    mut contexts: EguiContexts,
+    spaceships: Query<(&PlayerId, &Health, &Lives, &Score)>,
+    score: Res<Score>,
+    combo: Res<Combo>,
+    wave: Res<Wave>,
+    settings: Res<Settings>,
+) {
+    let Some(ctx) = contexts.try_ctx_mut() else {
         return;
     };
 
+    let mut spaceships: Vec<_> = spaceships.iter().collect();
+    if spaceships.is_empty() {
+        return;
+    }
+    spaceships.sort_by_key(|(player, ..)| **player);
+
     let score = score.into_inner();
+    let multiplayer = spaceships.len() > 1;
 
     bevy_inspector_egui::egui::SidePanel::right("Game")
         .default_width(200.0)
-        .show(contexts.ctx_mut(), |ui| {
+        .show(ctx, |ui| {
             ui.allocate_space(egui::Vec2::new(1.0, 300.0));
             ui.label(RichText::new(format!("Score: {score}")).color(Color32::YELLOW));
-            ui.label(RichText::new(format!("Health: {health}")).color(Color32::RED));
-            ui.label(RichText::new("Shield [F]").color(Color32::BLUE));
-            ui.label(RichText::new("Gun [Space]").color(Color32::GREEN));
+            ui.label(
+                RichText::new(format!("Combo: x{:.2}", combo.multiplier())).color(Color32::GOLD),
+            );
+            ui.label(RichText::new(format!("Wave: {}", *wave)).color(Color32::WHITE));
+
+            for (player, health, lives, player_score) in spaceships {
+                if multiplayer {
+                    ui.separator();
+                    ui.heading(player.to_string());
+                    ui.label(
+                        RichText::new(format!("Score: {player_score}")).color(Color32::YELLOW),
+                    );
+                }
+
+                ui.label(RichText::new(format!("Health: {health}")).color(Color32::RED));
+                ui.label(RichText::new(format!("Lives: {lives}")).color(Color32::LIGHT_GREEN));
+
+                let (shield, fire) = match settings.bindings(*player) {
+                    Some(bindings) => (key_name(bindings.shield), key_name(bindings.fire)),
+                    None => ("East".to_string(), "South".to_string()),
+                };
+                ui.label(RichText::new(format!("Shield [{shield}]")).color(Color32::BLUE));
+                ui.label(RichText::new(format!("Gun [{fire}]")).color(Color32::GREEN));
+            }
+        });
+}
+
+fn draw_score_popups(
+    mut contexts: EguiContexts,
+    camera: Query<(&Camera, &GlobalTransform)>,
+    popups: Query<(&ScorePopup, &GlobalTransform)>,
+) {
+    let Ok((camera, camera_transform)) = camera.get_single() else {
+        return;
+    };
+
+    let Some(ctx) = contexts.try_ctx_mut() else {
+        return;
+    };
+
+    let painter = ctx.layer_painter(egui::LayerId::new(
+        egui::Order::Foreground,
+        egui::Id::new("score_popups"),
+    ));
+
+    for (popup, transform) in popups.iter() {
+        let Some(position) = camera.world_to_viewport(camera_transform, transform.translation())
+        else {
+            continue;
+        };
+
+        painter.text(
+            egui::pos2(position.x, position.y),
+            egui::Align2::CENTER_CENTER,
+            &popup.text,
+            egui::FontId::proportional(18.0),
+            Color32::YELLOW.gamma_multiply(popup.timer.fraction_remaining()),
+        );
+    }
+}
+
+/// Plots everything with a [`RadarBlip`] around the first followed spaceship,
+/// turned so its heading points up.
+fn draw_radar(
+    mut contexts: EguiContexts,
+    spaceships: Query<(Entity, &PlayerId, &GlobalTransform), With<CameraTarget>>,
+    blips: Query<(Entity, &RadarBlip, &GlobalTransform)>,
+) {
+    let Some(ctx) = contexts.try_ctx_mut() else {
+        return;
+    };
+
+    let Some((center, _, spaceship)) = spaceships.iter().min_by_key(|(_, player, _)| **player)
+    else {
+        return;
+    };
+
+    // Spaceships fly towards their back, see `spaceship::steer`.
+    let heading = -spaceship.forward();
+    let front = Vec3::new(heading.x, 0., heading.z).normalize_or_zero();
+    let right = front.cross(Vec3::Y);
+    let origin = spaceship.translation();
+
+    egui::Area::new(egui::Id::new("Radar"))
+        .anchor(egui::Align2::LEFT_BOTTOM, egui::vec2(10., -10.))
+        .interactable(false)
+        .show(ctx, |ui| {
+            let (response, painter) =
+                ui.allocate_painter(egui::Vec2::splat(RADAR_SIZE), egui::Sense::hover());
+            let middle = response.rect.center();
+            let radius = RADAR_SIZE / 2.;
+
+            painter.circle_filled(middle, radius, Color32::from_black_alpha(160));
+            for ring in 1..=RADAR_RINGS {
+                painter.circle_stroke(
+                    middle,
+                    radius * ring as f32 / RADAR_RINGS as f32,
+                    egui::Stroke::new(1., Color32::from_gray(80)),
+                );
+            }
+
+            painter.add(egui::Shape::convex_polygon(
+                vec![
+                    middle + egui::vec2(0., -6.),
+                    middle + egui::vec2(4., 4.),
+                    middle + egui::vec2(-4., 4.),
+                ],
+                Color32::WHITE,
+                egui::Stroke::NONE,
+            ));
+
+            for (entity, blip, transform) in blips.iter() {
+                let offset = transform.translation() - origin;
+
+                if entity == center || offset.length() > RADAR_RANGE {
+                    continue;
+                }
+
+                let x = offset.dot(right) / RADAR_RANGE * radius;
+                let y = offset.dot(front) / RADAR_RANGE * radius;
+                painter.circle_filled(middle + egui::vec2(x, -y), 3., blip.0.color());
+            }
         });
 }
+
+/// Whether something will pass within `reach` of the spaceship in the next
+/// few seconds, if neither changes course.
+fn on_collision_course(relative_position: Vec3, relative_velocity: Vec3, reach: f32) -> bool {
+    let speed_squared = relative_velocity.length_squared();
+    if speed_squared == 0. {
+        return false;
+    }
+
+    let time = -relative_position.dot(relative_velocity) / speed_squared;
+    let closest = relative_position + relative_velocity * time;
+
+    (0.0..=INCOMING_WARNING_TIME).contains(&time) && closest.length() < reach
+}
+
+/// Points from the edge of the screen at hazards that can't be seen yet but
+/// are heading for a followed spaceship.
+fn draw_incoming_arrows(
+    mut contexts: EguiContexts,
+    camera: Query<(&Camera, &GlobalTransform)>,
+    spaceships: Query<(&GlobalTransform, Option<&Velocity>), With<CameraTarget>>,
+    blips: Query<(
+        &RadarBlip,
+        &GlobalTransform,
+        Option<&Velocity>,
+        Option<&Collider>,
+    )>,
+) {
+    let Ok((camera, camera_transform)) = camera.get_single() else {
+        return;
+    };
+
+    let Some(screen) = camera.logical_viewport_size() else {
+        return;
+    };
+
+    let Some(ctx) = contexts.try_ctx_mut() else {
+        return;
+    };
+
+    let painter = ctx.layer_painter(egui::LayerId::new(
+        egui::Order::Foreground,
+        egui::Id::new("incoming_arrows"),
+    ));
+    let screen_center = screen / 2.;
+    let edge = screen_center - Vec2::splat(ARROW_EDGE_INSET);
+
+    for (spaceship, spaceship_velocity) in spaceships.iter() {
+        let spaceship_velocity = spaceship_velocity.map_or(Vec3::ZERO, |velocity| velocity.value);
+
+        for (blip, transform, velocity, collider) in blips.iter() {
+            if !blip.0.is_hazard() {
+                continue;
+            }
+
+            let velocity = velocity.map_or(Vec3::ZERO, |velocity| velocity.value);
+            let reach = SPACESHIP_RADIUS
+                + collider.map_or(0., |collider| collider.radius)
+                + INCOMING_MARGIN;
+
+            if !on_collision_course(
+                transform.translation() - spaceship.translation(),
+                velocity - spaceship_velocity,
+                reach,
+            ) {
+                continue;
+            }
+
+            let Some(position) =
+                camera.world_to_viewport(camera_transform, transform.translation())
+            else {
+                continue;
+            };
+
+            let on_screen = position.cmpge(Vec2::ZERO).all() && position.cmple(screen).all();
+            let direction = (position - screen_center).normalize_or_zero();
+            if on_screen || direction == Vec2::ZERO {
+                continue;
+            }
+
+            let distance = (edge.x / direction.x.abs()).min(edge.y / direction.y.abs());
+            let tip = screen_center + direction * distance;
+            let back = tip - direction * ARROW_SIZE;
+            let side = direction.perp() * ARROW_SIZE / 2.;
+
+            painter.add(egui::Shape::convex_polygon(
+                [tip, back + side, back - side]
+                    .map(|point| egui::pos2(point.x, point.y))
+                    .to_vec(),
+                blip.0.color(),
+                egui::Stroke::new(1., Color32::BLACK),
+            ));
+        }
+    }
+}